@@ -0,0 +1,105 @@
+// compiles the GLSL shaders in src/render/shaders to SPIR-V in OUT_DIR/shaders, where they get
+// embedded from (see embedded_spirv! in src/render/shaders/mod.rs)
+// every module is validated for Vulkan 1.0 with SPIRV-Tools, which is built along with this crate
+
+use std::{
+  env, fs,
+  path::{Path, PathBuf},
+};
+
+use rspirv::{dr::Operand, spirv::ExecutionModel};
+
+#[path = "src/render/shaders/compiler.rs"]
+mod compiler;
+
+use compiler::Compiler;
+use spirv_tools::{val::Validator, TargetEnv};
+
+const SHADER_DIR: &str = "src/render/shaders";
+const STAGES: [(&str, ExecutionModel); 3] = [
+  ("vert", ExecutionModel::Vertex),
+  ("frag", ExecutionModel::Fragment),
+  ("comp", ExecutionModel::GLCompute),
+];
+
+fn main() {
+  println!("cargo:rerun-if-changed={}", SHADER_DIR);
+  println!("cargo:rerun-if-env-changed={}", compiler::COMPILER_ENV);
+
+  let compiler = Compiler::find().unwrap_or_else(|| {
+    panic!(
+      "No GLSL compiler found, install glslc or glslangValidator (both come with the Vulkan SDK) \
+       or set {} to the path of one",
+      compiler::COMPILER_ENV
+    )
+  });
+  let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap()).join("shaders");
+
+  for (source, model) in shader_sources(Path::new(SHADER_DIR)) {
+    let name = source.strip_prefix(SHADER_DIR).unwrap();
+    let spv_name = spv_name(name);
+    let output = out_dir.join(&spv_name);
+    fs::create_dir_all(output.parent().unwrap()).unwrap();
+
+    compiler
+      .compile(&source, &output)
+      .unwrap_or_else(|err| panic!("Failed to compile {:?}:\n{}", source, err));
+    validate(&output).unwrap_or_else(|err| panic!("Invalid SPIR-V for {:?}: {}", source, err));
+    check_entry_point(&output, model)
+      .unwrap_or_else(|err| panic!("Unusable SPIR-V for {:?}: {}", source, err));
+  }
+}
+
+// plain/shader.vert becomes plain/shader.vert.spv
+fn spv_name(name: &Path) -> PathBuf {
+  let mut spv_name = name.to_owned().into_os_string();
+  spv_name.push(".spv");
+  PathBuf::from(spv_name)
+}
+
+// shader sources in dir and its subdirectories, with the execution model of their stage
+fn shader_sources(dir: &Path) -> Vec<(PathBuf, ExecutionModel)> {
+  let mut sources = Vec::new();
+  for entry in fs::read_dir(dir).unwrap() {
+    let path = entry.unwrap().path();
+    if path.is_dir() {
+      sources.extend(shader_sources(&path));
+      continue;
+    }
+    let stage = path.extension().and_then(|ext| {
+      STAGES
+        .iter()
+        .find(|(stage_ext, _)| ext == *stage_ext)
+        .map(|(_, model)| *model)
+    });
+    if let Some(model) = stage {
+      sources.push((path, model));
+    }
+  }
+  sources
+}
+
+// valid modules can still lack the entry point the pipelines use
+fn check_entry_point(spv: &Path, model: ExecutionModel) -> Result<(), String> {
+  let code = fs::read(spv).map_err(|err| err.to_string())?;
+  let module = rspirv::dr::load_bytes(&code).map_err(|err| format!("{:?}", err))?;
+  let has_main = module.entry_points.iter().any(|entry_point| {
+    entry_point.operands.first() == Some(&Operand::ExecutionModel(model))
+      && entry_point.operands.get(2) == Some(&Operand::LiteralString("main".to_owned()))
+  });
+  if !has_main {
+    return Err(format!("No {:?} entry point named main", model));
+  }
+  Ok(())
+}
+
+fn validate(spv: &Path) -> Result<(), String> {
+  let code = fs::read(spv).map_err(|err| err.to_string())?;
+  let words: Vec<u32> = code
+    .chunks_exact(4)
+    .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+    .collect();
+  spirv_tools::val::create(Some(TargetEnv::Vulkan_1_0))
+    .validate(words, None)
+    .map_err(|err| err.to_string())
+}
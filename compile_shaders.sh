@@ -1,8 +0,0 @@
-glslc src/render/shaders/plain/shader.frag -o assets/shaders/plain/frag.spv
-glslc src/render/shaders/plain/shader.vert -o assets/shaders/plain/vert.spv
-
-glslc src/render/shaders/tex_plain/shader.frag -o assets/shaders/tex_plain/frag.spv
-glslc src/render/shaders/tex_plain/shader.vert -o assets/shaders/tex_plain/vert.spv
-
-glslc src/render/shaders/compute/instance.comp -o assets/shaders/compute/instance.spv
-
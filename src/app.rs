@@ -1,6 +1,10 @@
-use std::time::Duration;
+use std::{
+  path::PathBuf,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use cgmath::{Euler, Point3, Rad};
+use cgmath::{Euler, Rad};
+use log::info;
 use rand::Rng;
 use winit::{
   dpi::PhysicalPosition,
@@ -10,36 +14,54 @@ use winit::{
 
 use crate::{
   keys::{Keys, Pressed},
-  objects::Niko,
-  render::{Camera, RenderableIn3d, SyncRender},
+  objects::Object,
+  render::{GpuOverride, LoadState, ModelHandle, Models, RenderResult, RenderableIn3d, SyncRender},
+  scene::SceneDescription,
 };
 
-const MAX_DYN_OBJ_AMOUNT: usize = 16;
-
 const CAMERA_NORMAL_SPEED: f32 = 2.0;
 const CAMERA_FAST_SPEED: f32 = 10.0;
 
+const CAPTURES_DIR: &str = "captures";
+
 pub struct App {
   render: SyncRender,
-  objects: Vec<Niko>,
+  objects: Vec<Object>,
+  // objects spawned at runtime cycle through all models, skipping the ones that aren't loaded
+  spawn_models: Vec<ModelHandle>,
+  next_spawn_i: usize,
   keys: Keys,
 }
 
 impl App {
-  pub fn new(event_loop: &EventLoop<()>) -> Self {
-    let objects = vec![Niko::from_full(
-      Point3::new(-0.5, 0.0, 0.5),
-      Euler::new(Rad(0.0), Rad(0.0), Rad(0.0)),
-      0.1,
-    )];
-    let camera = Camera::new(CAMERA_NORMAL_SPEED);
-    let render = SyncRender::initialize(event_loop, camera, MAX_DYN_OBJ_AMOUNT as u64);
-
-    Self {
+  pub fn new(
+    event_loop: &EventLoop<()>,
+    models: Models,
+    scene: &SceneDescription,
+    frames_in_flight: usize,
+    gpu_override: Option<GpuOverride>,
+  ) -> RenderResult<Self> {
+    let objects = scene.dynamic_objects(&models);
+    let spawn_models = models.handles().collect();
+    let camera = scene.camera(CAMERA_NORMAL_SPEED);
+    let static_scene = scene.static_scene(&models);
+    let render = SyncRender::initialize(
+      event_loop,
+      camera,
+      models,
+      &static_scene,
+      objects.len() as u64,
+      frames_in_flight,
+      gpu_override,
+    )?;
+
+    Ok(Self {
       render,
       objects,
+      spawn_models,
+      next_spawn_i: 0,
       keys: Keys::new(),
-    }
+    })
   }
 
   pub fn handle_key_event(&mut self, keycode: Option<VirtualKeyCode>, state: ElementState) -> bool {
@@ -51,24 +73,31 @@ impl App {
         }
         (VirtualKeyCode::Q, ElementState::Released) => {
           // all just a placeholder for now
-          // TODO: implement dynamic allocation buffers
-          if self.objects.len() < MAX_DYN_OBJ_AMOUNT {
-            let mut rng = rand::thread_rng();
-            let size = rng.gen::<f32>() * 0.2 + 0.05;
-            let pos =
-              self.render.camera.position() + (self.render.camera.front() * (2.0 + (size * 5.0)));
-            self.objects.push(Niko::from_full(
-              pos,
-              Euler::new(Rad(0.0), Rad(0.0), Rad(0.0)),
-              size,
-            ));
-          } else {
-            println!("Max dynamic object amount reached");
-          }
+          let mut rng = rand::thread_rng();
+          let size = rng.gen::<f32>() * 0.2 + 0.05;
+          let pos =
+            self.render.camera.position() + (self.render.camera.front() * (2.0 + (size * 5.0)));
+          let model = self.next_spawn_model();
+          self.objects.push(Object::from_full(
+            model,
+            pos,
+            Euler::new(Rad(0.0), Rad(0.0), Rad(0.0)),
+            size,
+          ));
+        }
+        (VirtualKeyCode::E, ElementState::Released) => {
+          // removes the last spawned object
+          self.objects.pop();
+        }
+        (VirtualKeyCode::F12, ElementState::Pressed) => {
+          self.capture_next_frame();
         }
         (VirtualKeyCode::C, ElementState::Pressed) => {
           self.render.toggle_cursor_grab();
         }
+        (VirtualKeyCode::M, ElementState::Pressed) => {
+          info!("{}", self.render.memory_stats());
+        }
         (VirtualKeyCode::LControl, ElementState::Pressed) => {
           let speed = self.render.camera.speed_mut();
           *speed = CAMERA_FAST_SPEED;
@@ -83,6 +112,34 @@ impl App {
     false
   }
 
+  // built in models are always loaded, so one is always found
+  fn next_spawn_model(&mut self) -> ModelHandle {
+    loop {
+      let model = self.spawn_models[self.next_spawn_i];
+      self.next_spawn_i = (self.next_spawn_i + 1) % self.spawn_models.len();
+      if self.render.model_state(model) == LoadState::Ready {
+        return model;
+      }
+    }
+  }
+
+  fn capture_next_frame(&mut self) {
+    if let Err(err) = std::fs::create_dir_all(CAPTURES_DIR) {
+      info!("Failed to create captures directory: {}", err);
+      return;
+    }
+    let timestamp = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_millis();
+    let path = PathBuf::from(CAPTURES_DIR).join(format!("frame_{}.png", timestamp));
+    self.render.capture_next_frame(path);
+  }
+
+  pub fn watch_shaders(&mut self) {
+    self.render.watch_shaders()
+  }
+
   pub fn handle_cursor_moved(&mut self, position: PhysicalPosition<f64>) {
     self.render.handle_cursor_moved(position)
   }
@@ -107,7 +164,7 @@ impl App {
     self.render.request_redraw()
   }
 
-  pub fn render_next_frame(&mut self, duration_since_last_frame: Duration) {
+  pub fn render_next_frame(&mut self, duration_since_last_frame: Duration) -> RenderResult<()> {
     if self.keys.a ^ self.keys.d {
       if self.keys.a == Pressed {
         self.render.camera.move_left(&duration_since_last_frame)
@@ -143,8 +200,9 @@ impl App {
       });
     }
 
+    let objects: Vec<&dyn RenderableIn3d> = self.objects.iter().map(|obj| obj as _).collect();
     self
       .render
-      .render_next_frame(&duration_since_last_frame, &self.objects);
+      .render_next_frame(&duration_since_last_frame, &objects)
   }
 }
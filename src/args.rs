@@ -0,0 +1,167 @@
+use crate::{
+  render::{parse_frames_in_flight, GpuOverride, DEFAULT_FRAMES_IN_FLIGHT},
+  scene::DEFAULT_SCENE_PATH,
+};
+
+pub const USAGE: &str = "\
+usage: ash_boilerplate [--scene <path>] [--gpu <index | name>]
+  [--frames-in-flight <1-4>] [--hot-reload]
+  [--headless [frame count] [output.png | output.ppm]]
+
+--hot-reload recompiles shaders from src/render/shaders when they change
+--gpu selects a device by its enumeration index or part of its name, instead of the highest
+  scoring one, and can also be given through the ASH_GPU environment variable
+--headless renders frames without opening a window, one at a time, so it can't be combined with
+  --frames-in-flight or --hot-reload";
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Headless {
+  pub frame_count: usize,
+  pub output_path: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Args {
+  pub scene_path: String,
+  pub frames_in_flight: usize,
+  pub gpu_override: Option<GpuOverride>,
+  pub hot_reload: bool,
+  pub headless: Option<Headless>,
+}
+
+impl Args {
+  // options can be given in any order, errors are meant to be shown along with USAGE
+  pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+    let mut args = args.into_iter().peekable();
+    let mut scene_path = None;
+    let mut frames_in_flight = None;
+    let mut gpu_override = None;
+    let mut hot_reload = false;
+    let mut headless = None;
+
+    while let Some(arg) = args.next() {
+      let mut value = |description: &str| {
+        args
+          .next()
+          .ok_or_else(|| format!("{} should be followed by {}", arg, description))
+      };
+      match arg.as_str() {
+        "--scene" => scene_path = Some(value("a path")?),
+        "--frames-in-flight" => {
+          frames_in_flight = Some(parse_frames_in_flight(&value("a count")?)?);
+        }
+        "--gpu" => gpu_override = Some(GpuOverride::parse(&value("an index or a name")?)),
+        "--hot-reload" => hot_reload = true,
+        "--headless" => {
+          // optional positional values, which can't be mistaken for the next option
+          let mut positional = || args.next_if(|arg| !arg.starts_with("--"));
+          let frame_count = match positional() {
+            // at least one frame has to be rendered for there to be something to save
+            Some(count) => count
+              .parse()
+              .ok()
+              .filter(|count| *count > 0)
+              .ok_or_else(|| {
+                format!(
+                  "Headless frame count should be a positive integer, got {:?}",
+                  count
+                )
+              })?,
+            None => 1,
+          };
+          headless = Some(Headless {
+            frame_count,
+            output_path: positional(),
+          });
+        }
+        _ => return Err(format!("Unknown argument {:?}", arg)),
+      }
+    }
+
+    if headless.is_some() {
+      if frames_in_flight.is_some() {
+        return Err("--frames-in-flight can't be combined with --headless".to_owned());
+      }
+      if hot_reload {
+        return Err("--hot-reload can't be combined with --headless".to_owned());
+      }
+    }
+
+    Ok(Self {
+      scene_path: scene_path.unwrap_or_else(|| DEFAULT_SCENE_PATH.to_owned()),
+      frames_in_flight: frames_in_flight.unwrap_or(DEFAULT_FRAMES_IN_FLIGHT),
+      gpu_override: gpu_override.or_else(GpuOverride::from_env),
+      hot_reload,
+      headless,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(args: &[&str]) -> Result<Args, String> {
+    Args::parse(args.iter().map(|arg| arg.to_string()))
+  }
+
+  #[test]
+  fn options_in_any_order() {
+    let args = parse(&[
+      "--hot-reload",
+      "--gpu",
+      "1",
+      "--frames-in-flight",
+      "3",
+      "--scene",
+      "scene.ron",
+    ])
+    .unwrap();
+    assert_eq!(args.scene_path, "scene.ron");
+    assert_eq!(args.frames_in_flight, 3);
+    assert_eq!(args.gpu_override, Some(GpuOverride::Index(1)));
+    assert!(args.hot_reload);
+    assert_eq!(args.headless, None);
+  }
+
+  #[test]
+  fn headless_values_are_optional() {
+    let args = parse(&["--headless", "--scene", "scene.ron"]).unwrap();
+    assert_eq!(
+      args.headless,
+      Some(Headless {
+        frame_count: 1,
+        output_path: None
+      })
+    );
+
+    let args = parse(&["--headless", "10", "out.png", "--gpu", "lavapipe"]).unwrap();
+    assert_eq!(
+      args.headless,
+      Some(Headless {
+        frame_count: 10,
+        output_path: Some("out.png".to_owned())
+      })
+    );
+    assert_eq!(
+      args.gpu_override,
+      Some(GpuOverride::Name("lavapipe".to_owned()))
+    );
+  }
+
+  #[test]
+  fn invalid_arguments_are_errors() {
+    assert!(parse(&["--unknown"]).is_err());
+    assert!(parse(&["--scene"]).is_err());
+    assert!(parse(&["--frames-in-flight", "5"]).is_err());
+    assert!(parse(&["--headless", "many"]).is_err());
+    assert!(parse(&["--headless", "0", "out.png"]).is_err());
+    assert!(parse(&["--headless", "2", "out.png", "extra"]).is_err());
+  }
+
+  #[test]
+  fn headless_excludes_frames_in_flight() {
+    assert!(parse(&["--frames-in-flight", "2", "--headless"]).is_err());
+    assert!(parse(&["--headless", "--hot-reload"]).is_err());
+  }
+}
@@ -3,18 +3,13 @@ use std::ops::BitXor;
 use winit::event::{ElementState, VirtualKeyCode};
 
 /// State of each key
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Clone, Copy, Default)]
 pub enum KeyState {
   Pressed,
+  #[default]
   Released,
 }
-pub use KeyState::{Pressed, Released};
-
-impl Default for KeyState {
-  fn default() -> Self {
-    KeyState::Released
-  }
-}
+pub use KeyState::Pressed;
 
 impl BitXor for KeyState {
   type Output = bool;
@@ -33,11 +28,11 @@ impl From<ElementState> for KeyState {
   }
 }
 
-impl Into<bool> for KeyState {
-  fn into(self) -> bool {
-    match self {
-      Self::Pressed => true,
-      Self::Released => false,
+impl From<KeyState> for bool {
+  fn from(val: KeyState) -> Self {
+    match val {
+      KeyState::Pressed => true,
+      KeyState::Released => false,
     }
   }
 }
@@ -1,23 +1,29 @@
-#![feature(iter_next_chunk)]
-#![feature(pointer_byte_offsets)]
-
 mod app;
+mod args;
 mod keys;
 mod objects;
 mod render;
+mod scene;
 mod static_scene;
 mod structures;
 
-use std::time::{Duration, Instant};
+use std::{
+  error::Error,
+  time::{Duration, Instant},
+};
 
 use app::App;
-use log::{debug, info};
+use args::{Args, USAGE};
+use ash::vk;
+use log::{debug, error, info};
+use render::{GpuOverride, HeadlessRender, Models, RenderableIn3d, MODELS_DIR};
+use scene::SceneDescription;
 use winit::{
   event::{Event, KeyboardInput, MouseScrollDelta, WindowEvent},
   event_loop::{ControlFlow, EventLoop},
 };
 
-pub const WINDOW_TITLE: &'static str = "Ash boilerplate";
+pub const WINDOW_TITLE: &str = "Ash boilerplate";
 pub const INITIAL_WINDOW_WIDTH: u32 = 800;
 pub const INITIAL_WINDOW_HEIGHT: u32 = 600;
 
@@ -43,18 +49,20 @@ pub fn main_loop(event_loop: EventLoop<()>, mut app: App) {
     }
     Event::WindowEvent { event, .. } => match event {
       WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-      WindowEvent::KeyboardInput { input, .. } => match input {
-        KeyboardInput {
+      #[allow(clippy::collapsible_match)]
+      WindowEvent::KeyboardInput {
+        input: KeyboardInput {
           virtual_keycode,
           state,
           ..
-        } => {
-          // returns true if program needs to terminate
-          if app.handle_key_event(virtual_keycode, state) {
-            *control_flow = ControlFlow::Exit
-          }
+        },
+        ..
+      } => {
+        // returns true if program needs to terminate
+        if app.handle_key_event(virtual_keycode, state) {
+          *control_flow = ControlFlow::Exit
         }
-      },
+      }
       WindowEvent::Resized(dimensions) => {
         info!("Window resized");
         if dimensions.width == 0 && dimensions.height == 0 {
@@ -68,10 +76,11 @@ pub fn main_loop(event_loop: EventLoop<()>, mut app: App) {
       WindowEvent::CursorMoved { position, .. } => {
         app.handle_cursor_moved(position);
       }
-      WindowEvent::MouseWheel { delta, .. } => {
-        if let MouseScrollDelta::LineDelta(_, y) = delta {
-          app.handle_mouse_wheel(y);
-        }
+      WindowEvent::MouseWheel {
+        delta: MouseScrollDelta::LineDelta(_, y),
+        ..
+      } => {
+        app.handle_mouse_wheel(y);
       }
       WindowEvent::CursorLeft { .. } => {
         app.handle_cursor_left_window();
@@ -84,20 +93,120 @@ pub fn main_loop(event_loop: EventLoop<()>, mut app: App) {
     Event::MainEventsCleared => {
       app.request_redraw();
     }
-    Event::RedrawRequested(_window_id) => {
-      if !application_paused {
-        let now = Instant::now();
-        app.render_next_frame(now - last_frame_instant);
-        last_frame_instant = now;
+    Event::RedrawRequested(_window_id) if !application_paused => {
+      let now = Instant::now();
+      if let Err(err) = app.render_next_frame(now - last_frame_instant) {
+        error!("Rendering failed: {}", err);
+        *control_flow = ControlFlow::ExitWithCode(1);
       }
+      last_frame_instant = now;
     }
     _ => (),
   })
 }
 
+// renders a number of frames without opening a window and exits
+// the last frame is saved to output_path if one is given
+fn run_headless(
+  models: Models,
+  scene: &SceneDescription,
+  frame_count: usize,
+  output_path: Option<String>,
+  gpu_override: Option<GpuOverride>,
+) -> Result<(), Box<dyn Error>> {
+  let extent = vk::Extent2D {
+    width: INITIAL_WINDOW_WIDTH,
+    height: INITIAL_WINDOW_HEIGHT,
+  };
+  let objects = scene.dynamic_objects(&models);
+  let static_scene = scene.static_scene(&models);
+  let mut render = HeadlessRender::initialize(
+    extent,
+    scene.camera(0.0),
+    models,
+    &static_scene,
+    objects.len() as u64,
+    gpu_override,
+  )?;
+
+  let start = Instant::now();
+  let dyn_objects: Vec<&dyn RenderableIn3d> = objects.iter().map(|obj| obj as _).collect();
+  for _ in 0..frame_count {
+    render.render_frame(&dyn_objects)?;
+  }
+  info!(
+    "Rendered {} headless frames in {:?}",
+    frame_count,
+    start.elapsed()
+  );
+
+  if let Some(path) = output_path {
+    render
+      .capture()?
+      .save(&path)
+      .map_err(|err| format!("Failed to save frame to {:?}: {}", path, err))?;
+    info!("Saved last frame to {:?}", path);
+  }
+  Ok(())
+}
+
 fn main() {
   env_logger::init();
+
+  let args = match Args::parse(std::env::args().skip(1)) {
+    Ok(args) => args,
+    Err(err) => {
+      eprintln!("{}\n\n{}", err, USAGE);
+      std::process::exit(2);
+    }
+  };
+
+  // the window shows up right away while model files load in the background, except when rendering
+  // headlessly, where every frame should show the whole scene
+  let models = if args.headless.is_some() {
+    Models::load(MODELS_DIR)
+  } else {
+    Models::scan(MODELS_DIR)
+  };
+  let scene = match SceneDescription::load(&args.scene_path, &models) {
+    Ok(scene) => scene,
+    Err(err) => {
+      error!("{}", err);
+      std::process::exit(1);
+    }
+  };
+  info!("Loaded scene {:?}", args.scene_path);
+
+  if let Some(headless) = args.headless {
+    if let Err(err) = run_headless(
+      models,
+      &scene,
+      headless.frame_count,
+      headless.output_path,
+      args.gpu_override,
+    ) {
+      error!("Headless rendering failed: {}", err);
+      std::process::exit(1);
+    }
+    return;
+  }
+
   let event_loop = EventLoop::new();
-  let app = App::new(&event_loop);
+  let mut app = match App::new(
+    &event_loop,
+    models,
+    &scene,
+    args.frames_in_flight,
+    args.gpu_override,
+  ) {
+    Ok(app) => app,
+    Err(err) => {
+      error!("Failed to initialize the renderer: {}", err);
+      std::process::exit(1);
+    }
+  };
+  if args.hot_reload {
+    app.watch_shaders();
+  }
   main_loop(event_loop, app);
 }
@@ -0,0 +1,127 @@
+use std::path::Path;
+
+use ash::vk;
+use image::{ImageResult, RgbaImage};
+
+use super::error::{RenderError, RenderResult};
+
+// memory order of the channels of a 4 byte pixel
+#[derive(Clone, Copy)]
+enum PixelLayout {
+  Rgba8,
+  Bgra8,
+  // packed little endian 32 bit values, with red or blue in the least significant bits
+  A2Bgr10,
+  A2Rgb10,
+}
+
+fn pixel_layout(format: vk::Format) -> Option<PixelLayout> {
+  match format {
+    vk::Format::R8G8B8A8_SRGB
+    | vk::Format::R8G8B8A8_UNORM
+    | vk::Format::A8B8G8R8_SRGB_PACK32
+    | vk::Format::A8B8G8R8_UNORM_PACK32 => Some(PixelLayout::Rgba8),
+    vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => Some(PixelLayout::Bgra8),
+    vk::Format::A2B10G10R10_UNORM_PACK32 => Some(PixelLayout::A2Bgr10),
+    vk::Format::A2R10G10B10_UNORM_PACK32 => Some(PixelLayout::A2Rgb10),
+    _ => None,
+  }
+}
+
+// rendered frame copied back to the cpu, stored as 8 bit RGBA
+pub struct CapturedFrame {
+  pub image: RgbaImage,
+}
+
+impl CapturedFrame {
+  // whether images of this format can be converted, all of them have 4 bytes per pixel
+  pub fn supports(format: vk::Format) -> bool {
+    pixel_layout(format).is_some()
+  }
+
+  // converts raw tightly packed image data of the given format
+  pub fn from_raw(
+    format: vk::Format,
+    extent: vk::Extent2D,
+    mut data: Vec<u8>,
+  ) -> RenderResult<Self> {
+    match pixel_layout(format) {
+      Some(PixelLayout::Rgba8) => {}
+      Some(PixelLayout::Bgra8) => {
+        for pixel in data.chunks_exact_mut(4) {
+          pixel.swap(0, 2);
+        }
+      }
+      Some(layout @ (PixelLayout::A2Bgr10 | PixelLayout::A2Rgb10)) => {
+        for pixel in data.chunks_exact_mut(4) {
+          let packed = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+          // 10 bit channels keep their 8 most significant bits, 2 bit alpha is scaled up
+          let low = (packed >> 2) as u8;
+          let mid = (packed >> 12) as u8;
+          let high = (packed >> 22) as u8;
+          let alpha = (packed >> 30) as u8 * 85;
+          let (r, b) = match layout {
+            PixelLayout::A2Bgr10 => (low, high),
+            _ => (high, low),
+          };
+          pixel.copy_from_slice(&[r, mid, b, alpha]);
+        }
+      }
+      None => return Err(RenderError::UnsupportedCaptureFormat(format)),
+    }
+
+    let image = RgbaImage::from_raw(extent.width, extent.height, data)
+      .expect("Captured image data is smaller than its extent");
+    Ok(Self { image })
+  }
+
+  // the format is deduced from the file extension, ".ppm" files are saved without alpha
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> ImageResult<()> {
+    let path = path.as_ref();
+    let is_ppm = path
+      .extension()
+      .is_some_and(|ext| ext.eq_ignore_ascii_case("ppm"));
+    if is_ppm {
+      image::DynamicImage::ImageRgba8(self.image.clone())
+        .to_rgb8()
+        .save(path)
+    } else {
+      self.image.save(path)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const EXTENT: vk::Extent2D = vk::Extent2D {
+    width: 1,
+    height: 1,
+  };
+
+  #[test]
+  fn converts_to_rgba() {
+    let bgra = CapturedFrame::from_raw(vk::Format::B8G8R8A8_SRGB, EXTENT, vec![1, 2, 3, 4]);
+    assert_eq!(bgra.unwrap().image.as_raw(), &[3, 2, 1, 4]);
+
+    // red 1023, green 512, blue 0, alpha 3
+    let packed: u32 = 1023 | (512 << 10) | (3 << 30);
+    let a2bgr10 = CapturedFrame::from_raw(
+      vk::Format::A2B10G10R10_UNORM_PACK32,
+      EXTENT,
+      packed.to_le_bytes().to_vec(),
+    );
+    assert_eq!(a2bgr10.unwrap().image.as_raw(), &[255, 128, 0, 255]);
+  }
+
+  #[test]
+  fn rejects_unsupported_formats() {
+    let format = vk::Format::R16G16B16A16_SFLOAT;
+    assert!(!CapturedFrame::supports(format));
+    assert!(matches!(
+      CapturedFrame::from_raw(format, EXTENT, vec![0; 8]),
+      Err(RenderError::UnsupportedCaptureFormat(_))
+    ));
+  }
+}
@@ -0,0 +1,178 @@
+use std::{fmt, path::PathBuf};
+
+use ash::{prelude::VkResult, vk};
+
+// errors the renderer can't handle by itself, like device loss or running out of memory
+#[derive(Debug)]
+pub enum RenderError {
+  // a Vulkan call failed while doing what the context describes
+  Vulkan {
+    context: &'static str,
+    result: vk::Result,
+  },
+  // the Vulkan library couldn't be loaded
+  #[cfg(feature = "load_vulkan")]
+  Loading(ash::LoadingError),
+  MissingLayers(Vec<String>),
+  MissingExtension(String),
+  NoSuitableDevice,
+  // no suitable device matches the selected GPU override
+  NoMatchingDevice(String),
+  // no format the device supports for the given usage
+  NoSupportedFormat(&'static str),
+  NoSuitableMemoryType {
+    type_filter: u32,
+    required: vk::MemoryPropertyFlags,
+  },
+  Surface(String),
+  Texture {
+    path: PathBuf,
+    error: image::ImageError,
+  },
+  // a shader doesn't match the layouts of its pipeline
+  Shader(String),
+  // frames rendered in this format can't be converted to RGBA
+  UnsupportedCaptureFormat(vk::Format),
+  // capturing before any frame was rendered
+  NoFrameToCapture,
+  // the render target images can't be copied from, which the surface may not support
+  CaptureNotSupported,
+}
+
+pub type RenderResult<T> = Result<T, RenderError>;
+
+impl RenderError {
+  pub fn vk_result(&self) -> Option<vk::Result> {
+    match self {
+      RenderError::Vulkan { result, .. } => Some(*result),
+      _ => None,
+    }
+  }
+
+  pub fn is_device_lost(&self) -> bool {
+    self.vk_result() == Some(vk::Result::ERROR_DEVICE_LOST)
+  }
+
+  pub fn is_out_of_memory(&self) -> bool {
+    matches!(
+      self.vk_result(),
+      Some(vk::Result::ERROR_OUT_OF_HOST_MEMORY | vk::Result::ERROR_OUT_OF_DEVICE_MEMORY)
+    )
+  }
+}
+
+impl fmt::Display for RenderError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      RenderError::Vulkan { context, result } => write!(f, "{}: {:?}", context, result),
+      #[cfg(feature = "load_vulkan")]
+      RenderError::Loading(err) => write!(f, "Failed to load Vulkan: {}", err),
+      RenderError::MissingLayers(layers) => {
+        write!(f, "Missing instance layers: {}", layers.join(", "))
+      }
+      RenderError::MissingExtension(extension) => {
+        write!(f, "Required extension is not available: {}", extension)
+      }
+      RenderError::NoSuitableDevice => write!(f, "No suitable physical device found"),
+      RenderError::NoMatchingDevice(gpu) => {
+        write!(f, "No suitable physical device matches the {}", gpu)
+      }
+      RenderError::NoSupportedFormat(usage) => write!(f, "No supported {} format", usage),
+      RenderError::NoSuitableMemoryType {
+        type_filter,
+        required,
+      } => write!(
+        f,
+        "No memory type in {:#b} has properties {:?}",
+        type_filter, required
+      ),
+      RenderError::Surface(message) => write!(f, "Failed to create surface: {}", message),
+      RenderError::Texture { path, error } => {
+        write!(f, "Failed to load texture {:?}: {}", path, error)
+      }
+      RenderError::Shader(message) => f.write_str(message),
+      RenderError::UnsupportedCaptureFormat(format) => {
+        write!(
+          f,
+          "Capturing images with format {:?} is not supported",
+          format
+        )
+      }
+      RenderError::NoFrameToCapture => write!(f, "No frame has been rendered yet to capture"),
+      RenderError::CaptureNotSupported => write!(
+        f,
+        "Capturing is not supported, the surface doesn't allow copying from its images"
+      ),
+    }
+  }
+}
+
+impl std::error::Error for RenderError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      RenderError::Vulkan { result, .. } => Some(result),
+      #[cfg(feature = "load_vulkan")]
+      RenderError::Loading(err) => Some(err),
+      RenderError::Texture { error, .. } => Some(error),
+      _ => None,
+    }
+  }
+}
+
+#[cfg(feature = "load_vulkan")]
+impl From<ash::LoadingError> for RenderError {
+  fn from(err: ash::LoadingError) -> Self {
+    RenderError::Loading(err)
+  }
+}
+
+// replaces .expect("Failed to ...") on Vulkan calls
+pub trait VkResultExt<T> {
+  fn context(self, context: &'static str) -> RenderResult<T>;
+}
+
+impl<T> VkResultExt<T> for VkResult<T> {
+  fn context(self, context: &'static str) -> RenderResult<T> {
+    self.map_err(|result| RenderError::Vulkan { context, result })
+  }
+}
+
+// pipeline creation also returns the pipelines that were created before the failure
+pub trait PipelineResultExt {
+  // destroys the pipelines that were created if any failed, so that .context() can be used after
+  unsafe fn destroy_created_on_err(self, device: &ash::Device) -> VkResult<Vec<vk::Pipeline>>;
+}
+
+impl PipelineResultExt for Result<Vec<vk::Pipeline>, (Vec<vk::Pipeline>, vk::Result)> {
+  unsafe fn destroy_created_on_err(self, device: &ash::Device) -> VkResult<Vec<vk::Pipeline>> {
+    self.map_err(|(pipelines, result)| {
+      for pipeline in pipelines {
+        if pipeline != vk::Pipeline::null() {
+          device.destroy_pipeline(pipeline, None);
+        }
+      }
+      result
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn vulkan_errors_keep_their_result() {
+    let err = VkResult::<()>::Err(vk::Result::ERROR_DEVICE_LOST)
+      .context("Failed to submit")
+      .unwrap_err();
+    assert!(err.is_device_lost());
+    assert!(!err.is_out_of_memory());
+    assert_eq!(err.to_string(), "Failed to submit: ERROR_DEVICE_LOST");
+
+    let err = VkResult::<()>::Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY)
+      .context("Failed to allocate")
+      .unwrap_err();
+    assert!(err.is_out_of_memory());
+    assert_eq!(RenderError::NoSuitableDevice.vk_result(), None);
+  }
+}
@@ -0,0 +1,99 @@
+use image::{Rgba, RgbaImage};
+
+pub struct Comparison {
+  // pixels which have at least one channel differing by more than the tolerance
+  pub mismatched: usize,
+  pub max_difference: u8,
+  // mismatched pixels are red, others are a dimmed version of the reference
+  pub diff: RgbaImage,
+}
+
+pub fn compare(
+  reference: &RgbaImage,
+  actual: &RgbaImage,
+  tolerance: u8,
+) -> Result<Comparison, String> {
+  if reference.dimensions() != actual.dimensions() {
+    return Err(format!(
+      "Image dimensions differ: reference is {:?} and actual is {:?}",
+      reference.dimensions(),
+      actual.dimensions()
+    ));
+  }
+
+  let mut mismatched = 0;
+  let mut max_difference = 0;
+  let mut diff = RgbaImage::new(reference.width(), reference.height());
+  for ((expected, got), out) in reference
+    .pixels()
+    .zip(actual.pixels())
+    .zip(diff.pixels_mut())
+  {
+    let difference = expected
+      .0
+      .iter()
+      .zip(got.0.iter())
+      .map(|(a, b)| a.abs_diff(*b))
+      .max()
+      .unwrap();
+    max_difference = max_difference.max(difference);
+
+    *out = if difference > tolerance {
+      mismatched += 1;
+      Rgba([255, 0, 0, 255])
+    } else {
+      let [r, g, b, _] = expected.0;
+      let luma = ((r as u32 + g as u32 + b as u32) / 3 / 4) as u8;
+      Rgba([luma, luma, luma, 255])
+    };
+  }
+
+  Ok(Comparison {
+    mismatched,
+    max_difference,
+    diff,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn filled(color: [u8; 4]) -> RgbaImage {
+    RgbaImage::from_pixel(4, 4, Rgba(color))
+  }
+
+  #[test]
+  fn identical_images_match() {
+    let image = filled([10, 20, 30, 255]);
+    let comparison = compare(&image, &image, 0).unwrap();
+    assert_eq!(comparison.mismatched, 0);
+    assert_eq!(comparison.max_difference, 0);
+  }
+
+  #[test]
+  fn differences_within_tolerance_match() {
+    let comparison = compare(&filled([10, 20, 30, 255]), &filled([12, 18, 30, 255]), 2).unwrap();
+    assert_eq!(comparison.mismatched, 0);
+    assert_eq!(comparison.max_difference, 2);
+  }
+
+  #[test]
+  fn differences_above_tolerance_are_marked() {
+    let reference = filled([10, 20, 30, 255]);
+    let mut actual = reference.clone();
+    actual.put_pixel(1, 2, Rgba([10, 20, 40, 255]));
+
+    let comparison = compare(&reference, &actual, 2).unwrap();
+    assert_eq!(comparison.mismatched, 1);
+    assert_eq!(comparison.max_difference, 10);
+    assert_eq!(*comparison.diff.get_pixel(1, 2), Rgba([255, 0, 0, 255]));
+    assert_ne!(*comparison.diff.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+  }
+
+  #[test]
+  fn different_dimensions_fail() {
+    let actual = RgbaImage::new(4, 5);
+    assert!(compare(&filled([0, 0, 0, 255]), &actual, 255).is_err());
+  }
+}
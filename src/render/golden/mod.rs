@@ -0,0 +1,183 @@
+// golden image tests: fixed scenes are rendered headlessly and compared against reference images
+// in tests/golden
+//
+// run with GOLDEN_BLESS=1 to (re)generate the reference images
+// when a comparison fails, the actual and diff images are written to target/golden
+// the tests need lavapipe, the implementation the references were rendered with (see
+// tests/golden/README.md), and are ignored by default, run them with `cargo test golden -- --ignored`
+mod compare;
+
+use std::path::PathBuf;
+
+use ash::vk;
+use cgmath::Point3;
+
+use crate::{objects::Object, scene::SceneDescription, static_scene::StaticScene};
+
+use super::{
+  capture::CapturedFrame, renderer::Renderer, Camera, GpuOverride, HeadlessRender, Models,
+  RenderableIn3d, MODELS_DIR,
+};
+
+const EXTENT: vk::Extent2D = vk::Extent2D {
+  width: 320,
+  height: 240,
+};
+
+// the reference images are rendered with lavapipe, whose devices are named "llvmpipe (LLVM ...)"
+// other implementations rasterize differently, so they are never used for these tests
+const REFERENCE_GPU: &str = "llvmpipe";
+
+// maximum allowed difference of each channel, rasterization can vary slightly between implementations
+const CHANNEL_TOLERANCE: u8 = 3;
+// maximum allowed fraction of mismatched pixels
+const MAX_MISMATCHED_FRACTION: f64 = 0.001;
+
+struct Scene {
+  name: &'static str,
+  camera: Camera,
+  models: Models,
+  static_scene: StaticScene,
+  dyn_objects: Vec<Object>,
+}
+
+impl Scene {
+  // tests/golden/scene.ron viewed from camera
+  fn new(name: &'static str, position: Point3<f32>, yaw: f32, pitch: f32) -> Self {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let models = Models::load(root.join(MODELS_DIR));
+    let description = SceneDescription::load(root.join("tests/golden/scene.ron"), &models).unwrap();
+
+    let mut camera = Camera::new(0.0);
+    camera.position = position;
+    camera.yaw = yaw;
+    camera.pitch = pitch;
+
+    Self {
+      name,
+      camera,
+      static_scene: description.static_scene(&models),
+      dyn_objects: description.dynamic_objects(&models),
+      models,
+    }
+  }
+
+  fn render(self) -> CapturedFrame {
+    let mut render = HeadlessRender::initialize(
+      EXTENT,
+      self.camera,
+      self.models,
+      &self.static_scene,
+      self.dyn_objects.len() as u64,
+      Some(GpuOverride::Name(REFERENCE_GPU.to_owned())),
+    )
+    .expect("Failed to initialize the renderer");
+    let dyn_objects: Vec<&dyn RenderableIn3d> =
+      self.dyn_objects.iter().map(|obj| obj as _).collect();
+    render
+      .render_frame(&dyn_objects)
+      .expect("Failed to render frame");
+    render.capture().expect("Failed to capture frame")
+  }
+}
+
+fn reference_path(name: &str) -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("tests/golden")
+    .join(format!("{name}.png"))
+}
+
+fn output_path(name: &str, kind: &str) -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("target/golden")
+    .join(format!("{name}.{kind}.png"))
+}
+
+fn check_golden(scene: Scene) {
+  assert!(
+    Renderer::is_available(),
+    "Golden test {:?} needs Vulkan with lavapipe installed",
+    scene.name
+  );
+
+  let name = scene.name;
+  let frame = scene.render();
+  let reference_path = reference_path(name);
+
+  if std::env::var_os("GOLDEN_BLESS").is_some_and(|v| v == "1") {
+    frame
+      .save(&reference_path)
+      .expect("Failed to save reference image");
+    eprintln!("Blessed golden image {:?}", reference_path);
+    return;
+  }
+
+  let reference = image::open(&reference_path)
+    .unwrap_or_else(|err| {
+      panic!(
+        "Failed to open reference image {:?} ({}), run with GOLDEN_BLESS=1 to create it",
+        reference_path, err
+      )
+    })
+    .to_rgba8();
+
+  let save_outputs = |diff: Option<&image::RgbaImage>| {
+    let actual_path = output_path(name, "actual");
+    std::fs::create_dir_all(actual_path.parent().unwrap()).expect("Failed to create output dir");
+    frame
+      .save(&actual_path)
+      .expect("Failed to save actual image");
+    if let Some(diff) = diff {
+      diff
+        .save(output_path(name, "diff"))
+        .expect("Failed to save diff image");
+    }
+    actual_path
+  };
+
+  let comparison = match compare::compare(&reference, &frame.image, CHANNEL_TOLERANCE) {
+    Ok(comparison) => comparison,
+    Err(err) => {
+      let actual_path = save_outputs(None);
+      panic!(
+        "Golden image {:?} mismatch: {} (actual saved to {:?})",
+        name, err, actual_path
+      );
+    }
+  };
+
+  let pixel_count = (EXTENT.width * EXTENT.height) as f64;
+  if comparison.mismatched as f64 > pixel_count * MAX_MISMATCHED_FRACTION {
+    let actual_path = save_outputs(Some(&comparison.diff));
+    panic!(
+      "Golden image {:?} mismatch: {} pixels differ (max channel difference {}), see {:?}",
+      name,
+      comparison.mismatched,
+      comparison.max_difference,
+      actual_path.parent().unwrap()
+    );
+  }
+}
+
+#[test]
+#[ignore = "needs Vulkan, run with --ignored"]
+fn golden_front() {
+  check_golden(Scene::new("front", Point3::new(-2.0, 5.0, 5.0), 0.0, 0.0));
+}
+
+#[test]
+#[ignore = "needs Vulkan, run with --ignored"]
+fn golden_side() {
+  check_golden(Scene::new(
+    "side",
+    Point3::new(4.0, 5.0, 0.0),
+    std::f32::consts::FRAC_PI_2,
+    0.0,
+  ));
+}
+
+#[test]
+#[ignore = "needs Vulkan, run with --ignored"]
+fn golden_above() {
+  check_golden(Scene::new("above", Point3::new(0.0, 9.0, 5.0), 0.0, -0.6));
+}
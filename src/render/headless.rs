@@ -0,0 +1,369 @@
+use std::ptr;
+
+use ash::vk;
+
+use crate::static_scene::StaticScene;
+
+use super::{
+  camera::{Camera, RenderCamera},
+  capture::CapturedFrame,
+  error::{RenderError, RenderResult, VkResultExt},
+  models::Models,
+  objects::GpuOverride,
+  renderer::Renderer,
+  sync::{get_dyn_instance_data, CAMERA_SENTIVITY, INITIAL_CAMERA_FOV},
+  RenderableIn3d,
+};
+
+// renders one frame at a time into an offscreen image, without a window or a swapchain
+pub struct HeadlessRender {
+  renderer: Renderer,
+  compute_finished: vk::Semaphore,
+  finished: vk::Fence,
+  // frames rendered so far, each one having finished before the next is recorded
+  frame_count: u64,
+  // whether finished is signaled by a rendered frame, which recreating it after a device loss undoes
+  has_frame: bool,
+  pub camera: RenderCamera,
+}
+
+impl HeadlessRender {
+  pub fn initialize(
+    extent: vk::Extent2D,
+    camera: Camera,
+    models: Models,
+    scene: &StaticScene,
+    initial_dyn_inst_count: u64,
+    gpu_override: Option<GpuOverride>,
+  ) -> RenderResult<Self> {
+    // each frame is waited upon before the next one is recorded
+    let renderer = Renderer::new_headless(
+      extent,
+      models,
+      scene,
+      initial_dyn_inst_count,
+      1,
+      gpu_override,
+    )?;
+    let (compute_finished, finished) = create_sync_objects(renderer.device())?;
+
+    let aspect_ratio = extent.width as f32 / extent.height as f32;
+    let camera = RenderCamera::new(camera, INITIAL_CAMERA_FOV, aspect_ratio, CAMERA_SENTIVITY);
+
+    Ok(Self {
+      renderer,
+      compute_finished,
+      finished,
+      frame_count: 0,
+      has_frame: false,
+      camera,
+    })
+  }
+
+  // renders a frame and waits for it to finish
+  // if the device gets lost, it is recreated and the frame rendered again
+  pub fn render_frame(&mut self, dyn_objects: &[&dyn RenderableIn3d]) -> RenderResult<()> {
+    match self.try_render_frame(dyn_objects) {
+      Err(err) if err.is_device_lost() => {
+        unsafe { self.recover_lost_device()? };
+        self.try_render_frame(dyn_objects)
+      }
+      result => result,
+    }
+  }
+
+  unsafe fn recover_lost_device(&mut self) -> RenderResult<()> {
+    self.has_frame = false;
+    self.renderer.wait_idle();
+    self.destroy_sync_objects();
+    self.renderer.recover_lost_device()?;
+    (self.compute_finished, self.finished) = create_sync_objects(self.renderer.device())?;
+    Ok(())
+  }
+
+  unsafe fn destroy_sync_objects(&mut self) {
+    let device = self.renderer.device();
+    device.destroy_semaphore(self.compute_finished, None);
+    device.destroy_fence(self.finished, None);
+  }
+
+  fn try_render_frame(&mut self, dyn_objects: &[&dyn RenderableIn3d]) -> RenderResult<()> {
+    let (dyn_instances, dyn_inst_props) = get_dyn_instance_data(dyn_objects);
+
+    // there is only one frame in flight and one image to render to
+    unsafe {
+      self.renderer.stream_models(self.frame_count)?;
+      self
+        .renderer
+        .record_frame(0, 0, &self.camera, &dyn_instances, &dyn_inst_props)?;
+
+      self.renderer.submit_compute(0, self.compute_finished)?;
+      // the fence is left signaled after each frame so that it can be captured, and only reset
+      // right before submitting so that it stays signaled if anything before fails
+      self
+        .renderer
+        .device()
+        .reset_fences(&[self.finished])
+        .context("Failed to reset fence")?;
+      self.renderer.submit_graphics(
+        0,
+        &[self.compute_finished],
+        &[vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::VERTEX_INPUT],
+        &[],
+        self.finished,
+      )?;
+
+      self
+        .renderer
+        .device()
+        .wait_for_fences(&[self.finished], true, u64::MAX)
+        .context("Failed to wait for fence")?;
+      self.frame_count += 1;
+      self.has_frame = true;
+      self.renderer.destroy_retired(self.frame_count);
+    }
+    Ok(())
+  }
+
+  // copies the last rendered frame back to the cpu
+  pub fn capture(&mut self) -> RenderResult<CapturedFrame> {
+    // waiting on the fence would never end otherwise
+    if !self.has_frame {
+      return Err(RenderError::NoFrameToCapture);
+    }
+    unsafe { self.renderer.capture_frame(0, self.finished) }
+  }
+}
+
+impl Drop for HeadlessRender {
+  fn drop(&mut self) {
+    // already destroyed if recreating the device failed
+    if self.renderer.is_lost() {
+      return;
+    }
+    self.renderer.wait_idle();
+    unsafe {
+      self.destroy_sync_objects();
+    }
+  }
+}
+
+fn create_sync_objects(device: &ash::Device) -> RenderResult<(vk::Semaphore, vk::Fence)> {
+  let semaphore_create_info = vk::SemaphoreCreateInfo {
+    s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
+    p_next: ptr::null(),
+    flags: vk::SemaphoreCreateFlags::empty(),
+  };
+  let compute_finished = unsafe {
+    device
+      .create_semaphore(&semaphore_create_info, None)
+      .context("Failed to create semaphore")?
+  };
+
+  let fence_create_info = vk::FenceCreateInfo {
+    s_type: vk::StructureType::FENCE_CREATE_INFO,
+    p_next: ptr::null(),
+    flags: vk::FenceCreateFlags::empty(),
+  };
+  let finished = unsafe {
+    device
+      .create_fence(&fence_create_info, None)
+      .context("Failed to create fence")?
+  };
+  Ok((compute_finished, finished))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use cgmath::{Euler, Matrix4, Point3, Rad};
+
+  use crate::{objects::Object, scene::SceneDescription};
+
+  use super::{
+    super::{
+      objects::{IndirectDraw, RenderInstance},
+      shaders::compute_reference as reference,
+      MODELS_DIR,
+    },
+    *,
+  };
+
+  const EXTENT: vk::Extent2D = vk::Extent2D {
+    width: 320,
+    height: 240,
+  };
+
+  fn approx_eq(a: &Matrix4<f32>, b: &Matrix4<f32>) -> bool {
+    let a: &[f32; 16] = a.as_ref();
+    let b: &[f32; 16] = b.as_ref();
+    a.iter()
+      .zip(b.iter())
+      .all(|(x, y)| (x - y).abs() <= 1e-4 * x.abs().max(1.0))
+  }
+
+  // the order of instances within a draw depends on scheduling, so they are compared as sets
+  fn assert_draw_matches(
+    draw_i: usize,
+    expected_draw: &IndirectDraw,
+    expected: &[RenderInstance],
+    draw: &IndirectDraw,
+    actual: &[RenderInstance],
+  ) {
+    assert_eq!(
+      draw.command.instance_count, expected_draw.command.instance_count,
+      "Draw {} has a different amount of visible instances",
+      draw_i
+    );
+    let first = draw.command.first_instance as usize;
+    let range = first..(first + draw.command.instance_count as usize);
+    let mut remaining: Vec<&RenderInstance> = actual[range.clone()].iter().collect();
+    for expected_inst in &expected[range] {
+      let position = remaining
+        .iter()
+        .position(|inst| {
+          inst.texture_i == expected_inst.texture_i
+            && approx_eq(&inst.matrix, &expected_inst.matrix)
+        })
+        .unwrap_or_else(|| panic!("Draw {} is missing an instance", draw_i));
+      remaining.swap_remove(position);
+    }
+  }
+
+  #[test]
+  #[ignore = "needs Vulkan"]
+  fn recovers_from_device_loss() {
+    assert!(Renderer::is_available(), "Vulkan is not available");
+
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let models = Models::load(root.join(MODELS_DIR));
+    let description = SceneDescription::load(root.join("tests/golden/scene.ron"), &models).unwrap();
+    let static_scene = description.static_scene(&models);
+    let dyn_objects = description.dynamic_objects(&models);
+    let dyn_refs: Vec<&dyn RenderableIn3d> = dyn_objects.iter().map(|obj| obj as _).collect();
+
+    let mut camera = Camera::new(0.0);
+    camera.position = Point3::new(-2.0, 5.0, 5.0);
+    let mut render = HeadlessRender::initialize(
+      EXTENT,
+      camera,
+      models,
+      &static_scene,
+      dyn_objects.len() as u64,
+      GpuOverride::from_env(),
+    )
+    .unwrap();
+    render.render_frame(&dyn_refs).unwrap();
+    let before = render.capture().unwrap();
+
+    render.renderer.fake_device_lost();
+    render.render_frame(&dyn_refs).unwrap();
+    let after = render.capture().unwrap();
+
+    // the recreated device renders the same scene, uploaded again
+    assert!(before.image == after.image);
+  }
+
+  #[test]
+  #[ignore = "needs Vulkan"]
+  fn renders_empty_scene() {
+    assert!(Renderer::is_available(), "Vulkan is not available");
+
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let models = Models::load(root.join(MODELS_DIR));
+    let static_scene = StaticScene {
+      objects: Vec::new(),
+    };
+    let mut render = HeadlessRender::initialize(
+      EXTENT,
+      Camera::new(0.0),
+      models,
+      &static_scene,
+      0,
+      GpuOverride::from_env(),
+    )
+    .unwrap();
+    render.render_frame(&[]).unwrap();
+    render.capture().unwrap();
+  }
+
+  #[test]
+  #[ignore = "needs Vulkan"]
+  fn instance_compute_matches_reference() {
+    assert!(Renderer::is_available(), "Vulkan is not available");
+
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let models = Models::load(root.join(MODELS_DIR));
+    let description = SceneDescription::load(root.join("tests/golden/scene.ron"), &models).unwrap();
+    let static_scene = description.static_scene(&models);
+    // enough dynamic objects for several workgroups, with part of them off screen
+    let cube = models.get("cube").expect("cube should be a builtin model");
+    let dyn_objects: Vec<Object> = (0..150)
+      .map(|i| {
+        Object::from_full(
+          cube,
+          Point3::new(i as f32 - 75.0, 5.0, -5.0),
+          Euler::new(Rad(0.0), Rad(0.0), Rad(0.0)),
+          0.3,
+        )
+      })
+      .collect();
+    let dyn_refs: Vec<&dyn RenderableIn3d> = dyn_objects.iter().map(|obj| obj as _).collect();
+
+    let mut camera = Camera::new(0.0);
+    camera.position = Point3::new(-2.0, 5.0, 5.0);
+    let mut render = HeadlessRender::initialize(
+      EXTENT,
+      camera,
+      models,
+      &static_scene,
+      dyn_objects.len() as u64,
+      GpuOverride::from_env(),
+    )
+    .unwrap();
+    render.render_frame(&dyn_refs).unwrap();
+
+    // same inputs as the ones uploaded by the renderer
+    let (static_objs, _) = static_scene.objects();
+    let static_input: Vec<Matrix4<f32>> = static_objs
+      .into_iter()
+      .map(|obj| *obj.ren().model())
+      .collect();
+    let (dyn_instances, dyn_inst_props) = get_dyn_instance_data(&dyn_refs);
+    let dyn_input: Vec<Matrix4<f32>> = dyn_instances.iter().map(|inst| inst.matrix).collect();
+    let mut expected_draws = render.renderer.indirect_draws(&dyn_inst_props).draws;
+    let (static_pc, dyn_pc) = render.renderer.inst_push_constants(
+      &render.camera,
+      dyn_input.len() as u32,
+      dyn_inst_props.len() as u32,
+    );
+
+    let inst_count = static_input.len() + dyn_input.len();
+    let mut expected = vec![
+      RenderInstance {
+        matrix: Matrix4::from_scale(0.0),
+        texture_i: 0,
+        _padding: [0; 3],
+      };
+      inst_count
+    ];
+    reference::dispatch(
+      &static_pc,
+      &static_input,
+      &mut expected_draws,
+      &mut expected,
+    );
+    reference::dispatch(&dyn_pc, &dyn_input, &mut expected_draws, &mut expected);
+
+    let (draws, actual) = unsafe {
+      render
+        .renderer
+        .read_instances(0, expected_draws.len(), inst_count)
+        .unwrap()
+    };
+    for (draw_i, (expected_draw, draw)) in expected_draws.iter().zip(draws.iter()).enumerate() {
+      assert_draw_matches(draw_i, expected_draw, &expected, draw, &actual);
+    }
+  }
+}
@@ -1,5 +1,10 @@
 mod camera;
+mod capture;
 mod cursor;
+mod error;
+#[cfg(test)]
+mod golden;
+mod headless;
 mod models;
 mod objects;
 mod renderable_3d;
@@ -14,18 +19,20 @@ use std::ffi::CStr;
 #[cfg(feature = "vulkan_vl")]
 macro_rules! cstr {
   ( $s:literal ) => {{
-    unsafe { std::mem::transmute::<_, &CStr>(concat!($s, "\0")) }
+    unsafe { CStr::from_bytes_with_nul_unchecked(concat!($s, "\0").as_bytes()) }
   }};
 }
 
 // validation layers should be valid cstrings (for example, not contain null bytes)
 #[cfg(feature = "vulkan_vl")]
-pub const VALIDATION_LAYERS: [&'static CStr; 1] = [cstr!("VK_LAYER_KHRONOS_validation")];
+pub const VALIDATION_LAYERS: [&CStr; 1] = [cstr!("VK_LAYER_KHRONOS_validation")];
 
-pub const DEVICE_EXTENSIONS: [&'static str; 1] = ["VK_KHR_swapchain"];
+pub const DEVICE_EXTENSIONS: [&str; 1] = ["VK_KHR_swapchain"];
 
 pub use camera::Camera;
-pub use models::Models;
-pub use objects::MatrixInstance;
+pub use error::RenderResult;
+pub use headless::HeadlessRender;
+pub use models::{LoadState, ModelHandle, Models, MODELS_DIR};
+pub use objects::{GpuOverride, MatrixInstance};
 pub use renderable_3d::{Renderable3dObject, RenderableIn3d};
-pub use sync::SyncRender;
+pub use sync::{parse_frames_in_flight, SyncRender, DEFAULT_FRAMES_IN_FLIGHT};
@@ -47,6 +47,8 @@ impl Cube {
 }
 
 impl Model for Cube {
+  const NAME: &'static str = "cube";
+
   fn load() -> (Vec<ColorVertex>, Vec<u16>) {
     (Self::VERTICES.to_vec(), Self::INDICES.to_vec())
   }
@@ -0,0 +1,130 @@
+use std::{
+  path::PathBuf,
+  sync::mpsc::{self, Receiver, Sender},
+  thread::{self, JoinHandle},
+};
+
+use image::RgbaImage;
+use log::error;
+
+use super::{
+  obj_file::{self, ObjMesh},
+  ModelHandle,
+};
+
+// a parsed model file, with its texture decoded if it has one
+pub struct LoadedModel {
+  pub handle: ModelHandle,
+  pub result: Result<(ObjMesh, Option<RgbaImage>), String>,
+}
+
+// parses model files and decodes their textures on a background thread, in request order
+pub struct AssetLoader {
+  // both dropped before joining, so that the thread stops after the model it is loading
+  requests: Option<Sender<(ModelHandle, PathBuf)>>,
+  loaded: Option<Receiver<LoadedModel>>,
+  thread: Option<JoinHandle<()>>,
+}
+
+impl AssetLoader {
+  pub fn spawn() -> Self {
+    let (requests, request_receiver) = mpsc::channel::<(ModelHandle, PathBuf)>();
+    let (loaded_sender, loaded) = mpsc::channel();
+    let thread = thread::Builder::new()
+      .name("asset loader".to_owned())
+      .spawn(move || {
+        for (handle, path) in request_receiver {
+          let result = load(path);
+          if loaded_sender.send(LoadedModel { handle, result }).is_err() {
+            break;
+          }
+        }
+      })
+      .expect("Failed to spawn the asset loader thread");
+    Self {
+      requests: Some(requests),
+      loaded: Some(loaded),
+      thread: Some(thread),
+    }
+  }
+
+  pub fn request(&self, handle: ModelHandle, path: PathBuf) {
+    if let Some(requests) = &self.requests {
+      // the thread only stops by itself if it panicked, which gets reported when joining
+      let _ = requests.send((handle, path));
+    }
+  }
+
+  // models loaded since the last call, without blocking
+  pub fn receive(&self) -> Vec<LoadedModel> {
+    self
+      .loaded
+      .iter()
+      .flat_map(|loaded| loaded.try_iter())
+      .collect()
+  }
+}
+
+impl Drop for AssetLoader {
+  fn drop(&mut self) {
+    // remaining requests are discarded
+    self.requests = None;
+    self.loaded = None;
+    if let Some(thread) = self.thread.take() {
+      if thread.join().is_err() {
+        error!("The asset loader thread panicked");
+      }
+    }
+  }
+}
+
+fn load(path: PathBuf) -> Result<(ObjMesh, Option<RgbaImage>), String> {
+  let mesh = obj_file::load(&path)?;
+  let texture = match &mesh {
+    ObjMesh::Textured(_, _, texture_path) => Some(
+      image::open(texture_path)
+        .map_err(|err| format!("Failed to load texture {:?}: {}", texture_path, err))?
+        .to_rgba8(),
+    ),
+    ObjMesh::Color(..) => None,
+  };
+  Ok((mesh, texture))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::Path;
+
+  use super::*;
+  use crate::render::models::{Models, MODELS_DIR};
+
+  #[test]
+  fn loads_requested_models_in_background() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(MODELS_DIR);
+    let models = Models::scan(dir);
+    let niko = models.get("niko").unwrap();
+
+    let loader = AssetLoader::spawn();
+    loader.request(
+      niko,
+      models
+        .pending()
+        .find(|(h, _)| *h == niko)
+        .unwrap()
+        .1
+        .to_owned(),
+    );
+    loader.request(niko, PathBuf::from("missing.obj"));
+
+    let mut loaded = Vec::new();
+    while loaded.len() < 2 {
+      loaded.extend(loader.receive());
+      thread::yield_now();
+    }
+    assert!(matches!(
+      &loaded[0].result,
+      Ok((ObjMesh::Textured(..), Some(_)))
+    ));
+    assert!(loaded[1].result.is_err());
+  }
+}
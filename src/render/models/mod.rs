@@ -1,68 +1,469 @@
+use std::{
+  collections::HashMap,
+  fs,
+  path::{Path, PathBuf},
+};
+
 use crate::structures::Linear2dVec;
 
-use super::objects::ColorVertex;
+use super::objects::{ColorVertex, TexVertex};
+
+use self::obj_file::ObjMesh;
 
 mod cube;
-mod niko;
+mod loader;
+mod obj_file;
 mod weird_square;
 
+pub use loader::AssetLoader;
+
+pub const MODELS_DIR: &str = "./assets/models";
+
+// built in models, registered before any model file
 trait Model {
+  const NAME: &'static str;
+
   fn load() -> (Vec<ColorVertex>, Vec<u16>);
 }
 
+// handle to a registered model, stays valid for the registry lifetime
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ModelHandle(usize);
+
+impl ModelHandle {
+  pub fn index(self) -> usize {
+    self.0
+  }
+}
+
+// position of a model in either the color or the textured vertex and index data
+enum ModelKind {
+  Color(usize),
+  Textured(usize),
+  // registered by scan, waiting for its file to be loaded
+  Pending(PathBuf),
+  // its file couldn't be loaded, the model is drawn as nothing
+  Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadState {
+  Pending,
+  Ready,
+  Failed,
+}
+
 #[derive(Debug)]
 pub struct ModelProperties {
+  #[allow(dead_code)]
   pub vertex_count: u32,
   pub vertex_offset: i32,
   pub index_count: u32,
   pub index_offset: u32,
+  // index into the loaded textures, textured models use their own vertex and index buffer regions
+  pub texture_i: Option<usize>,
+  // model space bounding sphere, (center, radius)
+  pub bounds: [f32; 4],
 }
 
+// sphere around the axis aligned bounding box center containing all positions
+fn bounding_sphere(positions: impl Iterator<Item = [f32; 3]> + Clone) -> [f32; 4] {
+  let mut min = [f32::MAX; 3];
+  let mut max = [f32::MIN; 3];
+  let mut empty = true;
+  for pos in positions.clone() {
+    empty = false;
+    for axis in 0..3 {
+      min[axis] = min[axis].min(pos[axis]);
+      max[axis] = max[axis].max(pos[axis]);
+    }
+  }
+  if empty {
+    return [0.0; 4];
+  }
+
+  let center: [f32; 3] = std::array::from_fn(|axis| (min[axis] + max[axis]) / 2.0);
+  let radius = positions
+    .map(|pos| {
+      (0..3)
+        .map(|axis| (pos[axis] - center[axis]).powi(2))
+        .sum::<f32>()
+        .sqrt()
+    })
+    .fold(0.0, f32::max);
+  [center[0], center[1], center[2], radius]
+}
+
+// model registry
+// color and textured models are stored in separate vertex and index regions, while handles follow
+// registration order
 pub struct Models {
   pub vertices: Linear2dVec<ColorVertex>,
   pub indices: Linear2dVec<u16>,
+  pub tex_vertices: Linear2dVec<TexVertex>,
+  pub tex_indices: Linear2dVec<u16>,
+  // texture path of each textured model
+  pub textures: Vec<PathBuf>,
+  kinds: Vec<ModelKind>,
+  names: HashMap<String, ModelHandle>,
 }
 
 impl Models {
-  pub const SQUARE_INDEX: usize = 0;
-  pub const CUBE_INDEX: usize = 1;
-  pub const NIKO_INDEX: usize = 2;
+  // built in models followed by every obj file in dir
+  pub fn load<P: AsRef<Path>>(dir: P) -> Self {
+    let mut models = Self::builtin();
+    models.load_dir(dir);
+    models
+  }
 
-  pub fn load() -> Self {
-    let data = [
-      weird_square::WeirdSquare::load(),
-      cube::Cube::load(),
-      niko::Niko::load(),
-    ];
-    let (vertices, indices) = data.into_iter().unzip();
-    let vertices = Self::load_vertices(vertices);
-    let indices = Self::load_indices(indices);
-    Self { vertices, indices }
+  // built in models followed by every obj file in dir, registered without being loaded
+  // the files are meant to be loaded in the background and passed to finish_loading
+  pub fn scan<P: AsRef<Path>>(dir: P) -> Self {
+    let mut models = Self::builtin();
+    for (name, path) in Self::obj_files(dir.as_ref()) {
+      models.add_name(name);
+      models.kinds.push(ModelKind::Pending(path));
+    }
+    models
   }
 
-  fn load_vertices(vertices: Vec<Vec<ColorVertex>>) -> Linear2dVec<ColorVertex> {
-    let mut iter = vertices.into_iter();
-    let iter: &mut dyn ExactSizeIterator<Item = Vec<ColorVertex>> = &mut iter;
-    Linear2dVec::from(iter)
+  // registry containing only the built in models
+  pub fn builtin() -> Self {
+    let mut models = Self {
+      vertices: Linear2dVec::default(),
+      indices: Linear2dVec::default(),
+      tex_vertices: Linear2dVec::default(),
+      tex_indices: Linear2dVec::default(),
+      textures: Vec::new(),
+      kinds: Vec::new(),
+      names: HashMap::new(),
+    };
+    models.register_builtin::<weird_square::WeirdSquare>();
+    models.register_builtin::<cube::Cube>();
+    models
   }
 
-  fn load_indices(indices: Vec<Vec<u16>>) -> Linear2dVec<u16> {
-    let mut iter = indices.into_iter();
-    let iter: &mut dyn ExactSizeIterator<Item = Vec<u16>> = &mut iter;
-    Linear2dVec::from(iter)
+  fn register_builtin<M: Model>(&mut self) -> ModelHandle {
+    let (vertices, indices) = M::load();
+    self.register_color(M::NAME, vertices, indices)
+  }
+
+  // registers every obj file in dir and in its direct subdirectories, named after the file stem
+  // files are registered in path order so handles don't depend on directory iteration order
+  pub fn load_dir<P: AsRef<Path>>(&mut self, dir: P) -> Vec<ModelHandle> {
+    Self::obj_files(dir.as_ref())
+      .into_iter()
+      .map(|(name, path)| self.load_obj(name, &path))
+      .collect()
   }
 
-  pub fn into_properties(self) -> Vec<ModelProperties> {
+  // obj files in dir and in its direct subdirectories in path order, along with their names
+  fn obj_files(dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut paths = Vec::new();
+    for path in Self::read_dir_sorted(dir) {
+      if path.is_dir() {
+        paths.extend(
+          Self::read_dir_sorted(&path)
+            .into_iter()
+            .filter(|p| Self::is_obj(p)),
+        );
+      } else if Self::is_obj(&path) {
+        paths.push(path);
+      }
+    }
+
+    paths
+      .into_iter()
+      .map(|path| {
+        let name = path
+          .file_stem()
+          .and_then(|stem| stem.to_str())
+          .unwrap_or_else(|| panic!("Invalid model file name {:?}", path))
+          .to_owned();
+        (name, path)
+      })
+      .collect()
+  }
+
+  fn read_dir_sorted(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+      .unwrap_or_else(|err| panic!("Failed to read models directory {:?}: {}", dir, err))
+      .map(|entry| entry.expect("Failed to read directory entry").path())
+      .collect();
+    paths.sort();
+    paths
+  }
+
+  fn is_obj(path: &Path) -> bool {
+    path.is_file() && path.extension().is_some_and(|ext| ext == "obj")
+  }
+
+  pub fn load_obj<S: Into<String>>(&mut self, name: S, path: &Path) -> ModelHandle {
+    let mesh = obj_file::load(path).unwrap_or_else(|err| panic!("{}", err));
+    let handle = self.add_name(name.into());
+    let kind = self.push_mesh(mesh);
+    self.kinds.push(kind);
+    handle
+  }
+
+  pub fn register_color<S: Into<String>>(
+    &mut self,
+    name: S,
+    vertices: Vec<ColorVertex>,
+    indices: Vec<u16>,
+  ) -> ModelHandle {
+    let handle = self.add_name(name.into());
+    let kind = self.push_mesh(ObjMesh::Color(vertices, indices));
+    self.kinds.push(kind);
+    handle
+  }
+
+  pub fn register_textured<S: Into<String>>(
+    &mut self,
+    name: S,
+    vertices: Vec<TexVertex>,
+    indices: Vec<u16>,
+    texture: PathBuf,
+  ) -> ModelHandle {
+    let handle = self.add_name(name.into());
+    let kind = self.push_mesh(ObjMesh::Textured(vertices, indices, texture));
+    self.kinds.push(kind);
+    handle
+  }
+
+  fn push_mesh(&mut self, mesh: ObjMesh) -> ModelKind {
+    match mesh {
+      ObjMesh::Color(vertices, indices) => {
+        let i = self.vertices.part_count();
+        self.vertices.push(vertices);
+        self.indices.push(indices);
+        ModelKind::Color(i)
+      }
+      ObjMesh::Textured(vertices, indices, texture) => {
+        let i = self.textures.len();
+        self.tex_vertices.push(vertices);
+        self.tex_indices.push(indices);
+        self.textures.push(texture);
+        ModelKind::Textured(i)
+      }
+    }
+  }
+
+  // pending models along with the file they should be loaded from
+  pub fn pending(&self) -> impl Iterator<Item = (ModelHandle, &Path)> {
     self
-      .vertices
-      .into_parts_iter()
-      .zip(self.indices.into_parts_iter())
-      .map(|(vertex_p, index_p)| ModelProperties {
-        vertex_count: vertex_p.size as u32,
-        vertex_offset: vertex_p.offset as i32,
-        index_count: index_p.size as u32,
-        index_offset: index_p.offset as u32,
+      .kinds
+      .iter()
+      .enumerate()
+      .filter_map(|(i, kind)| match kind {
+        ModelKind::Pending(path) => Some((ModelHandle(i), path.as_path())),
+        _ => None,
+      })
+  }
+
+  // stores the data of a pending model, textured models get the next texture index
+  pub fn finish_loading(&mut self, handle: ModelHandle, mesh: ObjMesh) {
+    assert_eq!(self.state(handle), LoadState::Pending);
+    self.kinds[handle.0] = self.push_mesh(mesh);
+  }
+
+  pub fn fail(&mut self, handle: ModelHandle) {
+    assert_eq!(self.state(handle), LoadState::Pending);
+    self.kinds[handle.0] = ModelKind::Failed;
+  }
+
+  pub fn state(&self, handle: ModelHandle) -> LoadState {
+    match self.kinds[handle.0] {
+      ModelKind::Color(_) | ModelKind::Textured(_) => LoadState::Ready,
+      ModelKind::Pending(_) => LoadState::Pending,
+      ModelKind::Failed => LoadState::Failed,
+    }
+  }
+
+  // state of each model, indexed by handle
+  pub fn states(&self) -> Vec<LoadState> {
+    self.handles().map(|handle| self.state(handle)).collect()
+  }
+
+  // textures that may end up loaded, as any pending model could be textured
+  pub fn texture_capacity(&self) -> usize {
+    self.textures.len() + self.pending().count()
+  }
+
+  fn add_name(&mut self, name: String) -> ModelHandle {
+    let handle = ModelHandle(self.kinds.len());
+    if self.names.insert(name.clone(), handle).is_some() {
+      panic!("Model {:?} is already registered", name);
+    }
+    handle
+  }
+
+  pub fn get(&self, name: &str) -> Option<ModelHandle> {
+    self.names.get(name).copied()
+  }
+
+  // all registered models in registration order
+  pub fn handles(&self) -> impl Iterator<Item = ModelHandle> {
+    (0..self.kinds.len()).map(ModelHandle)
+  }
+
+  // properties of each model, indexed by handle
+  pub fn properties(&self) -> Vec<ModelProperties> {
+    let bounds: Vec<[f32; 4]> = self
+      .kinds
+      .iter()
+      .map(|kind| match *kind {
+        ModelKind::Color(i) => bounding_sphere(self.vertices[i].iter().map(|v| v.pos)),
+        ModelKind::Textured(i) => bounding_sphere(self.tex_vertices[i].iter().map(|v| v.pos)),
+        ModelKind::Pending(_) | ModelKind::Failed => [0.0; 4],
+      })
+      .collect();
+
+    self
+      .kinds
+      .iter()
+      .zip(bounds)
+      .map(|(kind, bounds)| {
+        let (vertex_p, index_p, texture_i) = match *kind {
+          ModelKind::Color(i) => (self.vertices.part(i), self.indices.part(i), None),
+          ModelKind::Textured(i) => (self.tex_vertices.part(i), self.tex_indices.part(i), Some(i)),
+          // drawn with no indices until loaded
+          ModelKind::Pending(_) | ModelKind::Failed => {
+            return ModelProperties {
+              vertex_count: 0,
+              vertex_offset: 0,
+              index_count: 0,
+              index_offset: 0,
+              texture_i: None,
+              bounds,
+            }
+          }
+        };
+        ModelProperties {
+          vertex_count: vertex_p.size as u32,
+          vertex_offset: vertex_p.offset as i32,
+          index_count: index_p.size as u32,
+          index_offset: index_p.offset as u32,
+          texture_i,
+          bounds,
+        }
       })
       .collect()
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn triangle() -> (Vec<ColorVertex>, Vec<u16>) {
+    let vertex = ColorVertex {
+      pos: [0.0, 0.0, 0.0],
+      color: [1.0, 1.0, 1.0],
+    };
+    (vec![vertex; 3], vec![0, 1, 2])
+  }
+
+  fn tex_triangle() -> (Vec<TexVertex>, Vec<u16>) {
+    let vertex = TexVertex {
+      pos: [0.0, 0.0, 0.0],
+      normal: [0.0, 0.0, 1.0],
+      tex: [0.0, 0.0],
+    };
+    (vec![vertex; 3], vec![0, 1, 2])
+  }
+
+  #[test]
+  fn handles_follow_registration_order() {
+    let mut models = Models::builtin();
+    let builtin_count = models.kinds.len();
+
+    let (vertices, indices) = tex_triangle();
+    let tex = models.register_textured("tex", vertices, indices, PathBuf::from("tex.png"));
+    let (vertices, indices) = triangle();
+    let color = models.register_color("color", vertices, indices);
+
+    assert_eq!(tex.index(), builtin_count);
+    assert_eq!(color.index(), builtin_count + 1);
+    assert_eq!(models.get("tex"), Some(tex));
+    assert_eq!(models.get("color"), Some(color));
+    assert_eq!(models.get("missing"), None);
+
+    let props = models.properties();
+    assert_eq!(props.len(), builtin_count + 2);
+    assert_eq!(props[tex.index()].texture_i, Some(0));
+    assert_eq!(props[tex.index()].vertex_offset, 0);
+    assert_eq!(props[color.index()].texture_i, None);
+    assert_eq!(props[color.index()].index_count, 3);
+  }
+
+  #[test]
+  fn bounding_sphere_contains_all_vertices() {
+    let positions = [[1.0, 0.0, 0.0], [3.0, 2.0, 0.0], [2.0, 1.0, 4.0]];
+    let [x, y, z, radius] = bounding_sphere(positions.iter().copied());
+    assert_eq!([x, y, z], [2.0, 1.0, 2.0]);
+    for pos in positions {
+      let distance = ((pos[0] - x).powi(2) + (pos[1] - y).powi(2) + (pos[2] - z).powi(2)).sqrt();
+      assert!(distance <= radius);
+    }
+    assert_eq!(bounding_sphere(std::iter::empty()), [0.0; 4]);
+  }
+
+  #[test]
+  #[should_panic(expected = "already registered")]
+  fn duplicate_names_panic() {
+    let mut models = Models::builtin();
+    let (vertices, indices) = triangle();
+    models.register_color("cube", vertices, indices);
+  }
+
+  #[test]
+  fn loads_models_directory() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(MODELS_DIR);
+    let models = Models::load(dir);
+
+    let niko = models.get("niko").expect("niko model should be loaded");
+    assert!(matches!(models.kinds[niko.index()], ModelKind::Textured(_)));
+    assert!(models.textures[0].ends_with("tex/baked.png"));
+  }
+
+  #[test]
+  fn scanned_models_are_pending_until_loaded() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(MODELS_DIR);
+    let mut models = Models::scan(&dir);
+    let cube = models.get("cube").unwrap();
+    let niko = models.get("niko").expect("niko model should be registered");
+    assert_eq!(models.state(cube), LoadState::Ready);
+    assert_eq!(models.state(niko), LoadState::Pending);
+    assert!(models.textures.is_empty());
+    let pending_count = models.pending().count();
+    assert_eq!(models.texture_capacity(), pending_count);
+    assert_eq!(models.properties()[niko.index()].index_count, 0);
+
+    let (handle, path) = models.pending().find(|(h, _)| *h == niko).unwrap();
+    let mesh = obj_file::load(path).unwrap();
+    models.finish_loading(handle, mesh);
+    assert_eq!(models.state(niko), LoadState::Ready);
+    assert_eq!(models.texture_capacity(), pending_count);
+    let props = models.properties();
+    assert_eq!(props[niko.index()].texture_i, Some(0));
+    assert!(props[niko.index()].index_count > 0);
+  }
+
+  #[test]
+  fn failed_models_are_drawn_as_nothing() {
+    let mut models = Models::builtin();
+    models.add_name("broken".to_owned());
+    models
+      .kinds
+      .push(ModelKind::Pending(PathBuf::from("broken.obj")));
+    let broken = models.get("broken").unwrap();
+    assert_eq!(models.texture_capacity(), 1);
+
+    models.fail(broken);
+    assert_eq!(models.state(broken), LoadState::Failed);
+    assert_eq!(models.pending().count(), 0);
+    assert_eq!(models.texture_capacity(), 0);
+    assert_eq!(models.properties()[broken.index()].index_count, 0);
+  }
+}
@@ -0,0 +1,82 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  time::Instant,
+};
+
+use log::{info, warn};
+use obj::{load_obj, Obj};
+
+use crate::render::objects::{ColorVertex, TexVertex};
+
+// color of vertices from obj files without a texture
+const UNTEXTURED_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+
+pub enum ObjMesh {
+  Color(Vec<ColorVertex>, Vec<u16>),
+  Textured(Vec<TexVertex>, Vec<u16>, PathBuf),
+}
+
+// loads an obj file, the model is textured if any of its materials has a diffuse texture map
+// only the first texture found is used for the whole model
+pub fn load(path: &Path) -> Result<ObjMesh, String> {
+  let start = Instant::now();
+  let contents =
+    fs::read(path).map_err(|err| format!("Failed to open model file {:?}: {}", path, err))?;
+  let dir = path.parent().unwrap_or(Path::new(""));
+
+  let mesh = match find_texture(&contents, dir) {
+    Some(texture) => {
+      let obj: Obj<obj::TexturedVertex, u16> = load_obj(contents.as_slice())
+        .map_err(|err| format!("Failed to load model {:?}: {}", path, err))?;
+      let vertices = obj
+        .vertices
+        .into_iter()
+        .map(|v| TexVertex {
+          pos: v.position,
+          normal: v.normal,
+          // obj texture coordinates start at the bottom left, while vulkan ones start at the top left
+          tex: [v.texture[0], 1.0 - v.texture[1]],
+        })
+        .collect();
+      ObjMesh::Textured(vertices, obj.indices, texture)
+    }
+    None => {
+      let obj: Obj<obj::Position, u16> = load_obj(contents.as_slice())
+        .map_err(|err| format!("Failed to load model {:?}: {}", path, err))?;
+      let vertices = obj
+        .vertices
+        .into_iter()
+        .map(|v| ColorVertex {
+          pos: v.position,
+          color: UNTEXTURED_COLOR,
+        })
+        .collect();
+      ObjMesh::Color(vertices, obj.indices)
+    }
+  };
+  info!("Loaded model {:?} in {:?}", path, start.elapsed());
+  Ok(mesh)
+}
+
+// path of the first "map_Kd" in the material libraries referenced by an obj file
+fn find_texture(obj_contents: &[u8], dir: &Path) -> Option<PathBuf> {
+  let obj_contents = String::from_utf8_lossy(obj_contents);
+  obj_contents
+    .lines()
+    .filter_map(|line| line.trim().strip_prefix("mtllib "))
+    .find_map(|mtl| {
+      let mtl_path = dir.join(mtl.trim());
+      let mtl_contents = match fs::read_to_string(&mtl_path) {
+        Ok(contents) => contents,
+        Err(err) => {
+          warn!("Failed to open material file {:?}: {}", mtl_path, err);
+          return None;
+        }
+      };
+      mtl_contents
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("map_Kd "))
+        .map(|texture| dir.join(texture.trim()))
+    })
+}
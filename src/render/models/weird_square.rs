@@ -28,6 +28,8 @@ impl WeirdSquare {
 }
 
 impl Model for WeirdSquare {
+  const NAME: &'static str = "square";
+
   fn load() -> (Vec<ColorVertex>, Vec<u16>) {
     (Self::VERTICES.to_vec(), Self::INDICES.to_vec())
   }
@@ -0,0 +1,144 @@
+// tracks free ranges of a memory block
+// ranges are sorted by offset and adjacent ones are always merged
+pub struct FreeList {
+  size: u64,
+  // (offset, size)
+  free: Vec<(u64, u64)>,
+  used: u64,
+  allocation_count: usize,
+}
+
+// alignment should be a power of 2
+pub fn align_up(value: u64, alignment: u64) -> u64 {
+  debug_assert!(alignment.is_power_of_two());
+  (value + alignment - 1) & !(alignment - 1)
+}
+
+impl FreeList {
+  pub fn new(size: u64) -> Self {
+    Self {
+      size,
+      free: vec![(0, size)],
+      used: 0,
+      allocation_count: 0,
+    }
+  }
+
+  // returns the offset of the first free range that fits size at the given alignment
+  // padding in front of the aligned offset stays free
+  pub fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+    assert!(size > 0);
+    let (i, offset) = self
+      .free
+      .iter()
+      .enumerate()
+      .find_map(|(i, &(start, len))| {
+        let offset = align_up(start, alignment);
+        (offset + size <= start + len).then_some((i, offset))
+      })?;
+
+    let (start, len) = self.free.remove(i);
+    let end = start + len;
+    let mut insert_i = i;
+    if offset > start {
+      self.free.insert(insert_i, (start, offset - start));
+      insert_i += 1;
+    }
+    if offset + size < end {
+      self
+        .free
+        .insert(insert_i, (offset + size, end - (offset + size)));
+    }
+
+    self.used += size;
+    self.allocation_count += 1;
+    Some(offset)
+  }
+
+  pub fn free(&mut self, offset: u64, size: u64) {
+    assert!(offset + size <= self.size);
+    let i = self.free.partition_point(|&(start, _)| start < offset);
+    debug_assert!(i == 0 || self.free[i - 1].0 + self.free[i - 1].1 <= offset);
+    debug_assert!(i == self.free.len() || offset + size <= self.free[i].0);
+
+    self.free.insert(i, (offset, size));
+    // merge with the next range, then with the previous one
+    if i + 1 < self.free.len() && offset + size == self.free[i + 1].0 {
+      self.free[i].1 += self.free[i + 1].1;
+      self.free.remove(i + 1);
+    }
+    if i > 0 && self.free[i - 1].0 + self.free[i - 1].1 == offset {
+      self.free[i - 1].1 += self.free[i].1;
+      self.free.remove(i);
+    }
+
+    self.used -= size;
+    self.allocation_count -= 1;
+  }
+
+  pub fn size(&self) -> u64 {
+    self.size
+  }
+
+  pub fn used(&self) -> u64 {
+    self.used
+  }
+
+  pub fn allocation_count(&self) -> usize {
+    self.allocation_count
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.allocation_count == 0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn align_up_keeps_aligned_values() {
+    assert_eq!(align_up(0, 256), 0);
+    assert_eq!(align_up(256, 256), 256);
+    assert_eq!(align_up(257, 256), 512);
+    assert_eq!(align_up(3, 4), 4);
+  }
+
+  #[test]
+  fn allocations_are_aligned_and_disjoint() {
+    let mut list = FreeList::new(1024);
+    assert_eq!(list.allocate(10, 1), Some(0));
+    assert_eq!(list.allocate(64, 64), Some(64));
+    // the padding between the first two allocations is still usable
+    assert_eq!(list.allocate(16, 16), Some(16));
+    assert_eq!(list.used(), 90);
+    assert_eq!(list.allocation_count(), 3);
+  }
+
+  #[test]
+  fn aligned_sizes_are_not_padded() {
+    let mut list = FreeList::new(512);
+    assert_eq!(list.allocate(256, 256), Some(0));
+    assert_eq!(list.allocate(256, 256), Some(256));
+    assert_eq!(list.allocate(1, 1), None);
+  }
+
+  #[test]
+  fn freed_ranges_are_merged() {
+    let mut list = FreeList::new(300);
+    let a = list.allocate(100, 1).unwrap();
+    let b = list.allocate(100, 1).unwrap();
+    let c = list.allocate(100, 1).unwrap();
+    assert_eq!(list.allocate(1, 1), None);
+
+    list.free(a, 100);
+    list.free(c, 100);
+    // two separate 100 byte ranges
+    assert_eq!(list.allocate(150, 1), None);
+
+    list.free(b, 100);
+    assert!(list.is_empty());
+    assert_eq!(list.allocate(300, 1), Some(0));
+  }
+}
@@ -0,0 +1,414 @@
+mod free_list;
+
+use std::{fmt, ptr};
+
+use ash::vk;
+use log::{debug, warn};
+
+use self::free_list::FreeList;
+use crate::render::error::{RenderError, RenderResult, VkResultExt};
+
+// size of each device memory block, bigger allocations get a block of their own
+const BLOCK_SIZE: u64 = 64 * 1024 * 1024;
+// smallest shared block tried when there isn't enough memory for a full one
+const MIN_BLOCK_SIZE: u64 = 4 * 1024 * 1024;
+
+// resource kinds are kept in separate blocks so that buffer image granularity never has to be
+// taken into account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResourceKind {
+  // buffers
+  Linear,
+  // optimal tiling images
+  Optimal,
+}
+
+// a region of device memory that a resource is bound to
+// like vulkan handles, copies refer to the same allocation which should only be freed once
+#[derive(Clone, Copy)]
+pub struct Allocation {
+  memory: vk::DeviceMemory,
+  offset: u64,
+  size: u64,
+  memory_type: u32,
+  block_id: u64,
+  // null if the memory is not host visible
+  mapped: *mut u8,
+}
+
+impl Allocation {
+  // pointer to the start of the allocation, memory has to be host visible
+  pub fn mapped_ptr(&self) -> *mut u8 {
+    assert!(!self.mapped.is_null(), "Allocation is not host visible");
+    self.mapped
+  }
+}
+
+struct MemoryBlock {
+  id: u64,
+  memory: vk::DeviceMemory,
+  kind: ResourceKind,
+  // whole block is mapped for its lifetime if host visible
+  mapped: *mut u8,
+  // blocks created for a single big allocation are freed as soon as they are empty
+  dedicated: bool,
+  free_list: FreeList,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MemoryTypeStats {
+  pub memory_type: u32,
+  pub block_count: usize,
+  pub allocation_count: usize,
+  // device memory allocated in blocks
+  pub reserved_bytes: u64,
+  // memory given out to allocations
+  pub used_bytes: u64,
+}
+
+// statistics of each memory type that has at least one block
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AllocatorStats {
+  pub memory_types: Vec<MemoryTypeStats>,
+}
+
+impl AllocatorStats {
+  pub fn total(&self) -> MemoryTypeStats {
+    self
+      .memory_types
+      .iter()
+      .fold(MemoryTypeStats::default(), |acc, stats| MemoryTypeStats {
+        memory_type: acc.memory_type,
+        block_count: acc.block_count + stats.block_count,
+        allocation_count: acc.allocation_count + stats.allocation_count,
+        reserved_bytes: acc.reserved_bytes + stats.reserved_bytes,
+        used_bytes: acc.used_bytes + stats.used_bytes,
+      })
+  }
+}
+
+impl fmt::Display for AllocatorStats {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let total = self.total();
+    write!(
+      f,
+      "{} allocations using {} of {} bytes in {} blocks",
+      total.allocation_count, total.used_bytes, total.reserved_bytes, total.block_count
+    )?;
+    for stats in self.memory_types.iter() {
+      write!(
+        f,
+        "\n  memory type {}: {} allocations using {} of {} bytes in {} blocks",
+        stats.memory_type,
+        stats.allocation_count,
+        stats.used_bytes,
+        stats.reserved_bytes,
+        stats.block_count
+      )?;
+    }
+    Ok(())
+  }
+}
+
+// sub-allocates resources from big device memory blocks, one list of blocks per memory type
+pub struct Allocator {
+  memory_properties: vk::PhysicalDeviceMemoryProperties,
+  blocks: Vec<Vec<MemoryBlock>>,
+  next_block_id: u64,
+}
+
+impl Allocator {
+  pub fn new(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Self {
+    let memory_properties =
+      unsafe { instance.get_physical_device_memory_properties(physical_device) };
+    let blocks = (0..memory_properties.memory_type_count)
+      .map(|_| Vec::new())
+      .collect();
+    Self {
+      memory_properties,
+      blocks,
+      next_block_id: 0,
+    }
+  }
+
+  // allocates memory for buffer and binds it
+  pub fn bind_buffer(
+    &mut self,
+    device: &ash::Device,
+    buffer: vk::Buffer,
+    required_flags: vk::MemoryPropertyFlags,
+  ) -> RenderResult<Allocation> {
+    let requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+    let allocation = self.allocate(device, requirements, required_flags, ResourceKind::Linear)?;
+    let result = unsafe { device.bind_buffer_memory(buffer, allocation.memory, allocation.offset) };
+    if let Err(result) = result {
+      self.free(device, allocation);
+      return Err(result).context("Failed to bind buffer to its memory");
+    }
+    Ok(allocation)
+  }
+
+  // allocates memory for an optimal tiling image and binds it
+  pub fn bind_image(
+    &mut self,
+    device: &ash::Device,
+    image: vk::Image,
+    required_flags: vk::MemoryPropertyFlags,
+  ) -> RenderResult<Allocation> {
+    let requirements = unsafe { device.get_image_memory_requirements(image) };
+    let allocation = self.allocate(device, requirements, required_flags, ResourceKind::Optimal)?;
+    let result = unsafe { device.bind_image_memory(image, allocation.memory, allocation.offset) };
+    if let Err(result) = result {
+      self.free(device, allocation);
+      return Err(result).context("Failed to bind image memory");
+    }
+    Ok(allocation)
+  }
+
+  fn allocate(
+    &mut self,
+    device: &ash::Device,
+    requirements: vk::MemoryRequirements,
+    required_flags: vk::MemoryPropertyFlags,
+    kind: ResourceKind,
+  ) -> RenderResult<Allocation> {
+    let memory_type = find_memory_type(
+      requirements.memory_type_bits,
+      required_flags,
+      self.memory_properties,
+    )?;
+    let existing = self.blocks[memory_type as usize]
+      .iter_mut()
+      .enumerate()
+      .filter(|(_, block)| block.kind == kind && !block.dedicated)
+      .find_map(|(i, block)| {
+        block
+          .free_list
+          .allocate(requirements.size, requirements.alignment)
+          .map(|offset| (i, offset))
+      });
+    let (block_i, offset) = match existing {
+      Some(found) => found,
+      None => {
+        let mut block = self.create_fitting_block(device, memory_type, requirements.size, kind)?;
+        // a new block always starts at an aligned offset
+        let offset = block
+          .free_list
+          .allocate(requirements.size, requirements.alignment)
+          .expect("New memory block should fit the allocation");
+        let blocks = &mut self.blocks[memory_type as usize];
+        blocks.push(block);
+        (blocks.len() - 1, offset)
+      }
+    };
+
+    let block = &self.blocks[memory_type as usize][block_i];
+    let mapped = if block.mapped.is_null() {
+      ptr::null_mut()
+    } else {
+      unsafe { block.mapped.add(offset as usize) }
+    };
+    Ok(Allocation {
+      memory: block.memory,
+      offset,
+      size: requirements.size,
+      memory_type,
+      block_id: block.id,
+      mapped,
+    })
+  }
+
+  // tries smaller blocks when running out of memory, down to one just big enough for size
+  fn create_fitting_block(
+    &mut self,
+    device: &ash::Device,
+    memory_type: u32,
+    size: u64,
+    kind: ResourceKind,
+  ) -> RenderResult<MemoryBlock> {
+    let mut result = None;
+    for (block_size, dedicated) in block_sizes(size) {
+      if result.is_some() {
+        warn!(
+          "Out of memory of type {}, retrying with a block of {} bytes",
+          memory_type, block_size
+        );
+      }
+      match self.create_block(device, memory_type, block_size, kind, dedicated) {
+        Err(err) if err.is_out_of_memory() => result = Some(Err(err)),
+        other => return other,
+      }
+    }
+    result.expect("There should be at least one block size")
+  }
+
+  fn create_block(
+    &mut self,
+    device: &ash::Device,
+    memory_type: u32,
+    size: u64,
+    kind: ResourceKind,
+    dedicated: bool,
+  ) -> RenderResult<MemoryBlock> {
+    debug!(
+      "Allocating {} bytes of memory type {} ({:?}, dedicated: {})",
+      size, memory_type, kind, dedicated
+    );
+    let allocate_info = vk::MemoryAllocateInfo {
+      s_type: vk::StructureType::MEMORY_ALLOCATE_INFO,
+      p_next: ptr::null(),
+      allocation_size: size,
+      memory_type_index: memory_type,
+    };
+    let memory = unsafe {
+      device
+        .allocate_memory(&allocate_info, None)
+        .context("Failed to allocate device memory")?
+    };
+
+    let host_visible = self.memory_properties.memory_types[memory_type as usize]
+      .property_flags
+      .contains(vk::MemoryPropertyFlags::HOST_VISIBLE);
+    let mapped = if host_visible {
+      match unsafe { device.map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty()) } {
+        Ok(mapped) => mapped as *mut u8,
+        Err(result) => {
+          unsafe { device.free_memory(memory, None) };
+          return Err(result).context("Failed to map memory");
+        }
+      }
+    } else {
+      ptr::null_mut()
+    };
+
+    let id = self.next_block_id;
+    self.next_block_id += 1;
+    Ok(MemoryBlock {
+      id,
+      memory,
+      kind,
+      mapped,
+      dedicated,
+      free_list: FreeList::new(size),
+    })
+  }
+
+  // the resource using allocation should already be destroyed or no longer in use
+  pub fn free(&mut self, device: &ash::Device, allocation: Allocation) {
+    let blocks = &mut self.blocks[allocation.memory_type as usize];
+    let block_i = blocks
+      .iter()
+      .position(|block| block.id == allocation.block_id)
+      .expect("Allocation doesn't belong to this allocator");
+
+    let block = &mut blocks[block_i];
+    block.free_list.free(allocation.offset, allocation.size);
+    // empty shared blocks are kept for future allocations
+    if block.dedicated && block.free_list.is_empty() {
+      let block = blocks.remove(block_i);
+      unsafe {
+        Self::free_block(device, block);
+      }
+    }
+  }
+
+  unsafe fn free_block(device: &ash::Device, block: MemoryBlock) {
+    // freeing memory implicitly unmaps it
+    device.free_memory(block.memory, None);
+  }
+
+  pub fn stats(&self) -> AllocatorStats {
+    let memory_types = self
+      .blocks
+      .iter()
+      .enumerate()
+      .filter(|(_, blocks)| !blocks.is_empty())
+      .map(|(memory_type, blocks)| MemoryTypeStats {
+        memory_type: memory_type as u32,
+        block_count: blocks.len(),
+        allocation_count: blocks.iter().map(|b| b.free_list.allocation_count()).sum(),
+        reserved_bytes: blocks.iter().map(|b| b.free_list.size()).sum(),
+        used_bytes: blocks.iter().map(|b| b.free_list.used()).sum(),
+      })
+      .collect();
+    AllocatorStats { memory_types }
+  }
+
+  pub unsafe fn destroy_self(&mut self, device: &ash::Device) {
+    let stats = self.stats();
+    if stats.total().allocation_count > 0 {
+      warn!("Destroying allocator with live allocations: {}", stats);
+    }
+    for blocks in self.blocks.iter_mut() {
+      for block in blocks.drain(..) {
+        Self::free_block(device, block);
+      }
+    }
+  }
+}
+
+// block sizes to try in order for an allocation of size, and whether the block is dedicated to it
+fn block_sizes(size: u64) -> Vec<(u64, bool)> {
+  if size > BLOCK_SIZE / 2 {
+    return vec![(size, true)];
+  }
+  let mut sizes: Vec<(u64, bool)> = std::iter::successors(Some(BLOCK_SIZE), |block_size| {
+    Some(block_size / 2).filter(|half| *half >= MIN_BLOCK_SIZE && *half >= size)
+  })
+  .map(|block_size| (block_size, false))
+  .collect();
+  sizes.push((size, true));
+  sizes
+}
+
+fn find_memory_type(
+  type_filter: u32,
+  required_properties: vk::MemoryPropertyFlags,
+  mem_properties: vk::PhysicalDeviceMemoryProperties,
+) -> RenderResult<u32> {
+  for (i, memory_type) in mem_properties.memory_types[..mem_properties.memory_type_count as usize]
+    .iter()
+    .enumerate()
+  {
+    // check each bit in type_filter for support
+    if (type_filter & (1 << i)) > 0 && memory_type.property_flags.contains(required_properties) {
+      return Ok(i as u32);
+    }
+  }
+
+  Err(RenderError::NoSuitableMemoryType {
+    type_filter,
+    required: required_properties,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const MIB: u64 = 1024 * 1024;
+
+  #[test]
+  fn smaller_blocks_are_tried_down_to_the_allocation() {
+    assert_eq!(
+      block_sizes(MIB),
+      [
+        (64 * MIB, false),
+        (32 * MIB, false),
+        (16 * MIB, false),
+        (8 * MIB, false),
+        (4 * MIB, false),
+        (MIB, true)
+      ]
+    );
+    assert_eq!(
+      block_sizes(20 * MIB),
+      [(64 * MIB, false), (32 * MIB, false), (20 * MIB, true)]
+    );
+  }
+
+  #[test]
+  fn big_allocations_only_get_their_own_block() {
+    assert_eq!(block_sizes(40 * MIB), [(40 * MIB, true)]);
+  }
+}
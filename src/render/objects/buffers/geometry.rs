@@ -0,0 +1,164 @@
+use std::ptr;
+
+use ash::vk;
+
+use crate::render::{
+  error::RenderResult,
+  models::Models,
+  objects::{
+    allocator::{Allocation, Allocator},
+    uploads::{StagingBuffer, UploadDst, UploadOperation},
+    ColorVertex, QueueFamilyIndices, TexVertex,
+  },
+};
+
+use super::{
+  create_buffer_with_sharing_exclusive, INDEX_DST_USAGE, LOCAL_MEMORY_PROPERTY_FLAGS,
+  VERTEX_DST_USAGE,
+};
+
+pub struct Vertex {
+  pub buffer: vk::Buffer,
+  // internal data offsets
+  pub color_offset: u64,
+  pub tex_offset: u64,
+}
+
+pub struct Index {
+  pub buffer: vk::Buffer,
+  // internal data offsets
+  pub color_offset: u64,
+  pub tex_offset: u64,
+}
+
+// vertex and index data of every loaded model, replaced as a whole when models finish loading
+// ------- Buffer Layout -------
+// [  Vertex  ] [  Index   ]
+// [Color][Tex] [Color][Tex]
+pub struct ModelGeometry {
+  // vertex and index allocations
+  allocations: [Allocation; 2],
+  pub vertex: Vertex,
+  pub index: Index,
+}
+
+impl ModelGeometry {
+  // creates the buffers along with the operations that upload the models into them
+  // the buffers can't be used until these complete
+  pub fn stage(
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    queue_families: &QueueFamilyIndices,
+    models: &Models,
+  ) -> RenderResult<(Self, Vec<UploadOperation>)> {
+    let color_vertex_size = (std::mem::size_of::<ColorVertex>() * models.vertices.len()) as u64;
+    let tex_vertex_size = (std::mem::size_of::<TexVertex>() * models.tex_vertices.len()) as u64;
+    let vertex_size = color_vertex_size + tex_vertex_size;
+
+    let color_index_size = (std::mem::size_of::<u16>() * models.indices.len()) as u64;
+    let tex_index_size = (std::mem::size_of::<u16>() * models.tex_indices.len()) as u64;
+    let index_size = color_index_size + tex_index_size;
+
+    let dst_buffers = [
+      (vertex_size, VERTEX_DST_USAGE),
+      (index_size, INDEX_DST_USAGE),
+    ]
+    .into_iter()
+    .map(|(size, usage)| {
+      let buffer = create_buffer_with_sharing_exclusive(device, size, usage)?;
+      let allocation = allocator.bind_buffer(device, buffer, LOCAL_MEMORY_PROPERTY_FLAGS)?;
+      Ok((buffer, allocation))
+    })
+    .collect::<RenderResult<Vec<_>>>()?;
+    let vertex_staging = StagingBuffer::uninit(device, allocator, vertex_size)?;
+    let index_staging = StagingBuffer::uninit(device, allocator, index_size)?;
+
+    let vertex = Vertex {
+      buffer: dst_buffers[0].0,
+      color_offset: 0,
+      tex_offset: color_vertex_size,
+    };
+    let index = Index {
+      buffer: dst_buffers[1].0,
+      color_offset: 0,
+      tex_offset: color_index_size,
+    };
+
+    // copy data into the staging buffers (host memory)
+    unsafe {
+      let vertex_ptr = vertex_staging.mapped_ptr();
+      let index_ptr = index_staging.mapped_ptr();
+
+      ptr::copy_nonoverlapping(
+        models.vertices.as_ptr() as *const u8,
+        vertex_ptr.byte_add(vertex.color_offset as usize),
+        color_vertex_size as usize,
+      );
+      ptr::copy_nonoverlapping(
+        models.tex_vertices.as_ptr() as *const u8,
+        vertex_ptr.byte_add(vertex.tex_offset as usize),
+        tex_vertex_size as usize,
+      );
+
+      ptr::copy_nonoverlapping(
+        models.indices.as_ptr() as *const u8,
+        index_ptr.byte_add(index.color_offset as usize),
+        color_index_size as usize,
+      );
+      ptr::copy_nonoverlapping(
+        models.tex_indices.as_ptr() as *const u8,
+        index_ptr.byte_add(index.tex_offset as usize),
+        tex_index_size as usize,
+      );
+    }
+
+    // both buffers are first read by the graphics queue when drawing
+    let operations = [
+      (
+        vertex_staging,
+        vertex.buffer,
+        vertex_size,
+        vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+      ),
+      (
+        index_staging,
+        index.buffer,
+        index_size,
+        vk::AccessFlags::INDEX_READ,
+      ),
+    ]
+    .into_iter()
+    .map(|(staging, buffer, size, dst_access)| UploadOperation {
+      staging,
+      dst: UploadDst::Buffer {
+        buffer,
+        regions: vec![vk::BufferCopy {
+          src_offset: 0,
+          dst_offset: 0,
+          size,
+        }],
+      },
+      dst_family: queue_families.graphics,
+      dst_stage: vk::PipelineStageFlags::VERTEX_INPUT,
+      dst_access,
+    })
+    .collect();
+
+    Ok((
+      Self {
+        allocations: [dst_buffers[0].1, dst_buffers[1].1],
+        vertex,
+        index,
+      },
+      operations,
+    ))
+  }
+
+  pub unsafe fn destroy_self(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+    device.destroy_buffer(self.vertex.buffer, None);
+    device.destroy_buffer(self.index.buffer, None);
+    for allocation in self.allocations {
+      allocator.free(device, allocation);
+    }
+  }
+}
@@ -1,84 +1,148 @@
 use ash::vk;
-use std::mem::MaybeUninit;
+use log::debug;
 
 use crate::render::{
-  objects::{MatrixInstance, QueueFamilyIndices},
-  sync::FRAMES_IN_FLIGHT,
-  utility,
+  error::RenderResult,
+  objects::{
+    allocator::{Allocation, Allocator},
+    IndirectDraw, MatrixInstance, QueueFamilyIndices,
+  },
 };
 
 use super::{
-  allocate_vk_buffers, create_buffer_with_sharing_exclusive, HOST_MEMORY_PROPERTY_FLAGS,
+  create_buffer, create_buffer_with_sharing_exclusive, DRAW_USAGE, HOST_MEMORY_PROPERTY_FLAGS,
   STORAGE_SRC_USAGE,
 };
 
-pub struct HostWritableMemory {
-  memory: vk::DeviceMemory,
-  pub inst: [(vk::Buffer, u64); FRAMES_IN_FLIGHT],
+// dynamic instance data written by the host each frame
+pub struct HostInstBuffer {
+  allocation: Allocation,
+  pub buffer: vk::Buffer,
+  // in instances
+  pub capacity: u64,
 }
 
-impl HostWritableMemory {
-  pub fn new(
-    device: &ash::Device,
-    memory_properties: vk::PhysicalDeviceMemoryProperties,
-    queue_families: &QueueFamilyIndices,
-    max_dyn_inst_count: u64,
-  ) -> Self {
-    let inst_size = std::mem::size_of::<MatrixInstance>() as u64 * max_dyn_inst_count;
+impl HostInstBuffer {
+  fn new(device: &ash::Device, allocator: &mut Allocator, capacity: u64) -> RenderResult<Self> {
+    let size = std::mem::size_of::<MatrixInstance>() as u64 * capacity;
+    // only used by compute
+    let buffer = create_buffer_with_sharing_exclusive(device, size, STORAGE_SRC_USAGE)?;
+    let allocation = allocator.bind_buffer(device, buffer, HOST_MEMORY_PROPERTY_FLAGS)?;
+    Ok(Self {
+      allocation,
+      buffer,
+      capacity,
+    })
+  }
 
-    let instance_usages: Vec<_> = std::iter::repeat((inst_size, STORAGE_SRC_USAGE))
-      .take(FRAMES_IN_FLIGHT)
-      .collect();
+  unsafe fn destroy_self(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+    device.destroy_buffer(self.buffer, None);
+    allocator.free(device, self.allocation);
+  }
+}
 
-    let buffers = instance_usages
-      .into_iter()
-      .map(|(size, usage)| {
-        (
-          size,
-          create_buffer_with_sharing_exclusive(device, size, usage, queue_families.compute),
-        )
-      })
-      .collect();
+// indirect draws written by the host, with instance counts filled in by the compute shader
+pub struct HostDrawBuffer {
+  allocation: Allocation,
+  pub buffer: vk::Buffer,
+  // in draws
+  pub capacity: u64,
+}
 
-    let (memory, _memory_size, offsets) = allocate_vk_buffers(
-      device,
-      &buffers,
-      memory_properties,
-      HOST_MEMORY_PROPERTY_FLAGS,
-    );
+impl HostDrawBuffer {
+  fn new(
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    queue_families: &QueueFamilyIndices,
+    capacity: u64,
+  ) -> RenderResult<Self> {
+    let size = IndirectDraw::offset(capacity.max(1) as usize);
+    // written by compute, read by graphics
+    let queue_indices = [queue_families.graphics, queue_families.compute];
+    let buffer = create_buffer(device, size, DRAW_USAGE, &queue_indices)?;
+    let allocation = allocator.bind_buffer(device, buffer, HOST_MEMORY_PROPERTY_FLAGS)?;
+    Ok(Self {
+      allocation,
+      buffer,
+      capacity,
+    })
+  }
+
+  unsafe fn destroy_self(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+    device.destroy_buffer(self.buffer, None);
+    allocator.free(device, self.allocation);
+  }
+}
 
-    let mut instance_iter = buffers
-      .into_iter()
-      .map(|(_, buffer)| buffer)
-      .zip(offsets.into_iter());
-    let inst = utility::iter_into_array!(instance_iter, FRAMES_IN_FLIGHT);
+// each frame in flight has its own allocation, so it can be resized while others are in use
+pub struct HostWritableMemory {
+  pub inst: Vec<HostInstBuffer>,
+  pub draws: Vec<HostDrawBuffer>,
+}
 
-    Self { memory, inst }
+impl HostWritableMemory {
+  pub fn new(
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    queue_families: &QueueFamilyIndices,
+    dyn_inst_capacity: u64,
+    draw_capacity: u64,
+    frames_in_flight: usize,
+  ) -> RenderResult<Self> {
+    let inst = (0..frames_in_flight)
+      .map(|_| HostInstBuffer::new(device, allocator, dyn_inst_capacity))
+      .collect::<RenderResult<_>>()?;
+    let draws = (0..frames_in_flight)
+      .map(|_| HostDrawBuffer::new(device, allocator, queue_families, draw_capacity))
+      .collect::<RenderResult<_>>()?;
+    Ok(Self { inst, draws })
   }
 
-  pub unsafe fn write_instance(
+  // frame i should not be in use by the device
+  pub unsafe fn resize(
     &mut self,
     i: usize,
     device: &ash::Device,
-    data: &Vec<MatrixInstance>,
-  ) {
+    allocator: &mut Allocator,
+    dyn_inst_capacity: u64,
+  ) -> RenderResult<()> {
+    debug!(
+      "Resizing host instance buffer {} from {} to {} instances",
+      i, self.inst[i].capacity, dyn_inst_capacity
+    );
+    // the old buffer is kept if the new one can't be created
+    let inst = HostInstBuffer::new(device, allocator, dyn_inst_capacity)?;
+    std::mem::replace(&mut self.inst[i], inst).destroy_self(device, allocator);
+    Ok(())
+  }
+
+  pub unsafe fn write_instance(&mut self, i: usize, data: &[MatrixInstance]) {
+    assert!(data.len() as u64 <= self.inst[i].capacity);
     // writes instance data from the start of the buffer
-    let data_ptr = device
-      .map_memory(
-        self.memory,
-        self.inst[i].1,
-        (std::mem::size_of::<MatrixInstance>() * data.len()) as u64,
-        vk::MemoryMapFlags::empty(),
-      )
-      .expect("Failed to map memory") as *mut MatrixInstance;
+    let data_ptr = self.inst[i].allocation.mapped_ptr() as *mut MatrixInstance;
     data_ptr.copy_from_nonoverlapping(data.as_ptr(), data.len());
-    device.unmap_memory(self.memory);
   }
 
-  pub unsafe fn destroy_self(&mut self, device: &ash::Device) {
-    for (buffer, _) in self.inst.iter_mut() {
-      device.destroy_buffer(*buffer, None);
+  pub unsafe fn write_draws(&mut self, i: usize, draws: &[IndirectDraw]) {
+    assert!(draws.len() as u64 <= self.draws[i].capacity);
+    let data_ptr = self.draws[i].allocation.mapped_ptr() as *mut IndirectDraw;
+    data_ptr.copy_from_nonoverlapping(draws.as_ptr(), draws.len());
+  }
+
+  // draws as left by the compute shader, frame i should have finished
+  #[cfg(test)]
+  pub unsafe fn read_draws(&self, i: usize, count: usize) -> Vec<IndirectDraw> {
+    assert!(count as u64 <= self.draws[i].capacity);
+    let data_ptr = self.draws[i].allocation.mapped_ptr() as *const IndirectDraw;
+    std::slice::from_raw_parts(data_ptr, count).to_vec()
+  }
+
+  pub unsafe fn destroy_self(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+    for inst in self.inst.iter_mut() {
+      inst.destroy_self(device, allocator);
+    }
+    for draws in self.draws.iter_mut() {
+      draws.destroy_self(device, allocator);
     }
-    device.free_memory(self.memory, None);
   }
 }
@@ -1,67 +1,114 @@
 use ash::vk;
 use log::debug;
-use std::mem::MaybeUninit;
 
 use crate::render::{
-  objects::{MatrixInstance, QueueFamilyIndices},
-  sync::FRAMES_IN_FLIGHT,
-  utility,
+  error::RenderResult,
+  objects::{
+    allocator::{Allocation, Allocator},
+    QueueFamilyIndices, RenderInstance,
+  },
 };
 
-use super::{
-  allocate_vk_buffers, create_buffer, LOCAL_MEMORY_PROPERTY_FLAGS, VERTEX_STORAGE_DST_USAGE,
-};
+use super::{create_buffer, LOCAL_MEMORY_PROPERTY_FLAGS, VERTEX_STORAGE_DST_USAGE};
+
+// transformed instances, static ones first followed by the dynamic ones
+pub struct LocalInstBuffer {
+  allocation: Allocation,
+  pub buffer: vk::Buffer,
+  // dynamic instance capacity
+  pub dyn_capacity: u64,
+}
+
+impl LocalInstBuffer {
+  fn new(
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    queue_families: &QueueFamilyIndices,
+    static_inst_count: u64,
+    dyn_capacity: u64,
+  ) -> RenderResult<Self> {
+    let size = std::mem::size_of::<RenderInstance>() as u64 * (static_inst_count + dyn_capacity);
+    // write by compute, read by graphics
+    let queue_indices = [queue_families.graphics, queue_families.compute];
+    let buffer = create_buffer(device, size, VERTEX_STORAGE_DST_USAGE, &queue_indices)?;
+    let allocation = allocator.bind_buffer(device, buffer, LOCAL_MEMORY_PROPERTY_FLAGS)?;
+    Ok(Self {
+      allocation,
+      buffer,
+      dyn_capacity,
+    })
+  }
+
+  unsafe fn destroy_self(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+    device.destroy_buffer(self.buffer, None);
+    allocator.free(device, self.allocation);
+  }
+}
 
+// each frame in flight has its own allocation, so it can be resized while others are in use
 pub struct LocalMemory {
-  memory: vk::DeviceMemory,
-  pub inst: [(vk::Buffer, u64); FRAMES_IN_FLIGHT],
+  static_inst_count: u64,
+  pub inst: Vec<LocalInstBuffer>,
 }
 
 impl LocalMemory {
   pub fn new(
     device: &ash::Device,
-    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    allocator: &mut Allocator,
     queue_families: &QueueFamilyIndices,
     static_inst_count: u64,
-    max_dyn_inst_count: u64,
-  ) -> Self {
+    dyn_inst_capacity: u64,
+    frames_in_flight: usize,
+  ) -> RenderResult<Self> {
     debug!(
       "Creating local instance buffer with {} static and {} dynamic count",
-      static_inst_count, max_dyn_inst_count
+      static_inst_count, dyn_inst_capacity
     );
-    let inst_size =
-      std::mem::size_of::<MatrixInstance>() as u64 * (static_inst_count + max_dyn_inst_count);
-
-    let inst_usages: Vec<_> = std::iter::repeat((inst_size, VERTEX_STORAGE_DST_USAGE))
-      .take(FRAMES_IN_FLIGHT)
-      .collect();
-    // write by compute, read by graphics
-    let queue_indices = [queue_families.graphics, queue_families.compute];
-    let buffers = inst_usages
-      .into_iter()
-      .map(|(size, usage)| (size, create_buffer(device, size, usage, &queue_indices)))
-      .collect();
+    let inst = (0..frames_in_flight)
+      .map(|_| {
+        LocalInstBuffer::new(
+          device,
+          allocator,
+          queue_families,
+          static_inst_count,
+          dyn_inst_capacity,
+        )
+      })
+      .collect::<RenderResult<_>>()?;
+    Ok(Self {
+      static_inst_count,
+      inst,
+    })
+  }
 
-    let (memory, _memory_size, offsets) = allocate_vk_buffers(
-      device,
-      &buffers,
-      memory_properties,
-      LOCAL_MEMORY_PROPERTY_FLAGS,
+  // frame i should not be in use by the device
+  pub unsafe fn resize(
+    &mut self,
+    i: usize,
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    queue_families: &QueueFamilyIndices,
+    dyn_inst_capacity: u64,
+  ) -> RenderResult<()> {
+    debug!(
+      "Resizing local instance buffer {} from {} to {} dynamic instances",
+      i, self.inst[i].dyn_capacity, dyn_inst_capacity
     );
-
-    let mut buffers_iter = buffers
-      .into_iter()
-      .map(|(_, buffer)| buffer)
-      .zip(offsets.into_iter());
-    let inst = utility::iter_into_array!(buffers_iter, FRAMES_IN_FLIGHT);
-
-    Self { memory, inst }
+    // the old buffer is kept if the new one can't be created
+    let inst = LocalInstBuffer::new(
+      device,
+      allocator,
+      queue_families,
+      self.static_inst_count,
+      dyn_inst_capacity,
+    )?;
+    std::mem::replace(&mut self.inst[i], inst).destroy_self(device, allocator);
+    Ok(())
   }
 
-  pub unsafe fn destroy_self(&mut self, device: &ash::Device) {
-    for (buffer, _) in self.inst.iter_mut() {
-      device.destroy_buffer(*buffer, None);
+  pub unsafe fn destroy_self(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+    for inst in self.inst.iter_mut() {
+      inst.destroy_self(device, allocator);
     }
-    device.free_memory(self.memory, None);
   }
 }
@@ -1,131 +1,64 @@
-use std::ptr;
-
 use ash::vk;
-use log::debug;
 
 use crate::{
   render::{
+    error::RenderResult,
     models::Models,
     objects::{
-      command_buffer_pools::CopyBufferOperation, ColorVertex, CommandBufferPools, InstProperties,
-      MatrixInstance, QueueFamilyIndices, Queues, TexVertex,
+      allocator::{Allocation, Allocator},
+      uploads::{StagingBuffer, UploadDst, UploadOperation, Uploader},
+      InstProperties, MatrixInstance, QueueFamilyIndices,
     },
   },
   static_scene::StaticScene,
 };
 
 use super::{
-  create_travel_buffers, HOST_MEMORY_PROPERTY_FLAGS, INDEX_DST_USAGE, LOCAL_MEMORY_PROPERTY_FLAGS,
-  STORAGE_USAGE, VERTEX_DST_USAGE,
+  create_buffer_with_sharing_exclusive, geometry::ModelGeometry, LOCAL_MEMORY_PROPERTY_FLAGS,
+  STORAGE_USAGE,
 };
 
-pub struct Vertex {
-  pub buffer: vk::Buffer,
-  pub mem_offset: u64,
-  // internal data offsets
-  pub color_offset: u64,
-  pub tex_offset: u64,
-}
-
-pub struct Index {
-  pub buffer: vk::Buffer,
-  pub mem_offset: u64,
-  // internal data offsets
-  pub color_offset: u64,
-  pub tex_offset: u64,
-}
-
 pub struct Inst {
   pub buffer: vk::Buffer,
-  pub mem_offset: u64,
   pub size: u64,
   pub count: u32,
   pub props: Vec<InstProperties>,
 }
 
 // holds model information and static objects
-// contains local memory, buffers and their offsets (in memory)
-// ------------ Memory Layout ------------
-// [  Vertex  ] [  Index   ] [   Instance    ]
-// [Color][Tex] [Color][Tex] [Instance Blocks]
+// each buffer has its own allocation
+// ------------ Buffer Layout ------------
+// [  Geometry  ] [   Instance    ]
+//                [Instance Blocks]
 pub struct LocalConstantMemory {
-  memory: vk::DeviceMemory,
-  pub vertex: Vertex,
-  pub index: Index,
+  pub geometry: ModelGeometry,
+  inst_allocation: Allocation,
   pub inst: Inst,
 }
 
 impl LocalConstantMemory {
   pub fn new(
     device: &ash::Device,
-    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    allocator: &mut Allocator,
     queue_families: &QueueFamilyIndices,
-    queues: &Queues,
-    command_pools: &mut CommandBufferPools,
+    uploader: &mut Uploader,
     models: &Models,
-  ) -> Self {
-    let scene = StaticScene::load(); // information about static (constant location, etc.) objects
-
-    // TODO: dummy values
-    let tex_vertices: Vec<TexVertex> = vec![TexVertex::default(); 100];
-    let tex_indices: Vec<u16> = vec![0; 100];
-
-    // create vulkan buffers
-    let color_vertex_size = (std::mem::size_of::<ColorVertex>() * models.vertices.len()) as u64;
-    let tex_vertex_size = (std::mem::size_of::<TexVertex>() * tex_vertices.len()) as u64;
-    let vertex_size = color_vertex_size + tex_vertex_size;
-
-    let color_index_size = (std::mem::size_of::<u16>() * models.indices.len()) as u64;
-    let tex_index_size = (std::mem::size_of::<u16>() * tex_indices.len()) as u64;
-    let index_size = color_index_size + tex_index_size;
-
-    let inst_size = (std::mem::size_of::<MatrixInstance>() * scene.total_obj_count) as u64;
-    let total_unallocated_size = vertex_size + index_size + inst_size;
-
-    let vk_buffers = create_travel_buffers(
-      device,
-      queue_families,
-      vec![
-        (
-          vertex_size,
-          vk::BufferUsageFlags::TRANSFER_SRC,
-          VERTEX_DST_USAGE,
-        ),
-        (
-          index_size,
-          vk::BufferUsageFlags::TRANSFER_SRC,
-          INDEX_DST_USAGE,
-        ),
-        (inst_size, vk::BufferUsageFlags::TRANSFER_SRC, STORAGE_USAGE),
-      ],
-    );
-
-    // allocate vulkan buffers
-    let src_buffers = vk_buffers
-      .iter()
-      .map(|&(size, source, _)| (size, source))
-      .collect();
-    let dst_buffers = vk_buffers
-      .into_iter()
-      .map(|(size, _, dst)| (size, dst))
-      .collect();
-    let (src_memory, src_size, src_offsets) = super::allocate_vk_buffers(
-      device,
-      &src_buffers,
-      memory_properties,
-      HOST_MEMORY_PROPERTY_FLAGS,
-    );
-    let (dst_memory, dst_size, dst_offsets) = super::allocate_vk_buffers(
-      device,
-      &dst_buffers,
-      memory_properties,
-      LOCAL_MEMORY_PROPERTY_FLAGS,
-    );
-
-    debug!(
-      "Buffer internal alignment wasted space: {} bytes",
-      dst_size - total_unallocated_size
-    );
+    scene: &StaticScene, // information about static (constant location, etc.) objects
+  ) -> RenderResult<Self> {
+    let (geometry, mut operations) =
+      ModelGeometry::stage(device, allocator, queue_families, models)?;
+
+    let inst_size = static_inst_size(scene.total_obj_count());
+    // read by compute
+    let inst_buffer = create_buffer_with_sharing_exclusive(device, inst_size, STORAGE_USAGE)?;
+    let inst_allocation =
+      match allocator.bind_buffer(device, inst_buffer, LOCAL_MEMORY_PROPERTY_FLAGS) {
+        Ok(allocation) => allocation,
+        Err(err) => {
+          unsafe { device.destroy_buffer(inst_buffer, None) };
+          return Err(err);
+        }
+      };
 
     let (vec, inst_model_indices) = scene.objects();
     let (inst_objs, inst_parts) = vec.deconstruct();
@@ -140,164 +73,82 @@ impl LocalConstantMemory {
       .collect();
     let inst_data: Vec<MatrixInstance> = inst_objs
       .into_iter()
-      .map(|obj| MatrixInstance::new(obj.ren().model().clone()))
+      .map(|obj| MatrixInstance::new(*obj.ren().model()))
       .collect();
 
-    // Some fields are repeated but whatever
-    let vertex_src = Vertex {
-      buffer: src_buffers[0].1,
-      mem_offset: src_offsets[0],
-      color_offset: 0,
-      tex_offset: color_vertex_size,
-    };
-    let vertex_dst = Vertex {
-      buffer: dst_buffers[0].1,
-      mem_offset: dst_offsets[0],
-      color_offset: 0,
-      tex_offset: color_vertex_size,
-    };
-    let index_src = Index {
-      buffer: src_buffers[1].1,
-      mem_offset: src_offsets[1],
-      color_offset: 0,
-      tex_offset: color_index_size,
-    };
-    let index_dst = Index {
-      buffer: dst_buffers[1].1,
-      mem_offset: dst_offsets[1],
-      color_offset: 0,
-      tex_offset: color_index_size,
-    };
-    let inst_src_offset = src_offsets[2];
-    let inst_dst = Inst {
-      buffer: dst_buffers[2].1,
-      mem_offset: dst_offsets[2],
+    let inst = Inst {
+      buffer: inst_buffer,
       size: inst_size,
       count: inst_data.len() as u32,
       props: inst_props,
     };
 
-    // copy data into the source buffers (host memory)
-    // because the source buffer is internally aligned and probably not continuous,
-    //    data is copied separately
-    println!("Starting buffer copy");
-    unsafe {
-      let mem_ptr = device
-        .map_memory(src_memory, 0, src_size, vk::MemoryMapFlags::empty())
-        .expect("Failed to map constant source memory") as *mut u8;
-
-      ptr::copy_nonoverlapping(
-        models.vertices.as_ptr() as *const u8,
-        mem_ptr.byte_add((vertex_src.mem_offset + vertex_src.color_offset) as usize) as *mut u8,
-        color_vertex_size as usize,
-      );
-      ptr::copy_nonoverlapping(
-        tex_vertices.as_ptr() as *const u8,
-        mem_ptr.byte_add((vertex_src.mem_offset + vertex_src.tex_offset) as usize) as *mut u8,
-        tex_vertex_size as usize,
-      );
-
-      ptr::copy_nonoverlapping(
-        models.indices.as_ptr() as *const u8,
-        mem_ptr.byte_add((index_src.mem_offset + index_src.color_offset) as usize) as *mut u8,
-        color_index_size as usize,
-      );
-      ptr::copy_nonoverlapping(
-        tex_indices.as_ptr() as *const u8,
-        mem_ptr.byte_add((index_src.mem_offset + index_src.tex_offset) as usize) as *mut u8,
-        tex_index_size as usize,
-      );
-
-      ptr::copy_nonoverlapping(
-        inst_data.as_ptr() as *const u8,
-        mem_ptr.byte_add(inst_src_offset as usize) as *mut u8,
-        inst_size as usize,
-      );
-
-      device.unmap_memory(src_memory);
-    }
-
-    // copy data from source buffers into destination and deallocate the source memory
-    // instance destination buffer will be used as source for computations
-    unsafe {
-      let operations: Vec<CopyBufferOperation> = src_buffers
-        .iter()
-        .zip(dst_buffers.iter())
-        .map(|((src_size, src), (_dst_size, dst))| CopyBufferOperation {
-          source_buffer: *src,
-          dest_buffer: *dst,
-          copy_regions: vec![vk::BufferCopy {
+    // the staging memory is freed once the upload completes
+    // without static objects there is nothing to copy, as buffers and copies can't be empty
+    if !inst_data.is_empty() {
+      let inst_bytes = unsafe {
+        std::slice::from_raw_parts(
+          inst_data.as_ptr() as *const u8,
+          std::mem::size_of_val(inst_data.as_slice()),
+        )
+      };
+      operations.push(UploadOperation {
+        staging: StagingBuffer::new(device, allocator, inst_bytes)?,
+        dst: UploadDst::Buffer {
+          buffer: inst_buffer,
+          regions: vec![vk::BufferCopy {
             src_offset: 0,
             dst_offset: 0,
-            size: *src_size,
+            size: inst_bytes.len() as u64,
           }],
-        })
-        .collect();
-      command_pools
-        .transfer
-        .record_copy_buffers(device, &operations);
+        },
+        dst_family: queue_families.compute,
+        dst_stage: vk::PipelineStageFlags::COMPUTE_SHADER,
+        dst_access: vk::AccessFlags::SHADER_READ,
+      });
     }
 
-    // fence copying has finished
-    let finished = {
-      let create_info = vk::FenceCreateInfo {
-        s_type: vk::StructureType::FENCE_CREATE_INFO,
-        p_next: ptr::null(),
-        flags: vk::FenceCreateFlags::empty(),
-      };
-      unsafe {
-        device
-          .create_fence(&create_info, None)
-          .expect("Failed to create fence")
-      }
-    };
-
-    let command_buffer = command_pools.transfer.copy_buffer;
-    let submit_infos = [vk::SubmitInfo {
-      s_type: vk::StructureType::SUBMIT_INFO,
-      p_next: ptr::null(),
-      wait_semaphore_count: 0,
-      p_wait_semaphores: ptr::null(),
-      p_wait_dst_stage_mask: ptr::null(),
-      command_buffer_count: 1,
-      p_command_buffers: &command_buffer,
-      signal_semaphore_count: 0,
-      p_signal_semaphores: ptr::null(),
-    }];
-
+    // the static scene is needed for the first frame
     unsafe {
-      device
-        .queue_submit(
-          if let Some(transfer) = queues.transfer {
-            transfer
-          } else {
-            queues.graphics
-          },
-          &submit_infos,
-          finished,
-        )
-        .expect("Failed submit queue for copying initial buffers");
-      device.wait_for_fences(&[finished], true, u64::MAX).unwrap();
-
-      device.destroy_fence(finished, None);
-      for (_, src) in src_buffers {
-        device.destroy_buffer(src, None);
-      }
-      device.free_memory(src_memory, None);
+      let upload = uploader.submit(device, allocator, operations)?;
+      uploader.wait(device, allocator, upload)?;
     }
 
-    Self {
-      memory: dst_memory,
-      vertex: vertex_dst,
-      index: index_dst,
-      inst: inst_dst,
-    }
+    Ok(Self {
+      geometry,
+      inst_allocation,
+      inst,
+    })
   }
 
-  pub unsafe fn destroy_self(&mut self, device: &ash::Device) {
-    device.destroy_buffer(self.vertex.buffer, None);
-    device.destroy_buffer(self.index.buffer, None);
+  pub unsafe fn destroy_self(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+    self.geometry.destroy_self(device, allocator);
     device.destroy_buffer(self.inst.buffer, None);
-    device.free_memory(self.memory, None);
+    allocator.free(device, self.inst_allocation);
+  }
+}
+
+// the buffer always has room for at least one instance, so that it and its descriptor range are
+// never empty
+fn static_inst_size(inst_count: usize) -> u64 {
+  (std::mem::size_of::<MatrixInstance>() * inst_count.max(1)) as u64
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn empty_scene_reserves_one_instance() {
+    let empty = StaticScene {
+      objects: Vec::new(),
+    };
+    let (objects, model_indices) = empty.objects();
+    assert_eq!(objects.len(), 0);
+    assert!(model_indices.is_empty());
+
+    let inst_size = std::mem::size_of::<MatrixInstance>() as u64;
+    assert_eq!(static_inst_size(empty.total_obj_count()), inst_size);
+    assert_eq!(static_inst_size(3), 3 * inst_size);
   }
 }
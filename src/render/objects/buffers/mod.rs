@@ -1,3 +1,4 @@
+mod geometry;
 mod host_writable;
 mod local;
 mod local_constant;
@@ -7,13 +8,23 @@ use std::ptr;
 use ash::vk;
 use log::debug;
 
-use crate::render::Models;
+use crate::{
+  render::{
+    error::{RenderResult, VkResultExt},
+    Models,
+  },
+  static_scene::StaticScene,
+};
 
 use self::{
   host_writable::HostWritableMemory, local::LocalMemory, local_constant::LocalConstantMemory,
 };
 
-use super::{CommandBufferPools, MatrixInstance, QueueFamilyIndices, Queues};
+pub use geometry::ModelGeometry;
+
+use super::{
+  allocator::Allocator, uploads::Uploader, IndirectDraw, MatrixInstance, QueueFamilyIndices,
+};
 
 macro_rules! const_flag_bitor {
     ($t:ty, $x:expr, $($y:expr),+) => {
@@ -53,6 +64,11 @@ pub const STORAGE_USAGE: vk::BufferUsageFlags = buffer_usage!(
   vk::BufferUsageFlags::STORAGE_BUFFER
 );
 
+pub const DRAW_USAGE: vk::BufferUsageFlags = buffer_usage!(
+  vk::BufferUsageFlags::INDIRECT_BUFFER,
+  vk::BufferUsageFlags::STORAGE_BUFFER
+);
+
 pub const INDEX_DST_USAGE: vk::BufferUsageFlags = buffer_usage!(
   vk::BufferUsageFlags::INDEX_BUFFER,
   vk::BufferUsageFlags::TRANSFER_DST
@@ -68,64 +84,23 @@ pub const LOCAL_MEMORY_PROPERTY_FLAGS: vk::MemoryPropertyFlags = memory_property
   vk::MemoryPropertyFlags::HOST_COHERENT
 );
 
-// returns memory, memory size and offset of each buffer
-fn allocate_vk_buffers(
-  device: &ash::Device,
-  // (buffer data size, buffer)
-  buffers: &Vec<(u64, vk::Buffer)>,
-  mem_properties: vk::PhysicalDeviceMemoryProperties,
-  required_mem_flags: vk::MemoryPropertyFlags,
-) -> (vk::DeviceMemory, u64, Vec<u64>) {
-  let mut buffer_requirements_bits = 0;
-  let mut alignment = 0;
-  let mut full_sizes = Vec::with_capacity(buffers.len());
-  for (_, buffer) in buffers.iter() {
-    let mem_requirements = unsafe { device.get_buffer_memory_requirements(*buffer) };
-    buffer_requirements_bits |= mem_requirements.memory_type_bits;
-
-    // the specification guarantees that the alignment is a power of 2
-    #[cfg(debug_assertions)]
-    assert!(
-      mem_requirements.alignment > 0
-        && (mem_requirements.alignment & (mem_requirements.alignment - 1)) == 0
-    );
-    alignment = alignment.max(mem_requirements.alignment);
+// minimum amount of dynamic instances each frame's buffers can hold
+const MIN_DYN_INST_CAPACITY: u64 = 16;
 
-    full_sizes.push(mem_requirements.size);
-  }
-  // align each internal buffer
-  let mut total_size = 0;
-  let mut offsets = Vec::with_capacity(buffers.len());
-  for size in full_sizes.iter() {
-    let size = size + alignment - (size % alignment);
-    offsets.push(total_size);
-    total_size += size;
-  }
-
-  let memory_type = find_memory_type(buffer_requirements_bits, required_mem_flags, mem_properties);
-
-  let allocate_info = vk::MemoryAllocateInfo {
-    s_type: vk::StructureType::MEMORY_ALLOCATE_INFO,
-    p_next: ptr::null(),
-    allocation_size: total_size,
-    memory_type_index: memory_type,
-  };
-
-  debug!("Allocating buffer memory");
-  let buffer_memory = unsafe {
-    device
-      .allocate_memory(&allocate_info, None)
-      .expect("Failed to allocate vertex buffer memory")
-  };
-  for ((_size, buffer), offset) in buffers.iter().zip(offsets.iter()) {
-    unsafe {
-      device
-        .bind_buffer_memory(*buffer, buffer_memory, *offset)
-        .expect("Failed to bind buffer to its memory");
-    }
+// returns the capacity the dynamic instance buffers should be resized to in order to hold count
+// instances, or None if the current capacity should be kept
+// buffers grow to the next power of 2 and only shrink once less than a quarter is used, so that
+// counts changing around a boundary don't cause a reallocation every frame
+fn dyn_inst_capacity(current: u64, count: u64) -> Option<u64> {
+  let wanted = count.max(MIN_DYN_INST_CAPACITY).next_power_of_two();
+  if count > current
+    || current < MIN_DYN_INST_CAPACITY
+    || (wanted < current && count <= current / 4)
+  {
+    Some(wanted)
+  } else {
+    None
   }
-
-  (buffer_memory, total_size, offsets)
 }
 
 pub struct Buffers {
@@ -135,72 +110,99 @@ pub struct Buffers {
 }
 
 impl Buffers {
+  #[allow(clippy::too_many_arguments)]
   pub fn create(
-    instance: &ash::Instance,
     device: &ash::Device,
-    physical_device: vk::PhysicalDevice,
+    allocator: &mut Allocator,
     queue_families: &QueueFamilyIndices,
-    queues: &Queues,
-    command_pools: &mut CommandBufferPools,
+    uploader: &mut Uploader,
     models: &Models,
-    max_dyn_inst_count: u64,
-  ) -> Self {
-    let memory_properties =
-      unsafe { instance.get_physical_device_memory_properties(physical_device) };
+    scene: &StaticScene,
+    initial_dyn_inst_count: u64,
+    frames_in_flight: usize,
+  ) -> RenderResult<Self> {
+    let dyn_inst_capacity = dyn_inst_capacity(0, initial_dyn_inst_count)
+      .expect("Initial capacity should always be computed");
     debug!("Allocating constant memory");
-    let local_constant = LocalConstantMemory::new(
-      device,
-      memory_properties,
-      queue_families,
-      queues,
-      command_pools,
-      models,
-    );
+    let local_constant =
+      LocalConstantMemory::new(device, allocator, queue_families, uploader, models, scene)?;
     debug!("Allocating host memory");
+    // dynamic objects are drawn with at most one draw per model
+    let draw_capacity = local_constant.inst.props.len() as u64 + models.handles().count() as u64;
     let host_writable = HostWritableMemory::new(
       device,
-      memory_properties,
+      allocator,
       queue_families,
-      max_dyn_inst_count,
-    );
+      dyn_inst_capacity,
+      draw_capacity,
+      frames_in_flight,
+    )?;
     debug!("Allocating local memory");
     let local = LocalMemory::new(
       device,
-      memory_properties,
+      allocator,
       queue_families,
       local_constant.inst.count as u64,
-      max_dyn_inst_count,
-    );
+      dyn_inst_capacity,
+      frames_in_flight,
+    )?;
 
-    Self {
+    Ok(Self {
       local_constant,
       host_writable,
       local,
-    }
+    })
   }
 
-  pub unsafe fn update_instance_data(
+  // makes sure the dynamic instance buffers of frame i can hold count instances
+  // returns true if they were reallocated, in which case descriptor sets referencing them should be
+  // updated
+  // frame i should not be in use by the device
+  pub unsafe fn reserve_dyn_instances(
     &mut self,
     i: usize,
     device: &ash::Device,
-    data: &Vec<MatrixInstance>,
-  ) {
-    self.host_writable.write_instance(i, device, data);
+    allocator: &mut Allocator,
+    queue_families: &QueueFamilyIndices,
+    count: u64,
+  ) -> RenderResult<bool> {
+    // both buffers are resized again if only one of them could be before
+    let current = self.host_writable.inst[i]
+      .capacity
+      .min(self.local.inst[i].dyn_capacity);
+    match dyn_inst_capacity(current, count) {
+      Some(capacity) => {
+        self.host_writable.resize(i, device, allocator, capacity)?;
+        self
+          .local
+          .resize(i, device, allocator, queue_families, capacity)?;
+        Ok(true)
+      }
+      None => Ok(false),
+    }
+  }
+
+  pub unsafe fn update_instance_data(&mut self, i: usize, data: &[MatrixInstance]) {
+    self.host_writable.write_instance(i, data);
+  }
+
+  pub unsafe fn update_draws(&mut self, i: usize, draws: &[IndirectDraw]) {
+    self.host_writable.write_draws(i, draws);
   }
 
-  pub unsafe fn destroy_self(&mut self, device: &ash::Device) {
-    self.local_constant.destroy_self(device);
-    self.host_writable.destroy_self(device);
-    self.local.destroy_self(device);
+  pub unsafe fn destroy_self(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+    self.local_constant.destroy_self(device, allocator);
+    self.host_writable.destroy_self(device, allocator);
+    self.local.destroy_self(device, allocator);
   }
 }
 
-fn create_buffer_with_sharing_exclusive(
+// the buffer can only be accessed by one queue family at a time, other families have to acquire it
+pub(super) fn create_buffer_with_sharing_exclusive(
   device: &ash::Device,
   size: u64,
   usage: vk::BufferUsageFlags,
-  queue_family: u32,
-) -> vk::Buffer {
+) -> RenderResult<vk::Buffer> {
   let vertex_buffer_create_info = vk::BufferCreateInfo {
     s_type: vk::StructureType::BUFFER_CREATE_INFO,
     p_next: ptr::null(),
@@ -208,14 +210,15 @@ fn create_buffer_with_sharing_exclusive(
     size,
     usage,
     sharing_mode: vk::SharingMode::EXCLUSIVE,
-    queue_family_index_count: queue_family,
+    // queue family indices are ignored with exclusive sharing
+    queue_family_index_count: 0,
     p_queue_family_indices: ptr::null(),
   };
 
   unsafe {
     device
       .create_buffer(&vertex_buffer_create_info, None)
-      .expect("failed to create buffer")
+      .context("Failed to create buffer")
   }
 }
 
@@ -224,69 +227,54 @@ fn create_buffer(
   size: u64,
   usage: vk::BufferUsageFlags,
   queue_family_indices: &[u32],
-) -> vk::Buffer {
+) -> RenderResult<vk::Buffer> {
   assert!(size > 0);
+  // concurrent sharing requires distinct families
+  let mut unique_indices = queue_family_indices.to_vec();
+  unique_indices.sort();
+  unique_indices.dedup();
+  let sharing_mode = if unique_indices.len() > 1 {
+    vk::SharingMode::CONCURRENT
+  } else {
+    vk::SharingMode::EXCLUSIVE
+  };
   let vertex_buffer_create_info = vk::BufferCreateInfo {
     s_type: vk::StructureType::BUFFER_CREATE_INFO,
     p_next: ptr::null(),
     flags: vk::BufferCreateFlags::empty(),
     size,
     usage,
-    sharing_mode: vk::SharingMode::CONCURRENT,
-    queue_family_index_count: queue_family_indices.len() as u32,
-    p_queue_family_indices: queue_family_indices.as_ptr(),
+    sharing_mode,
+    queue_family_index_count: unique_indices.len() as u32,
+    p_queue_family_indices: unique_indices.as_ptr(),
   };
 
   unsafe {
     device
       .create_buffer(&vertex_buffer_create_info, None)
-      .expect("failed to create buffer")
+      .context("Failed to create buffer")
   }
 }
 
-// creates source and destination buffers from size and buffer usage
-fn create_travel_buffers(
-  device: &ash::Device,
-  queue_families: &QueueFamilyIndices,
-  data: Vec<(u64, vk::BufferUsageFlags, vk::BufferUsageFlags)>,
-) -> Vec<(u64, vk::Buffer, vk::Buffer)> {
-  if let Some(transfer) = queue_families.transfer {
-    let queue_indices = [queue_families.graphics, transfer];
-    data
-      .into_iter()
-      .map(|(size, source_usage, dst_usage)| {
-        (
-          size,
-          create_buffer(device, size, source_usage, &queue_indices),
-          create_buffer(device, size, dst_usage, &queue_indices),
-        )
-      })
-      .collect()
-  } else {
-    data
-      .into_iter()
-      .map(|(size, source_usage, dst_usage)| {
-        (
-          size,
-          create_buffer_with_sharing_exclusive(device, size, source_usage, queue_families.graphics),
-          create_buffer_with_sharing_exclusive(device, size, dst_usage, queue_families.graphics),
-        )
-      })
-      .collect()
-  }
-}
+#[cfg(test)]
+mod tests {
+  use super::*;
 
-fn find_memory_type(
-  type_filter: u32,
-  required_properties: vk::MemoryPropertyFlags,
-  mem_properties: vk::PhysicalDeviceMemoryProperties,
-) -> u32 {
-  for (i, memory_type) in mem_properties.memory_types.iter().enumerate() {
-    // check each bit in type_filter for support
-    if (type_filter & (1 << i)) > 0 && memory_type.property_flags.contains(required_properties) {
-      return i as u32;
-    }
+  #[test]
+  fn dyn_inst_capacity_grows_to_powers_of_two() {
+    assert_eq!(dyn_inst_capacity(0, 0), Some(MIN_DYN_INST_CAPACITY));
+    assert_eq!(dyn_inst_capacity(0, 3), Some(MIN_DYN_INST_CAPACITY));
+    assert_eq!(dyn_inst_capacity(16, 16), None);
+    assert_eq!(dyn_inst_capacity(16, 17), Some(32));
+    assert_eq!(dyn_inst_capacity(32, 100), Some(128));
   }
 
-  panic!("failed to find suitable memory type");
+  #[test]
+  fn dyn_inst_capacity_shrinks_with_hysteresis() {
+    assert_eq!(dyn_inst_capacity(32, 16), None);
+    assert_eq!(dyn_inst_capacity(128, 33), None);
+    assert_eq!(dyn_inst_capacity(128, 32), Some(32));
+    assert_eq!(dyn_inst_capacity(128, 0), Some(MIN_DYN_INST_CAPACITY));
+    assert_eq!(dyn_inst_capacity(MIN_DYN_INST_CAPACITY, 0), None);
+  }
 }
@@ -3,28 +3,37 @@ use std::{ops::BitOr, ptr};
 use ash::vk;
 
 use crate::render::{
-  models::ModelProperties,
-  objects::{Buffers, InstProperties, Pipelines, QueueFamilyIndices},
+  error::{RenderResult, VkResultExt},
+  objects::{Buffers, IndirectDraw, Pipelines, QueueFamilyIndices},
 };
 
 pub struct MainCommandBufferPool {
   pool: vk::CommandPool,
   command_buffers: Vec<vk::CommandBuffer>,
+  pub capture: vk::CommandBuffer,
 }
 
 impl MainCommandBufferPool {
-  pub fn create(device: &ash::Device, queue_families: &QueueFamilyIndices) -> Self {
+  pub fn create(
+    device: &ash::Device,
+    queue_families: &QueueFamilyIndices,
+    frames_in_flight: usize,
+  ) -> RenderResult<Self> {
     let flags =
       vk::CommandPoolCreateFlags::TRANSIENT.bitor(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
-    let pool = super::create_command_pool(device, flags, queue_families.graphics);
-    let buffers = super::create_command_buffers(device, pool, 2);
+    let pool = super::create_command_pool(device, flags, queue_families.graphics)?;
+    // one for each frame in flight and one for captures
+    let mut buffers = super::create_command_buffers(device, pool, frames_in_flight as u32 + 1)?;
+    let capture = buffers.pop().unwrap();
 
-    Self {
+    Ok(Self {
       pool,
       command_buffers: buffers,
-    }
+      capture,
+    })
   }
 
+  #[allow(clippy::too_many_arguments)]
   pub unsafe fn record(
     &mut self,
     i: usize,
@@ -34,14 +43,15 @@ impl MainCommandBufferPool {
     surface_extent: vk::Extent2D,
     pipelines: &Pipelines,
     buffers: &Buffers,
-    model_props: &Vec<ModelProperties>,
-    dyn_inst_props: &Vec<InstProperties>,
-  ) {
+    texture_set: vk::DescriptorSet,
+    color_draw_count: u32,
+    tex_draw_count: u32,
+  ) -> RenderResult<()> {
     let command_buffer = self.command_buffers[i];
 
     device
       .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
-      .expect("Failed to reset command buffer");
+      .context("Failed to reset command buffer")?;
 
     let command_buffer_begin_info = vk::CommandBufferBeginInfo {
       s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
@@ -52,13 +62,21 @@ impl MainCommandBufferPool {
 
     device
       .begin_command_buffer(command_buffer, &command_buffer_begin_info)
-      .expect("Failed to start recording command buffer");
+      .context("Failed to start recording command buffer")?;
 
-    let clear_values = [vk::ClearValue {
-      color: vk::ClearColorValue {
-        float32: [0.0, 0.0, 0.0, 1.0],
+    let clear_values = [
+      vk::ClearValue {
+        color: vk::ClearColorValue {
+          float32: [0.0, 0.0, 0.0, 1.0],
+        },
+      },
+      vk::ClearValue {
+        depth_stencil: vk::ClearDepthStencilValue {
+          depth: 1.0,
+          stencil: 0,
+        },
       },
-    }];
+    ];
 
     let render_pass_begin_info = vk::RenderPassBeginInfo {
       s_type: vk::StructureType::RENDER_PASS_BEGIN_INFO,
@@ -78,58 +96,275 @@ impl MainCommandBufferPool {
       &render_pass_begin_info,
       vk::SubpassContents::INLINE,
     );
-    device.cmd_bind_pipeline(
-      command_buffer,
-      vk::PipelineBindPoint::GRAPHICS,
-      pipelines.graphics.main,
-    );
-    let vertex_buffers = [
-      buffers.local_constant.vertex.buffer,
-      buffers.local.inst[i].0,
-    ];
-    let offsets = [0_u64, 0];
-
-    device.cmd_bind_vertex_buffers(command_buffer, 0, &vertex_buffers, &offsets);
-    device.cmd_bind_index_buffer(
-      command_buffer,
-      buffers.local_constant.index.buffer,
-      0,
-      vk::IndexType::UINT16,
-    );
+    // the whole render area is drawn to, calling this again before other draws would render
+    // them into another region with the same pipelines
+    set_viewport(device, command_buffer, render_pass_begin_info.render_area);
+    let vertex = &buffers.local_constant.geometry.vertex;
+    let index = &buffers.local_constant.geometry.index;
+    // color draws come first, followed by the textured ones
+    let draw_buffer = buffers.host_writable.draws[i].buffer;
 
-    // draw static objects
-    for inst_p in buffers.local_constant.inst.props.iter() {
-      let model_p = &model_props[inst_p.model_i];
-      device.cmd_draw_indexed(
+    if color_draw_count > 0 {
+      device.cmd_bind_pipeline(
+        command_buffer,
+        vk::PipelineBindPoint::GRAPHICS,
+        pipelines.graphics.main,
+      );
+      device.cmd_bind_vertex_buffers(
+        command_buffer,
+        0,
+        &[vertex.buffer, buffers.local.inst[i].buffer],
+        &[vertex.color_offset, 0],
+      );
+      device.cmd_bind_index_buffer(
         command_buffer,
-        model_p.index_count,
-        inst_p.inst_count,
-        model_p.index_offset,
-        model_p.vertex_offset,
-        inst_p.inst_offset,
-      )
+        index.buffer,
+        index.color_offset,
+        vk::IndexType::UINT16,
+      );
+      device.cmd_draw_indexed_indirect(
+        command_buffer,
+        draw_buffer,
+        0,
+        color_draw_count,
+        IndirectDraw::STRIDE,
+      );
     }
 
-    // draw dynamic objects (same thing but with offset)
-    // I guess this will be better when I find out how to use indirect buffers
-    let static_inst_offset = buffers.local_constant.inst.count;
-    for inst_p in dyn_inst_props.iter() {
-      let model_p = &model_props[inst_p.model_i];
-      device.cmd_draw_indexed(
+    if tex_draw_count > 0 {
+      device.cmd_bind_pipeline(
+        command_buffer,
+        vk::PipelineBindPoint::GRAPHICS,
+        pipelines.graphics.tex,
+      );
+      device.cmd_bind_vertex_buffers(
+        command_buffer,
+        0,
+        &[vertex.buffer, buffers.local.inst[i].buffer],
+        &[vertex.tex_offset, 0],
+      );
+      device.cmd_bind_index_buffer(
         command_buffer,
-        model_p.index_count,
-        inst_p.inst_count,
-        model_p.index_offset,
-        model_p.vertex_offset,
-        static_inst_offset + inst_p.inst_offset,
-      )
+        index.buffer,
+        index.tex_offset,
+        vk::IndexType::UINT16,
+      );
+      device.cmd_bind_descriptor_sets(
+        command_buffer,
+        vk::PipelineBindPoint::GRAPHICS,
+        pipelines.graphics.tex_layout,
+        0,
+        &[texture_set],
+        &[],
+      );
+      device.cmd_draw_indexed_indirect(
+        command_buffer,
+        draw_buffer,
+        IndirectDraw::offset(color_draw_count as usize),
+        tex_draw_count,
+        IndirectDraw::STRIDE,
+      );
     }
 
     device.cmd_end_render_pass(command_buffer);
 
     device
       .end_command_buffer(command_buffer)
-      .expect("Failed to finish recording command buffer")
+      .context("Failed to finish recording command buffer")
+  }
+
+  // copies a rendered image to a buffer, leaving the image in the same layout as it was
+  pub unsafe fn record_capture(
+    &mut self,
+    device: &ash::Device,
+    image: vk::Image,
+    layout: vk::ImageLayout,
+    extent: vk::Extent2D,
+    dst_buffer: vk::Buffer,
+  ) -> RenderResult<()> {
+    let command_buffer = self.capture;
+
+    device
+      .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+      .context("Failed to reset command buffer")?;
+
+    let command_buffer_begin_info = vk::CommandBufferBeginInfo {
+      s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+      p_next: ptr::null(),
+      p_inheritance_info: ptr::null(),
+      flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+    };
+
+    device
+      .begin_command_buffer(command_buffer, &command_buffer_begin_info)
+      .context("Failed to start recording command buffer")?;
+
+    let subresource_range = vk::ImageSubresourceRange {
+      aspect_mask: vk::ImageAspectFlags::COLOR,
+      base_mip_level: 0,
+      level_count: 1,
+      base_array_layer: 0,
+      layer_count: 1,
+    };
+
+    let to_transfer = vk::ImageMemoryBarrier {
+      s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+      p_next: ptr::null(),
+      src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+      dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+      old_layout: layout,
+      new_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+      src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+      dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+      image,
+      subresource_range,
+    };
+    device.cmd_pipeline_barrier(
+      command_buffer,
+      vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+      vk::PipelineStageFlags::TRANSFER,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[],
+      &[to_transfer],
+    );
+
+    let region = vk::BufferImageCopy {
+      buffer_offset: 0,
+      // tightly packed
+      buffer_row_length: 0,
+      buffer_image_height: 0,
+      image_subresource: vk::ImageSubresourceLayers {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        mip_level: 0,
+        base_array_layer: 0,
+        layer_count: 1,
+      },
+      image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+      image_extent: vk::Extent3D {
+        width: extent.width,
+        height: extent.height,
+        depth: 1,
+      },
+    };
+    device.cmd_copy_image_to_buffer(
+      command_buffer,
+      image,
+      vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+      dst_buffer,
+      &[region],
+    );
+
+    let to_host = vk::BufferMemoryBarrier {
+      s_type: vk::StructureType::BUFFER_MEMORY_BARRIER,
+      p_next: ptr::null(),
+      src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+      dst_access_mask: vk::AccessFlags::HOST_READ,
+      src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+      dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+      buffer: dst_buffer,
+      offset: 0,
+      size: vk::WHOLE_SIZE,
+    };
+    let to_previous_layout = vk::ImageMemoryBarrier {
+      s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+      p_next: ptr::null(),
+      src_access_mask: vk::AccessFlags::TRANSFER_READ,
+      dst_access_mask: vk::AccessFlags::empty(),
+      old_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+      new_layout: layout,
+      src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+      dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+      image,
+      subresource_range,
+    };
+    device.cmd_pipeline_barrier(
+      command_buffer,
+      vk::PipelineStageFlags::TRANSFER,
+      vk::PipelineStageFlags::HOST | vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+      vk::DependencyFlags::empty(),
+      &[],
+      &[to_host],
+      &[to_previous_layout],
+    );
+
+    device
+      .end_command_buffer(command_buffer)
+      .context("Failed to finish recording command buffer")
+  }
+
+  // copies a buffer written by earlier submissions to a host visible one
+  #[cfg(test)]
+  pub unsafe fn record_buffer_readback(
+    &mut self,
+    device: &ash::Device,
+    src_buffer: vk::Buffer,
+    dst_buffer: vk::Buffer,
+    size: u64,
+  ) -> RenderResult<()> {
+    let command_buffer = self.capture;
+
+    device
+      .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+      .context("Failed to reset command buffer")?;
+
+    let command_buffer_begin_info = vk::CommandBufferBeginInfo {
+      s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+      p_next: ptr::null(),
+      p_inheritance_info: ptr::null(),
+      flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+    };
+
+    device
+      .begin_command_buffer(command_buffer, &command_buffer_begin_info)
+      .context("Failed to start recording command buffer")?;
+
+    let to_transfer = vk::MemoryBarrier {
+      s_type: vk::StructureType::MEMORY_BARRIER,
+      p_next: ptr::null(),
+      src_access_mask: vk::AccessFlags::MEMORY_WRITE,
+      dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+    };
+    device.cmd_pipeline_barrier(
+      command_buffer,
+      vk::PipelineStageFlags::ALL_COMMANDS,
+      vk::PipelineStageFlags::TRANSFER,
+      vk::DependencyFlags::empty(),
+      &[to_transfer],
+      &[],
+      &[],
+    );
+
+    device.cmd_copy_buffer(
+      command_buffer,
+      src_buffer,
+      dst_buffer,
+      &[vk::BufferCopy {
+        src_offset: 0,
+        dst_offset: 0,
+        size,
+      }],
+    );
+
+    let to_host = vk::MemoryBarrier {
+      s_type: vk::StructureType::MEMORY_BARRIER,
+      p_next: ptr::null(),
+      src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+      dst_access_mask: vk::AccessFlags::HOST_READ,
+    };
+    device.cmd_pipeline_barrier(
+      command_buffer,
+      vk::PipelineStageFlags::TRANSFER,
+      vk::PipelineStageFlags::HOST,
+      vk::DependencyFlags::empty(),
+      &[to_host],
+      &[],
+      &[],
+    );
+
+    device
+      .end_command_buffer(command_buffer)
+      .context("Failed to finish recording command buffer")
   }
 
   pub unsafe fn destroy_self(&mut self, device: &ash::Device) {
@@ -140,3 +375,17 @@ impl MainCommandBufferPool {
     self.command_buffers[i]
   }
 }
+
+// viewport and scissor are dynamic states of all graphics pipelines
+unsafe fn set_viewport(device: &ash::Device, command_buffer: vk::CommandBuffer, area: vk::Rect2D) {
+  let viewport = vk::Viewport {
+    x: area.offset.x as f32,
+    y: area.offset.y as f32,
+    width: area.extent.width as f32,
+    height: area.extent.height as f32,
+    min_depth: 0.0,
+    max_depth: 1.0,
+  };
+  device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+  device.cmd_set_scissor(command_buffer, 0, &[area]);
+}
@@ -6,33 +6,32 @@ use std::ptr;
 
 use ash::vk;
 
-use self::{
-  compute::ComputeCommandBufferPool, main::MainCommandBufferPool,
-  transfer::TransferCommandBufferPool,
-};
+use self::{compute::ComputeCommandBufferPool, main::MainCommandBufferPool};
 
 use super::QueueFamilyIndices;
+use crate::render::error::{RenderResult, VkResultExt};
 
-pub use transfer::CopyBufferOperation;
+pub use transfer::TransferCommandBufferPool;
 
 pub struct CommandBufferPools {
   pub main: MainCommandBufferPool,
-  pub transfer: TransferCommandBufferPool,
   pub compute: ComputeCommandBufferPool,
 }
 
 impl CommandBufferPools {
-  pub fn create(device: &ash::Device, queue_families: &QueueFamilyIndices) -> Self {
-    Self {
-      main: MainCommandBufferPool::create(device, queue_families),
-      transfer: TransferCommandBufferPool::create(device, queue_families),
-      compute: ComputeCommandBufferPool::create(device, queue_families),
-    }
+  pub fn create(
+    device: &ash::Device,
+    queue_families: &QueueFamilyIndices,
+    frames_in_flight: usize,
+  ) -> RenderResult<Self> {
+    Ok(Self {
+      main: MainCommandBufferPool::create(device, queue_families, frames_in_flight)?,
+      compute: ComputeCommandBufferPool::create(device, queue_families, frames_in_flight)?,
+    })
   }
 
   pub unsafe fn destroy_self(&mut self, device: &ash::Device) {
     self.main.destroy_self(device);
-    self.transfer.destroy_self(device);
     self.compute.destroy_self(device)
   }
 }
@@ -41,7 +40,7 @@ pub fn create_command_pool(
   device: &ash::Device,
   flags: vk::CommandPoolCreateFlags,
   queue_family_index: u32,
-) -> vk::CommandPool {
+) -> RenderResult<vk::CommandPool> {
   let command_pool_create_info = vk::CommandPoolCreateInfo {
     s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
     p_next: ptr::null(),
@@ -52,15 +51,15 @@ pub fn create_command_pool(
   unsafe {
     device
       .create_command_pool(&command_pool_create_info, None)
-      .expect("Failed to create Command Pool!")
+      .context("Failed to create command pool")
   }
 }
 
-fn create_command_buffers(
+pub(super) fn create_command_buffers(
   device: &ash::Device,
   command_pool: vk::CommandPool,
   command_buffer_count: u32,
-) -> Vec<vk::CommandBuffer> {
+) -> RenderResult<Vec<vk::CommandBuffer>> {
   let allocate_info = vk::CommandBufferAllocateInfo {
     s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
     p_next: ptr::null(),
@@ -72,6 +71,6 @@ fn create_command_buffers(
   unsafe {
     device
       .allocate_command_buffers(&allocate_info)
-      .expect("Failed to allocate command buffers")
+      .context("Failed to allocate command buffers")
   }
 }
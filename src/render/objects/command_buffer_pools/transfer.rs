@@ -1,71 +1,293 @@
-use std::{ops::BitOr, ptr};
+use std::ptr;
 
 use ash::vk;
 
-use crate::render::objects::QueueFamilyIndices;
-
-pub struct CopyBufferOperation {
-  pub source_buffer: vk::Buffer,
-  pub dest_buffer: vk::Buffer,
-  pub copy_regions: Vec<vk::BufferCopy>,
-}
+use crate::render::{
+  error::{RenderResult, VkResultExt},
+  objects::{
+    uploads::{UploadDst, UploadOperation},
+    QueueFamilyIndices,
+  },
+};
 
+// command buffers are allocated for each upload and freed once it completes
 pub struct TransferCommandBufferPool {
+  // family that records the copies, the transfer family if there is one or else graphics
+  pub family: u32,
   pool: vk::CommandPool,
-  pub copy_buffer: vk::CommandBuffer,
+  // pools of the other families, that acquire the resources copied into
+  acquire_pools: Vec<(u32, vk::CommandPool)>,
 }
 
 impl TransferCommandBufferPool {
-  pub fn create(device: &ash::Device, queue_families: &QueueFamilyIndices) -> Self {
-    let flags =
-      vk::CommandPoolCreateFlags::TRANSIENT.bitor(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
-    let pool = if let Some(transfer) = queue_families.transfer {
-      super::create_command_pool(device, flags, transfer)
-    } else {
-      super::create_command_pool(device, flags, queue_families.graphics)
-    };
+  pub fn create(device: &ash::Device, queue_families: &QueueFamilyIndices) -> RenderResult<Self> {
+    let flags = vk::CommandPoolCreateFlags::TRANSIENT;
+    let family = queue_families.transfer.unwrap_or(queue_families.graphics);
+    let pool = super::create_command_pool(device, flags, family)?;
 
-    let mut buffer_iter = super::create_command_buffers(device, pool, 1).into_iter();
-    let copy_buffer = buffer_iter.next().unwrap();
+    let mut acquire_families = vec![queue_families.graphics, queue_families.compute];
+    acquire_families.sort();
+    acquire_families.dedup();
+    acquire_families.retain(|&f| f != family);
+    let acquire_pools = acquire_families
+      .into_iter()
+      .map(|f| Ok((f, super::create_command_pool(device, flags, f)?)))
+      .collect::<RenderResult<_>>()?;
 
-    Self { pool, copy_buffer }
+    Ok(Self {
+      family,
+      pool,
+      acquire_pools,
+    })
   }
 
-  pub unsafe fn record_copy_buffers(
-    &mut self,
-    device: &ash::Device,
-    operations: &[CopyBufferOperation],
-  ) {
-    device
-      .reset_command_buffer(self.copy_buffer, vk::CommandBufferResetFlags::empty())
-      .expect("Failed to reset command buffer");
-
-    let command_buffer_begin_info = vk::CommandBufferBeginInfo {
-      s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
-      p_next: ptr::null(),
-      p_inheritance_info: ptr::null(),
-      flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
-    };
+  fn acquire_pool(&self, family: u32) -> vk::CommandPool {
+    self
+      .acquire_pools
+      .iter()
+      .find(|(f, _)| *f == family)
+      .unwrap_or_else(|| panic!("No command pool for queue family {}", family))
+      .1
+  }
 
-    device
-      .begin_command_buffer(self.copy_buffer, &command_buffer_begin_info)
-      .expect("Failed to start recording command buffer");
+  // copies every staging buffer into its destination and releases the destinations owned by other
+  // families, the rest is made available to their first use directly
+  pub unsafe fn record_release(
+    &self,
+    device: &ash::Device,
+    operations: &[UploadOperation],
+  ) -> RenderResult<vk::CommandBuffer> {
+    let command_buffer = begin(device, self.pool)?;
 
     for op in operations {
-      device.cmd_copy_buffer(
-        self.copy_buffer,
-        op.source_buffer,
-        op.dest_buffer,
-        &op.copy_regions,
+      if let UploadDst::Image { image, .. } = op.dst {
+        let to_transfer_dst = image_barrier(
+          image,
+          vk::ImageLayout::UNDEFINED,
+          vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+          vk::AccessFlags::empty(),
+          vk::AccessFlags::TRANSFER_WRITE,
+          (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED),
+        );
+        device.cmd_pipeline_barrier(
+          command_buffer,
+          vk::PipelineStageFlags::TOP_OF_PIPE,
+          vk::PipelineStageFlags::TRANSFER,
+          vk::DependencyFlags::empty(),
+          &[],
+          &[],
+          &[to_transfer_dst],
+        );
+      }
+
+      match &op.dst {
+        UploadDst::Buffer { buffer, regions } => {
+          device.cmd_copy_buffer(command_buffer, op.staging.buffer, *buffer, regions)
+        }
+        UploadDst::Image { image, extent } => device.cmd_copy_buffer_to_image(
+          command_buffer,
+          op.staging.buffer,
+          *image,
+          vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+          &[image_copy(*extent)],
+        ),
+      }
+
+      // the destination stage is ignored by release barriers
+      let (dst_stage, dst_access, families) = if op.dst_family == self.family {
+        (
+          op.dst_stage,
+          op.dst_access,
+          (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED),
+        )
+      } else {
+        (
+          vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+          vk::AccessFlags::empty(),
+          (self.family, op.dst_family),
+        )
+      };
+      record_dst_barrier(
+        device,
+        command_buffer,
+        op,
+        (vk::PipelineStageFlags::TRANSFER, dst_stage),
+        (vk::AccessFlags::TRANSFER_WRITE, dst_access),
+        families,
       );
     }
 
-    device
-      .end_command_buffer(self.copy_buffer)
-      .expect("Failed to finish recording command buffer")
+    end(device, self.pool, command_buffer)
+  }
+
+  // acquires the destinations of the operations owned by family, matching the barriers of
+  // record_release
+  pub unsafe fn record_acquire(
+    &self,
+    device: &ash::Device,
+    family: u32,
+    operations: &[UploadOperation],
+  ) -> RenderResult<vk::CommandBuffer> {
+    let pool = self.acquire_pool(family);
+    let command_buffer = begin(device, pool)?;
+    for op in operations.iter().filter(|op| op.dst_family == family) {
+      // the source stage is ignored by acquire barriers
+      record_dst_barrier(
+        device,
+        command_buffer,
+        op,
+        (vk::PipelineStageFlags::TOP_OF_PIPE, op.dst_stage),
+        (vk::AccessFlags::empty(), op.dst_access),
+        (self.family, family),
+      );
+    }
+    end(device, pool, command_buffer)
+  }
+
+  pub unsafe fn free(&self, device: &ash::Device, family: u32, command_buffer: vk::CommandBuffer) {
+    let pool = if family == self.family {
+      self.pool
+    } else {
+      self.acquire_pool(family)
+    };
+    device.free_command_buffers(pool, &[command_buffer]);
   }
 
   pub unsafe fn destroy_self(&mut self, device: &ash::Device) {
     device.destroy_command_pool(self.pool, None);
+    for &(_, pool) in self.acquire_pools.iter() {
+      device.destroy_command_pool(pool, None);
+    }
+  }
+}
+
+unsafe fn begin(device: &ash::Device, pool: vk::CommandPool) -> RenderResult<vk::CommandBuffer> {
+  let command_buffer = super::create_command_buffers(device, pool, 1)?[0];
+  let command_buffer_begin_info = vk::CommandBufferBeginInfo {
+    s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+    p_next: ptr::null(),
+    p_inheritance_info: ptr::null(),
+    flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+  };
+  device
+    .begin_command_buffer(command_buffer, &command_buffer_begin_info)
+    .context("Failed to start recording command buffer")
+    .inspect_err(|_| device.free_command_buffers(pool, &[command_buffer]))?;
+  Ok(command_buffer)
+}
+
+unsafe fn end(
+  device: &ash::Device,
+  pool: vk::CommandPool,
+  command_buffer: vk::CommandBuffer,
+) -> RenderResult<vk::CommandBuffer> {
+  device
+    .end_command_buffer(command_buffer)
+    .context("Failed to finish recording command buffer")
+    .inspect_err(|_| device.free_command_buffers(pool, &[command_buffer]))?;
+  Ok(command_buffer)
+}
+
+// barrier between the copy into the destination of op and its first use
+// images are transitioned to SHADER_READ_ONLY_OPTIMAL, which release and acquire barriers both
+// have to specify
+unsafe fn record_dst_barrier(
+  device: &ash::Device,
+  command_buffer: vk::CommandBuffer,
+  op: &UploadOperation,
+  (src_stage, dst_stage): (vk::PipelineStageFlags, vk::PipelineStageFlags),
+  (src_access, dst_access): (vk::AccessFlags, vk::AccessFlags),
+  (src_family, dst_family): (u32, u32),
+) {
+  match &op.dst {
+    UploadDst::Buffer { buffer, .. } => {
+      let barrier = vk::BufferMemoryBarrier {
+        s_type: vk::StructureType::BUFFER_MEMORY_BARRIER,
+        p_next: ptr::null(),
+        src_access_mask: src_access,
+        dst_access_mask: dst_access,
+        src_queue_family_index: src_family,
+        dst_queue_family_index: dst_family,
+        buffer: *buffer,
+        offset: 0,
+        size: vk::WHOLE_SIZE,
+      };
+      device.cmd_pipeline_barrier(
+        command_buffer,
+        src_stage,
+        dst_stage,
+        vk::DependencyFlags::empty(),
+        &[],
+        &[barrier],
+        &[],
+      );
+    }
+    UploadDst::Image { image, .. } => {
+      let barrier = image_barrier(
+        *image,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        src_access,
+        dst_access,
+        (src_family, dst_family),
+      );
+      device.cmd_pipeline_barrier(
+        command_buffer,
+        src_stage,
+        dst_stage,
+        vk::DependencyFlags::empty(),
+        &[],
+        &[],
+        &[barrier],
+      );
+    }
+  }
+}
+
+fn image_barrier(
+  image: vk::Image,
+  old_layout: vk::ImageLayout,
+  new_layout: vk::ImageLayout,
+  src_access_mask: vk::AccessFlags,
+  dst_access_mask: vk::AccessFlags,
+  (src_queue_family_index, dst_queue_family_index): (u32, u32),
+) -> vk::ImageMemoryBarrier {
+  vk::ImageMemoryBarrier {
+    s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+    p_next: ptr::null(),
+    src_access_mask,
+    dst_access_mask,
+    old_layout,
+    new_layout,
+    src_queue_family_index,
+    dst_queue_family_index,
+    image,
+    subresource_range: vk::ImageSubresourceRange {
+      aspect_mask: vk::ImageAspectFlags::COLOR,
+      base_mip_level: 0,
+      level_count: 1,
+      base_array_layer: 0,
+      layer_count: 1,
+    },
+  }
+}
+
+fn image_copy(extent: vk::Extent2D) -> vk::BufferImageCopy {
+  vk::BufferImageCopy {
+    buffer_offset: 0,
+    buffer_row_length: 0,
+    buffer_image_height: 0,
+    image_subresource: vk::ImageSubresourceLayers {
+      aspect_mask: vk::ImageAspectFlags::COLOR,
+      mip_level: 0,
+      base_array_layer: 0,
+      layer_count: 1,
+    },
+    image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+    image_extent: vk::Extent3D {
+      width: extent.width,
+      height: extent.height,
+      depth: 1,
+    },
   }
 }
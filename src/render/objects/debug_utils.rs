@@ -2,6 +2,8 @@ use ash::vk::{self, DebugUtilsMessengerCreateInfoEXT};
 use log::{debug, error, info, warn};
 use std::{ffi::CStr, os::raw::c_void, ptr};
 
+use crate::render::error::{RenderResult, VkResultExt};
+
 unsafe extern "system" fn vulkan_debug_utils_callback(
   message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
   message_type: vk::DebugUtilsMessageTypeFlagsEXT,
@@ -37,17 +39,17 @@ impl DebugUtils {
     entry: &ash::Entry,
     instance: &ash::Instance,
     create_info: DebugUtilsMessengerCreateInfoEXT,
-  ) -> Self {
+  ) -> RenderResult<Self> {
     let loader = ash::extensions::ext::DebugUtils::new(entry, instance);
 
     info!("Creating debug utils messenger");
     let messenger = unsafe {
       loader
         .create_debug_utils_messenger(&create_info, None)
-        .expect("Failed to create debug utils")
+        .context("Failed to create debug utils")?
     };
 
-    Self { loader, messenger }
+    Ok(Self { loader, messenger })
   }
 
   pub fn get_debug_messenger_create_info() -> vk::DebugUtilsMessengerCreateInfoEXT {
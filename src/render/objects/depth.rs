@@ -0,0 +1,73 @@
+use ash::vk;
+use log::info;
+
+use super::{
+  allocator::{Allocation, Allocator},
+  images::{create_image, create_image_view},
+};
+use crate::render::error::{RenderError, RenderResult};
+
+// in order of preference
+const DEPTH_FORMATS: [vk::Format; 3] = [
+  vk::Format::D32_SFLOAT,
+  vk::Format::D32_SFLOAT_S8_UINT,
+  vk::Format::D24_UNORM_S8_UINT,
+];
+
+pub struct DepthBuffer {
+  image: vk::Image,
+  allocation: Allocation,
+  pub view: vk::ImageView,
+  pub format: vk::Format,
+}
+
+impl DepthBuffer {
+  pub fn create(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    extent: vk::Extent2D,
+  ) -> RenderResult<Self> {
+    info!("Creating depth buffer");
+    let format = select_depth_format(instance, physical_device)?;
+
+    let (image, allocation) = create_image(
+      device,
+      allocator,
+      extent,
+      format,
+      vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+    )?;
+    let view = create_image_view(device, image, format, vk::ImageAspectFlags::DEPTH)?;
+
+    Ok(Self {
+      image,
+      allocation,
+      view,
+      format,
+    })
+  }
+
+  pub unsafe fn destroy_self(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+    device.destroy_image_view(self.view, None);
+    device.destroy_image(self.image, None);
+    allocator.free(device, self.allocation);
+  }
+}
+
+fn select_depth_format(
+  instance: &ash::Instance,
+  physical_device: vk::PhysicalDevice,
+) -> RenderResult<vk::Format> {
+  DEPTH_FORMATS
+    .into_iter()
+    .find(|&format| {
+      let properties =
+        unsafe { instance.get_physical_device_format_properties(physical_device, format) };
+      properties
+        .optimal_tiling_features
+        .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+    })
+    .ok_or(RenderError::NoSupportedFormat("depth"))
+}
@@ -2,9 +2,13 @@ use std::{ops::Deref, ptr};
 
 use ash::vk;
 
+use crate::render::error::{RenderResult, VkResultExt};
+
 pub struct SizedLayout {
   pub layout: vk::DescriptorSetLayout,
   pub descriptor_count: usize,
+  // kept to check pipelines against their shaders
+  pub bindings: Vec<vk::DescriptorSetLayoutBinding>,
 }
 
 impl Deref for SizedLayout {
@@ -17,10 +21,18 @@ impl Deref for SizedLayout {
 
 pub struct DescriptorSetLayouts {
   pub inst: SizedLayout,
+  pub texture: SizedLayout,
 }
 
 impl DescriptorSetLayouts {
-  pub fn new(device: &ash::Device) -> Self {
+  pub fn new(device: &ash::Device, texture_count: usize) -> RenderResult<Self> {
+    Ok(Self {
+      inst: Self::create_inst(device)?,
+      texture: Self::create_texture(device, texture_count)?,
+    })
+  }
+
+  fn create_inst(device: &ash::Device) -> RenderResult<SizedLayout> {
     let bindings = [
       vk::DescriptorSetLayoutBinding {
         binding: 0,
@@ -36,35 +48,62 @@ impl DescriptorSetLayouts {
         stage_flags: vk::ShaderStageFlags::COMPUTE,
         p_immutable_samplers: ptr::null(),
       },
+      // indirect draws
+      vk::DescriptorSetLayoutBinding {
+        binding: 2,
+        descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+        descriptor_count: 1,
+        stage_flags: vk::ShaderStageFlags::COMPUTE,
+        p_immutable_samplers: ptr::null(),
+      },
     ];
-    let descriptor_count = bindings
-      .iter()
-      .map(|binding| binding.descriptor_count)
-      .sum::<u32>() as usize;
-
-    let create_info = vk::DescriptorSetLayoutCreateInfo {
-      s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
-      p_next: ptr::null(),
-      flags: vk::DescriptorSetLayoutCreateFlags::empty(),
-      binding_count: bindings.len() as u32,
-      p_bindings: bindings.as_ptr(),
-    };
-
-    let layout = unsafe {
-      device
-        .create_descriptor_set_layout(&create_info, None)
-        .expect("Failed to create descriptor set layout")
-    };
+    create_sized_layout(device, &bindings)
+  }
 
-    Self {
-      inst: SizedLayout {
-        layout,
-        descriptor_count,
-      },
-    }
+  // array of all textures, with room for at least one so that the layout matches the shader
+  fn create_texture(device: &ash::Device, texture_count: usize) -> RenderResult<SizedLayout> {
+    let bindings = [vk::DescriptorSetLayoutBinding {
+      binding: 0,
+      descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+      descriptor_count: texture_count.max(1) as u32,
+      stage_flags: vk::ShaderStageFlags::FRAGMENT,
+      p_immutable_samplers: ptr::null(),
+    }];
+    create_sized_layout(device, &bindings)
   }
 
   pub unsafe fn destroy_self(&mut self, device: &ash::Device) {
     device.destroy_descriptor_set_layout(self.inst.layout, None);
+    device.destroy_descriptor_set_layout(self.texture.layout, None);
   }
 }
+
+fn create_sized_layout(
+  device: &ash::Device,
+  bindings: &[vk::DescriptorSetLayoutBinding],
+) -> RenderResult<SizedLayout> {
+  let descriptor_count = bindings
+    .iter()
+    .map(|binding| binding.descriptor_count)
+    .sum::<u32>() as usize;
+
+  let create_info = vk::DescriptorSetLayoutCreateInfo {
+    s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+    p_next: ptr::null(),
+    flags: vk::DescriptorSetLayoutCreateFlags::empty(),
+    binding_count: bindings.len() as u32,
+    p_bindings: bindings.as_ptr(),
+  };
+
+  let layout = unsafe {
+    device
+      .create_descriptor_set_layout(&create_info, None)
+      .context("Failed to create descriptor set layout")?
+  };
+
+  Ok(SizedLayout {
+    layout,
+    descriptor_count,
+    bindings: bindings.to_vec(),
+  })
+}
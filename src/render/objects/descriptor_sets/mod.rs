@@ -1,6 +1,7 @@
 use log::debug;
 
 use self::{layouts::DescriptorSetLayouts, pool::DescriptorSetPool};
+use crate::render::error::RenderResult;
 
 mod layouts;
 mod pool;
@@ -11,12 +12,16 @@ pub struct DescriptorSets {
 }
 
 impl DescriptorSets {
-  pub fn new(device: &ash::Device) -> Self {
+  pub fn new(
+    device: &ash::Device,
+    texture_count: usize,
+    frames_in_flight: usize,
+  ) -> RenderResult<Self> {
     debug!("Creating descriptor set layouts");
-    let layouts = DescriptorSetLayouts::new(device);
+    let layouts = DescriptorSetLayouts::new(device, texture_count)?;
     debug!("Creating descriptor set pool");
-    let pool = DescriptorSetPool::new(device, &layouts);
-    Self { layouts, pool }
+    let pool = DescriptorSetPool::new(device, &layouts, frames_in_flight)?;
+    Ok(Self { layouts, pool })
   }
 
   pub unsafe fn destroy_self(&mut self, device: &ash::Device) {
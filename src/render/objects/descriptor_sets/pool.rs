@@ -2,28 +2,47 @@ use std::{mem::size_of, ptr};
 
 use ash::vk;
 
-use crate::render::{objects::Buffers, sync::FRAMES_IN_FLIGHT, MatrixInstance};
+use crate::render::{
+  error::{RenderResult, VkResultExt},
+  objects::{Buffers, Textures},
+  MatrixInstance,
+};
 
 use super::layouts::DescriptorSetLayouts;
 
 pub struct DescriptorSetPool {
   pool: vk::DescriptorPool,
-  pub inst_static: [vk::DescriptorSet; FRAMES_IN_FLIGHT],
-  pub inst_dyn: [vk::DescriptorSet; FRAMES_IN_FLIGHT],
+  // one of each for every frame in flight
+  pub inst_static: Vec<vk::DescriptorSet>,
+  pub inst_dyn: Vec<vk::DescriptorSet>,
+  // texture array, slots of textures that are still loading are bound to a placeholder
+  pub textures: Vec<vk::DescriptorSet>,
+  texture_capacity: usize,
 }
 
 impl DescriptorSetPool {
-  pub fn new(device: &ash::Device, layouts: &DescriptorSetLayouts) -> Self {
+  pub fn new(
+    device: &ash::Device,
+    layouts: &DescriptorSetLayouts,
+    frames_in_flight: usize,
+  ) -> RenderResult<Self> {
     // this all needs some sort of restructuring
-    // 2 for each set array
-    let layouts_arr = [layouts.inst.layout; FRAMES_IN_FLIGHT * 2];
-
-    // 2 for each descriptor array
-    let descriptor_count = (layouts.inst.descriptor_count * FRAMES_IN_FLIGHT * 2) as u32;
-    let sizes = [vk::DescriptorPoolSize {
-      ty: vk::DescriptorType::STORAGE_BUFFER,
-      descriptor_count,
-    }];
+    // a static and a dynamic instance set for each frame in flight, followed by a texture set for
+    // each, as textures get added while previous frames are rendering
+    let mut layouts_arr = vec![layouts.inst.layout; frames_in_flight * 2];
+    layouts_arr.extend(vec![layouts.texture.layout; frames_in_flight]);
+
+    let descriptor_count = (layouts.inst.descriptor_count * frames_in_flight * 2) as u32;
+    let sizes = [
+      vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::STORAGE_BUFFER,
+        descriptor_count,
+      },
+      vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        descriptor_count: (layouts.texture.descriptor_count * frames_in_flight) as u32,
+      },
+    ];
     let pool_create_info = vk::DescriptorPoolCreateInfo {
       s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
       p_next: ptr::null(),
@@ -35,7 +54,7 @@ impl DescriptorSetPool {
     let pool = unsafe {
       device
         .create_descriptor_pool(&pool_create_info, None)
-        .expect("Failed to create descriptor pool")
+        .context("Failed to create descriptor pool")?
     };
 
     let allocate_info = vk::DescriptorSetAllocateInfo {
@@ -48,62 +67,77 @@ impl DescriptorSetPool {
     let descriptor_sets = unsafe {
       device
         .allocate_descriptor_sets(&allocate_info)
-        .expect("Failed to allocate descriptor sets")
+        .context("Failed to allocate descriptor sets")
+        .inspect_err(|_| device.destroy_descriptor_pool(pool, None))?
     };
 
     let mut iter = descriptor_sets.into_iter();
-    let inst_static = iter.next_chunk().unwrap();
-    let inst_dyn = iter.next_chunk().unwrap();
-    Self {
+    let inst_static = iter.by_ref().take(frames_in_flight).collect();
+    let inst_dyn = iter.by_ref().take(frames_in_flight).collect();
+    let textures = iter.collect();
+    Ok(Self {
       pool,
       inst_static,
       inst_dyn,
-    }
+      textures,
+      texture_capacity: layouts.texture.descriptor_count,
+    })
   }
 
-  pub fn update_all_inst_static(&mut self, device: &ash::Device, buffers: &Buffers) {
-    for i in 0..(self.inst_static.len()) {
-      self.update_inst_static(i, device, buffers);
+  pub fn update_all_textures(&mut self, device: &ash::Device, textures: &Textures) {
+    for i in 0..(self.textures.len()) {
+      self.update_textures(i, device, textures);
     }
   }
 
-  pub fn update_inst_static(&mut self, i: usize, device: &ash::Device, buffers: &Buffers) {
-    // [static] [dyn] -> [static dyn]
-    let static_size = buffers.local_constant.inst.size;
-    let buffer_info_source = vk::DescriptorBufferInfo {
-      buffer: buffers.local_constant.inst.buffer,
-      offset: 0,
-      range: static_size,
-    };
-    let buffer_info_dest = vk::DescriptorBufferInfo {
-      buffer: buffers.local.inst[i].0,
-      offset: 0,
-      range: static_size,
-    };
-
-    let source = vk::WriteDescriptorSet {
+  // frame i should not be in use by the device
+  pub fn update_textures(&mut self, i: usize, device: &ash::Device, textures: &Textures) {
+    assert!(textures.textures.len() <= self.texture_capacity);
+    let image_infos: Vec<vk::DescriptorImageInfo> = textures
+      .textures
+      .iter()
+      .chain(std::iter::repeat(&textures.placeholder))
+      .take(self.texture_capacity)
+      .map(|texture| vk::DescriptorImageInfo {
+        sampler: textures.sampler,
+        image_view: texture.view,
+        image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+      })
+      .collect();
+
+    let writes = [vk::WriteDescriptorSet {
       s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
       p_next: ptr::null(),
-      dst_set: self.inst_static[i],
+      dst_set: self.textures[i],
       dst_binding: 0,
       dst_array_element: 0,
-      descriptor_count: 1,
-      descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
-      p_buffer_info: &buffer_info_source,
-      p_image_info: ptr::null(),
+      descriptor_count: image_infos.len() as u32,
+      descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+      p_buffer_info: ptr::null(),
+      p_image_info: image_infos.as_ptr(),
       p_texel_buffer_view: ptr::null(),
-    };
-    let mut dest = source.clone();
-    dest.dst_binding = 1;
-    dest.p_buffer_info = &buffer_info_dest;
-
-    let writes = [source, dest];
+    }];
     let copies = [];
     unsafe {
       device.update_descriptor_sets(&writes, &copies);
     }
   }
 
+  pub fn update_all_inst_static(&mut self, device: &ash::Device, buffers: &Buffers) {
+    for i in 0..(self.inst_static.len()) {
+      self.update_inst_static(i, device, buffers);
+    }
+  }
+
+  pub fn update_inst_static(&mut self, i: usize, device: &ash::Device, buffers: &Buffers) {
+    let buffer_info_source = vk::DescriptorBufferInfo {
+      buffer: buffers.local_constant.inst.buffer,
+      offset: 0,
+      range: buffers.local_constant.inst.size,
+    };
+    Self::write_inst(device, self.inst_static[i], buffer_info_source, i, buffers);
+  }
+
   pub fn update_inst_dyn(
     &mut self,
     i: usize,
@@ -111,24 +145,39 @@ impl DescriptorSetPool {
     buffers: &Buffers,
     dyn_inst_count: u64,
   ) {
-    // [static] [dyn] -> [static dyn]
-    let static_size = buffers.local_constant.inst.size;
-    let dyn_size = size_of::<MatrixInstance>() as u64 * dyn_inst_count;
+    // descriptor ranges can't be empty, buffers always have room for at least one instance
+    let dyn_size = size_of::<MatrixInstance>() as u64 * dyn_inst_count.max(1);
     let buffer_info_source = vk::DescriptorBufferInfo {
-      buffer: buffers.host_writable.inst[i].0,
+      buffer: buffers.host_writable.inst[i].buffer,
       offset: 0,
       range: dyn_size,
     };
+    Self::write_inst(device, self.inst_dyn[i], buffer_info_source, i, buffers);
+  }
+
+  // the whole destination and draw buffers are bound, as draws refer to absolute instance indices
+  fn write_inst(
+    device: &ash::Device,
+    set: vk::DescriptorSet,
+    buffer_info_source: vk::DescriptorBufferInfo,
+    i: usize,
+    buffers: &Buffers,
+  ) {
     let buffer_info_dest = vk::DescriptorBufferInfo {
-      buffer: buffers.local.inst[i].0,
-      offset: static_size,
-      range: dyn_size,
+      buffer: buffers.local.inst[i].buffer,
+      offset: 0,
+      range: vk::WHOLE_SIZE,
+    };
+    let buffer_info_draws = vk::DescriptorBufferInfo {
+      buffer: buffers.host_writable.draws[i].buffer,
+      offset: 0,
+      range: vk::WHOLE_SIZE,
     };
 
     let source = vk::WriteDescriptorSet {
       s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
       p_next: ptr::null(),
-      dst_set: self.inst_dyn[i],
+      dst_set: set,
       dst_binding: 0,
       dst_array_element: 0,
       descriptor_count: 1,
@@ -137,11 +186,14 @@ impl DescriptorSetPool {
       p_image_info: ptr::null(),
       p_texel_buffer_view: ptr::null(),
     };
-    let mut dest = source.clone();
+    let mut dest = source;
     dest.dst_binding = 1;
     dest.p_buffer_info = &buffer_info_dest;
+    let mut draws = source;
+    draws.dst_binding = 2;
+    draws.p_buffer_info = &buffer_info_draws;
 
-    let writes = [source, dest];
+    let writes = [source, dest, draws];
     let copies = [];
     unsafe {
       device.update_descriptor_sets(&writes, &copies);
@@ -2,16 +2,19 @@ use std::ptr;
 
 use ash::vk;
 
+use crate::render::error::{RenderResult, VkResultExt};
+
 pub fn create_framebuffers(
   device: &ash::Device,
   render_pass: vk::RenderPass,
-  image_views: &Vec<vk::ImageView>,
+  image_views: &[vk::ImageView],
+  depth_view: vk::ImageView,
   extent: &vk::Extent2D,
-) -> Vec<vk::Framebuffer> {
+) -> RenderResult<Vec<vk::Framebuffer>> {
   let mut framebuffers = Vec::with_capacity(image_views.len());
 
   for &image_view in image_views.iter() {
-    let attachments = [image_view];
+    let attachments = [image_view, depth_view];
 
     let framebuffer_create_info = vk::FramebufferCreateInfo {
       s_type: vk::StructureType::FRAMEBUFFER_CREATE_INFO,
@@ -28,11 +31,11 @@ pub fn create_framebuffers(
     let framebuffer = unsafe {
       device
         .create_framebuffer(&framebuffer_create_info, None)
-        .expect("Failed to create framebuffer")
+        .context("Failed to create framebuffer")?
     };
 
     framebuffers.push(framebuffer);
   }
 
-  framebuffers
+  Ok(framebuffers)
 }
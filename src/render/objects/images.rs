@@ -0,0 +1,82 @@
+use std::ptr;
+
+use ash::vk;
+
+use super::allocator::{Allocation, Allocator};
+use crate::render::error::{RenderResult, VkResultExt};
+
+// creates a 2D device local image
+pub fn create_image(
+  device: &ash::Device,
+  allocator: &mut Allocator,
+  extent: vk::Extent2D,
+  format: vk::Format,
+  usage: vk::ImageUsageFlags,
+) -> RenderResult<(vk::Image, Allocation)> {
+  let image_create_info = vk::ImageCreateInfo {
+    s_type: vk::StructureType::IMAGE_CREATE_INFO,
+    p_next: ptr::null(),
+    flags: vk::ImageCreateFlags::empty(),
+    image_type: vk::ImageType::TYPE_2D,
+    format,
+    extent: vk::Extent3D {
+      width: extent.width,
+      height: extent.height,
+      depth: 1,
+    },
+    mip_levels: 1,
+    array_layers: 1,
+    samples: vk::SampleCountFlags::TYPE_1,
+    tiling: vk::ImageTiling::OPTIMAL,
+    usage,
+    sharing_mode: vk::SharingMode::EXCLUSIVE,
+    queue_family_index_count: 0,
+    p_queue_family_indices: ptr::null(),
+    initial_layout: vk::ImageLayout::UNDEFINED,
+  };
+
+  let image = unsafe {
+    device
+      .create_image(&image_create_info, None)
+      .context("Failed to create image")?
+  };
+
+  let allocation = allocator.bind_image(device, image, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+
+  Ok((image, allocation))
+}
+
+pub fn create_image_view(
+  device: &ash::Device,
+  image: vk::Image,
+  format: vk::Format,
+  aspect_mask: vk::ImageAspectFlags,
+) -> RenderResult<vk::ImageView> {
+  let view_create_info = vk::ImageViewCreateInfo {
+    s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
+    p_next: ptr::null(),
+    flags: vk::ImageViewCreateFlags::empty(),
+    view_type: vk::ImageViewType::TYPE_2D,
+    format,
+    components: vk::ComponentMapping {
+      r: vk::ComponentSwizzle::IDENTITY,
+      g: vk::ComponentSwizzle::IDENTITY,
+      b: vk::ComponentSwizzle::IDENTITY,
+      a: vk::ComponentSwizzle::IDENTITY,
+    },
+    subresource_range: vk::ImageSubresourceRange {
+      aspect_mask,
+      base_mip_level: 0,
+      level_count: 1,
+      base_array_layer: 0,
+      layer_count: 1,
+    },
+    image,
+  };
+
+  unsafe {
+    device
+      .create_image_view(&view_create_info, None)
+      .context("Failed to create image view")
+  }
+}
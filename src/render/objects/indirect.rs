@@ -0,0 +1,141 @@
+use ash::vk;
+
+use crate::render::models::ModelProperties;
+
+// indexed indirect draw command followed by what the instance compute shader needs to cull and
+// compact the instances of the draw
+// instance_count starts at 0 and gets incremented by the compute shader for each visible instance
+// see the Draw struct in instance.comp (std430 layout)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct IndirectDraw {
+  pub command: vk::DrawIndexedIndirectCommand,
+  // end of the draw's instance range in the transformed instance buffer
+  pub inst_end: u32,
+  // index into the texture array, unused by color models
+  pub texture_i: u32,
+  // draws are ordered by pipeline, so the compute shader searches them by instance range through
+  // this field: the search_i of the k-th draw is the position of the draw with the k-th lowest
+  // instance range
+  pub search_i: u32,
+  // model space bounding sphere, (center, radius)
+  pub bounds: [f32; 4],
+}
+
+impl IndirectDraw {
+  pub const STRIDE: u32 = std::mem::size_of::<Self>() as u32;
+
+  pub fn new(model_p: &ModelProperties, first_instance: u32, inst_count: u32) -> Self {
+    Self {
+      command: vk::DrawIndexedIndirectCommand {
+        index_count: model_p.index_count,
+        instance_count: 0,
+        first_index: model_p.index_offset,
+        vertex_offset: model_p.vertex_offset,
+        first_instance,
+      },
+      inst_end: first_instance + inst_count,
+      texture_i: model_p.texture_i.unwrap_or(0) as u32,
+      search_i: 0,
+      bounds: model_p.bounds,
+    }
+  }
+
+  // offset of draw i in the draw buffer
+  pub fn offset(i: usize) -> u64 {
+    i as u64 * Self::STRIDE as u64
+  }
+}
+
+// draws of a frame, ordered by pipeline (color models first) so that each pipeline draws all of
+// its models with a single indirect call
+pub struct IndirectDraws {
+  pub draws: Vec<IndirectDraw>,
+  pub color_count: usize,
+}
+
+impl IndirectDraws {
+  // ranges are (model, first instance, instance count) and should be sorted by first instance
+  pub fn new<'a>(ranges: impl Iterator<Item = (&'a ModelProperties, u32, u32)>) -> Self {
+    let mut color = Vec::new();
+    let mut textured = Vec::new();
+    for (k, (model_p, first_instance, inst_count)) in ranges.enumerate() {
+      let draw = IndirectDraw::new(model_p, first_instance, inst_count);
+      if model_p.texture_i.is_some() {
+        textured.push((k, draw));
+      } else {
+        color.push((k, draw));
+      }
+    }
+
+    let color_count = color.len();
+    let ordered: Vec<(usize, IndirectDraw)> = color.into_iter().chain(textured).collect();
+    let mut draws: Vec<IndirectDraw> = ordered.iter().map(|(_, draw)| *draw).collect();
+    for (position, (k, _)) in ordered.iter().enumerate() {
+      draws[*k].search_i = position as u32;
+    }
+    Self { draws, color_count }
+  }
+
+  pub fn textured_count(&self) -> usize {
+    self.draws.len() - self.color_count
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use memoffset::offset_of;
+
+  use super::*;
+
+  fn model(texture_i: Option<usize>) -> ModelProperties {
+    ModelProperties {
+      vertex_count: 3,
+      vertex_offset: 0,
+      index_count: 3,
+      index_offset: 0,
+      texture_i,
+      bounds: [0.0, 0.0, 0.0, 1.0],
+    }
+  }
+
+  #[test]
+  fn draws_are_grouped_by_pipeline() {
+    let color = model(None);
+    let textured = model(Some(2));
+    let ranges = [
+      (&textured, 0, 2),
+      (&color, 2, 1),
+      (&textured, 3, 4),
+      (&color, 7, 3),
+    ];
+    let draws = IndirectDraws::new(ranges.into_iter());
+
+    assert_eq!(draws.color_count, 2);
+    assert_eq!(draws.textured_count(), 2);
+    let first_instances: Vec<u32> = draws
+      .draws
+      .iter()
+      .map(|draw| draw.command.first_instance)
+      .collect();
+    assert_eq!(first_instances, [2, 7, 0, 3]);
+    assert_eq!(draws.draws[2].texture_i, 2);
+
+    // searching through search_i visits the draws in instance order
+    let searched: Vec<u32> = (0..draws.draws.len())
+      .map(|k| {
+        draws.draws[draws.draws[k].search_i as usize]
+          .command
+          .first_instance
+      })
+      .collect();
+    assert_eq!(searched, [0, 2, 3, 7]);
+  }
+
+  #[test]
+  fn matches_shader_layout() {
+    assert_eq!(IndirectDraw::STRIDE, 48);
+    assert_eq!(offset_of!(IndirectDraw, inst_end), 20);
+    assert_eq!(offset_of!(IndirectDraw, bounds), 32);
+  }
+}
@@ -4,17 +4,24 @@ use raw_window_handle::HasRawDisplayHandle;
 use std::{ffi::CString, ptr};
 use winit::window::Window;
 
-use crate::{render::utility, WINDOW_TITLE};
+use crate::{
+  render::{
+    error::{RenderError, RenderResult, VkResultExt},
+    utility,
+  },
+  WINDOW_TITLE,
+};
 
 #[cfg(feature = "vulkan_vl")]
 use std::os::raw::{c_char, c_void};
 
+// window can be None when rendering headlessly, in which case no surface extensions are required
 pub fn create_instance(
   entry: &ash::Entry,
-  window: &Window,
-  #[cfg(feature = "vulkan_vl")] vl_pointers: &Vec<*const c_char>,
+  window: Option<&Window>,
+  #[cfg(feature = "vulkan_vl")] vl_pointers: &[*const c_char],
   #[cfg(feature = "vulkan_vl")] debug_create_info: &vk::DebugUtilsMessengerCreateInfoEXT,
-) -> ash::Instance {
+) -> RenderResult<ash::Instance> {
   let app_name = CString::new(WINDOW_TITLE).unwrap();
   let engine_name = CString::new("no engine").unwrap();
   let app_info = vk::ApplicationInfo {
@@ -28,14 +35,15 @@ pub fn create_instance(
   };
 
   #[allow(unused_mut)]
-  let mut required_extensions =
-    ash_window::enumerate_required_extensions(window.raw_display_handle())
-      .expect("Failed to enumerate window extensions")
-      .to_vec();
+  let mut required_extensions = match window {
+    Some(window) => ash_window::enumerate_required_extensions(window.raw_display_handle())
+      .context("Failed to enumerate window extensions")?
+      .to_vec(),
+    None => Vec::new(),
+  };
   #[cfg(feature = "vulkan_vl")]
   required_extensions.push(ash::extensions::ext::DebugUtils::name().as_ptr());
-  test_instance_extension_suport(entry, &required_extensions)
-    .unwrap_or_else(|ext| panic!("Required instance extension is not available: {ext}"));
+  test_instance_extension_suport(entry, &required_extensions)?;
 
   #[allow(unused_mut)]
   let mut create_info = vk::InstanceCreateInfo {
@@ -58,19 +66,17 @@ pub fn create_instance(
   }
 
   debug!("Creating instance");
-  let instance: ash::Instance = unsafe {
+  unsafe {
     entry
       .create_instance(&create_info, None)
-      .expect("Failed to create instance")
-  };
-
-  instance
+      .context("Failed to create instance")
+  }
 }
 
 fn test_instance_extension_suport(
   entry: &ash::Entry,
-  extensions: &Vec<*const i8>,
-) -> Result<(), String> {
+  extensions: &[*const i8],
+) -> RenderResult<()> {
   let required_extensions: Vec<&str> = extensions
     .iter()
     .map(|x| {
@@ -82,7 +88,7 @@ fn test_instance_extension_suport(
 
   let available_extensions: Vec<String> = entry
     .enumerate_instance_extension_properties(None)
-    .unwrap()
+    .context("Failed to enumerate instance extensions")?
     .iter()
     .filter_map(|x| match utility::i8_array_to_string(&x.extension_name) {
       Ok(s) => Some(s),
@@ -99,6 +105,6 @@ fn test_instance_extension_suport(
 
   match utility::contains_all(&mut available_extensions, &required_extensions) {
     Ok(_) => Ok(()),
-    Err(s) => Err(s.to_string()),
+    Err(s) => Err(RenderError::MissingExtension(s.to_string())),
   }
 }
@@ -3,14 +3,17 @@ use log::info;
 use std::{ffi::CString, os::raw::c_char, ptr};
 
 use super::QueueFamilyIndices;
+use crate::render::error::{RenderResult, VkResultExt};
+
+// static so that the pointer stays valid until the device is created
+static QUEUE_PRIORITIES: [f32; 1] = [1.0];
 
 fn get_queue_create_info(family_index: u32) -> vk::DeviceQueueCreateInfo {
-  let queue_priorities = [1.0_f32];
   vk::DeviceQueueCreateInfo {
     s_type: vk::StructureType::DEVICE_QUEUE_CREATE_INFO,
     queue_family_index: family_index,
     queue_count: 1,
-    p_queue_priorities: queue_priorities.as_ptr(),
+    p_queue_priorities: QUEUE_PRIORITIES.as_ptr(),
     p_next: ptr::null(),
     flags: vk::DeviceQueueCreateFlags::empty(),
   }
@@ -28,15 +31,18 @@ pub fn create_logical_device(
   device_features: &vk::PhysicalDeviceFeatures,
   device_extensions: &[String],
   family_indices: &QueueFamilyIndices,
-  #[cfg(feature = "vulkan_vl")] vl_pointers: &Vec<*const c_char>,
-) -> (ash::Device, Queues) {
+  #[cfg(feature = "vulkan_vl")] vl_pointers: &[*const c_char],
+) -> RenderResult<(ash::Device, Queues)> {
   let mut unique_queue_families = vec![family_indices.graphics, family_indices.compute];
   if let Some(transfer) = family_indices.transfer {
     unique_queue_families.push(transfer);
   }
+  // graphics and compute may share the same family
+  unique_queue_families.sort();
+  unique_queue_families.dedup();
   let queues_create_info: Vec<vk::DeviceQueueCreateInfo> = unique_queue_families
     .into_iter()
-    .map(|i| get_queue_create_info(i))
+    .map(get_queue_create_info)
     .collect();
 
   let device_extensions_c: Vec<CString> = device_extensions
@@ -53,7 +59,7 @@ pub fn create_logical_device(
     s_type: vk::StructureType::DEVICE_CREATE_INFO,
     p_queue_create_infos: queues_create_info.as_ptr(),
     queue_create_info_count: queues_create_info.len() as u32,
-    p_enabled_features: &*device_features,
+    p_enabled_features: device_features,
     p_next: ptr::null(),
     pp_enabled_layer_names: ptr::null(),
     enabled_layer_count: 0,
@@ -73,20 +79,18 @@ pub fn create_logical_device(
   let device: ash::Device = unsafe {
     instance
       .create_device(*physical_device, &create_info, None)
-      .expect("Failed to create logical device")
+      .context("Failed to create logical device")?
   };
 
   let queues = unsafe {
     Queues {
       graphics: device.get_device_queue(family_indices.graphics, 0),
       compute: device.get_device_queue(family_indices.compute, 0),
-      transfer: if let Some(transfer) = family_indices.transfer {
-        Some(device.get_device_queue(transfer, 0))
-      } else {
-        None
-      },
+      transfer: family_indices
+        .transfer
+        .map(|transfer| device.get_device_queue(transfer, 0)),
     }
   };
 
-  (device, queues)
+  Ok((device, queues))
 }
@@ -1,17 +1,28 @@
+mod allocator;
 mod buffers;
 mod command_buffer_pools;
 #[cfg(feature = "vulkan_vl")]
 mod debug_utils;
+mod depth;
 mod descriptor_sets;
 mod framebuffers;
+mod images;
+mod indirect;
 mod instance;
 mod logical_device;
+mod offscreen;
 mod physical_device;
+mod pipeline_cache;
 mod pipelines;
+mod readback;
 mod render_pass;
+mod render_target;
+mod retired;
 mod surface;
 mod surface_platforms;
 mod swapchain;
+mod textures;
+mod uploads;
 mod vertices;
 
 pub struct InstProperties {
@@ -20,17 +31,31 @@ pub struct InstProperties {
   pub model_i: usize,
 }
 
-pub use buffers::Buffers;
+pub use allocator::{Allocator, AllocatorStats};
+pub use buffers::{Buffers, ModelGeometry};
 pub use command_buffer_pools::CommandBufferPools;
 #[cfg(feature = "vulkan_vl")]
 pub use debug_utils::DebugUtils;
+pub use depth::DepthBuffer;
 pub use descriptor_sets::DescriptorSets;
 pub use framebuffers::create_framebuffers;
+pub use indirect::{IndirectDraw, IndirectDraws};
 pub use instance::create_instance;
 pub use logical_device::{create_logical_device, Queues};
-pub use physical_device::{select_physical_device, QueueFamilyIndices};
-pub use pipelines::Pipelines;
+pub use offscreen::OffscreenTarget;
+pub use physical_device::{
+  select_physical_device, DeviceIdentity, GpuOverride, QueueFamilyIndices,
+};
+pub use pipeline_cache::PipelineCache;
+#[cfg(test)]
+pub use pipelines::{instance_workgroup_count, INSTANCE_WORKGROUP_SIZE};
+pub use pipelines::{InstancePushConstants, Pipelines};
+pub use readback::ReadbackBuffer;
 pub use render_pass::create_render_pass;
+pub use render_target::RenderTarget;
+pub use retired::{RetiredResources, RetirementQueue};
 pub use surface::create_surface;
 pub use swapchain::Swapchains;
-pub use vertices::{ColorVertex, MatrixInstance, TexVertex, Vertex};
+pub use textures::{Texture, Textures};
+pub use uploads::{UploadId, Uploader};
+pub use vertices::{ColorVertex, MatrixInstance, RenderInstance, TexVertex, Vertex};
@@ -0,0 +1,56 @@
+use ash::vk;
+use log::info;
+
+use super::{
+  allocator::{Allocation, Allocator},
+  images::{create_image, create_image_view},
+};
+use crate::render::error::RenderResult;
+
+pub const OFFSCREEN_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+// single color image that is rendered to instead of a swapchain image
+// transfer source so that the result can be read back
+pub const OFFSCREEN_USAGE: vk::ImageUsageFlags = vk::ImageUsageFlags::from_raw(
+  vk::ImageUsageFlags::COLOR_ATTACHMENT.as_raw() | vk::ImageUsageFlags::TRANSFER_SRC.as_raw(),
+);
+
+pub struct OffscreenTarget {
+  pub image: vk::Image,
+  allocation: Allocation,
+  view: vk::ImageView,
+  pub format: vk::Format,
+  pub extent: vk::Extent2D,
+}
+
+impl OffscreenTarget {
+  pub fn create(
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    extent: vk::Extent2D,
+  ) -> RenderResult<Self> {
+    info!("Creating offscreen render target");
+    let format = OFFSCREEN_FORMAT;
+
+    let (image, allocation) = create_image(device, allocator, extent, format, OFFSCREEN_USAGE)?;
+    let view = create_image_view(device, image, format, vk::ImageAspectFlags::COLOR)?;
+
+    Ok(Self {
+      image,
+      allocation,
+      view,
+      format,
+      extent,
+    })
+  }
+
+  pub fn image_views(&self) -> &[vk::ImageView] {
+    std::slice::from_ref(&self.view)
+  }
+
+  pub unsafe fn destroy_self(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+    device.destroy_image_view(self.view, None);
+    device.destroy_image(self.image, None);
+    allocator.free(device, self.allocation);
+  }
+}
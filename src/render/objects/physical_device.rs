@@ -1,130 +1,223 @@
+use std::fmt;
+
 use ash::vk;
-use log::{debug, info};
+use log::{debug, info, warn};
 
-use crate::render::utility;
+use crate::render::{
+  error::{RenderError, RenderResult, VkResultExt},
+  utility,
+};
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct QueueFamilyIndices {
   pub graphics: u32,
   pub compute: u32,
   pub transfer: Option<u32>,
 }
 
+// identifies a device and its driver, data like pipeline caches is only valid for the same
+// identity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceIdentity {
+  pub vendor_id: u32,
+  pub device_id: u32,
+  pub driver_version: u32,
+  pub device_uuid: [u8; vk::UUID_SIZE],
+  pub pipeline_cache_uuid: [u8; vk::UUID_SIZE],
+}
+
+impl DeviceIdentity {
+  unsafe fn query(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Self {
+    let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+    let mut properties = vk::PhysicalDeviceProperties2::builder().push_next(&mut id_properties);
+    instance.get_physical_device_properties2(physical_device, &mut properties);
+    let properties = properties.properties;
+    Self {
+      vendor_id: properties.vendor_id,
+      device_id: properties.device_id,
+      driver_version: properties.driver_version,
+      device_uuid: id_properties.device_uuid,
+      pipeline_cache_uuid: properties.pipeline_cache_uuid,
+    }
+  }
+}
+
+// environment variable read by GpuOverride::from_env
+pub const GPU_OVERRIDE_ENV: &str = "ASH_GPU";
+
+// selects a specific physical device instead of the highest scoring one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GpuOverride {
+  // position in the physical device enumeration order, which includes unsuitable devices
+  Index(usize),
+  // case insensitive part of the device name
+  Name(String),
+}
+
+impl GpuOverride {
+  // numbers are taken as indices, anything else as a name
+  pub fn parse(arg: &str) -> Self {
+    match arg.parse() {
+      Ok(index) => GpuOverride::Index(index),
+      Err(_) => GpuOverride::Name(arg.to_owned()),
+    }
+  }
+
+  pub fn from_env() -> Option<Self> {
+    std::env::var(GPU_OVERRIDE_ENV)
+      .ok()
+      .filter(|value| !value.is_empty())
+      .map(|value| Self::parse(&value))
+  }
+
+  fn matches(&self, index: usize, name: &str) -> bool {
+    match self {
+      GpuOverride::Index(i) => *i == index,
+      GpuOverride::Name(part) => name.to_lowercase().contains(&part.to_lowercase()),
+    }
+  }
+}
+
+impl fmt::Display for GpuOverride {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      GpuOverride::Index(i) => write!(f, "index {}", i),
+      GpuOverride::Name(name) => write!(f, "name {:?}", name),
+    }
+  }
+}
+
+// devices are compared by type first, so that a discrete GPU is always preferred over an integrated
+// one, even if the latter reports more (shared) memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct DeviceScore {
+  device_type: u32,
+  // in bytes, of all device local heaps
+  vram: u64,
+  // number of supported features
+  features: u32,
+}
+
+impl DeviceScore {
+  fn new(
+    properties: &vk::PhysicalDeviceProperties,
+    memory: &vk::PhysicalDeviceMemoryProperties,
+    features: &vk::PhysicalDeviceFeatures,
+  ) -> Self {
+    let device_type = match properties.device_type {
+      vk::PhysicalDeviceType::DISCRETE_GPU => 4,
+      vk::PhysicalDeviceType::INTEGRATED_GPU => 3,
+      vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+      vk::PhysicalDeviceType::CPU => 1,
+      _ => 0,
+    };
+    let vram = memory.memory_heaps[0..(memory.memory_heap_count as usize)]
+      .iter()
+      .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+      .map(|heap| heap.size)
+      .sum();
+    let features = feature_flags(features)
+      .iter()
+      .filter(|&&flag| flag == vk::TRUE)
+      .count() as u32;
+    Self {
+      device_type,
+      vram,
+      features,
+    }
+  }
+}
+
+// a device that supports everything required
+struct Candidate {
+  index: usize,
+  name: String,
+  physical_device: vk::PhysicalDevice,
+  queue_families: QueueFamilyIndices,
+  score: DeviceScore,
+}
+
+// surface is None when rendering headlessly, in which case presentation support is not checked
+// the highest scoring device is selected, unless gpu_override selects one itself
 pub unsafe fn select_physical_device(
   instance: &ash::Instance,
-  surface: &vk::SurfaceKHR,
-  surface_loader: &ash::extensions::khr::Surface,
+  surface: Option<(vk::SurfaceKHR, &ash::extensions::khr::Surface)>,
   device_extensions: &[String],
   device_features: &vk::PhysicalDeviceFeatures,
-) -> (vk::PhysicalDevice, QueueFamilyIndices) {
-  let (physical_device, queue_family) = instance
+  gpu_override: Option<&GpuOverride>,
+) -> RenderResult<(vk::PhysicalDevice, QueueFamilyIndices, DeviceIdentity)> {
+  let candidates: Vec<Candidate> = instance
     .enumerate_physical_devices()
-    .expect("Failed to enumerate physical devices")
+    .context("Failed to enumerate physical devices")?
     .into_iter()
-    .filter(|p| {
-      let properties = instance.get_physical_device_properties(*p);
-      info!(
-        "
-Found physical device \"{}\":
-api_version: {},
-driver_version: {},
-vendor: {},
-device_id: {},
-device_type: {},
-limits: {:?}
-",
-        utility::c_char_array_to_string(&properties.device_name),
-        format!(
-          "{}.{}.{}",
-          vk::api_version_major(properties.api_version),
-          vk::api_version_minor(properties.api_version),
-          vk::api_version_patch(properties.api_version)
-        ),
-        properties.driver_version,
-        match properties.vendor_id {
-          0x1002 => "AMD".to_owned(),
-          0x1010 => "ImgTec".to_owned(),
-          0x10DE => "NVIDIA".to_owned(),
-          0x13B5 => "ARM".to_owned(),
-          0x5143 => "Qualcomm".to_owned(),
-          0x8086 => "INTEL".to_owned(),
-          other => format!("Unknown ({})", other),
-        },
-        properties.device_id,
-        match properties.device_type {
-          vk::PhysicalDeviceType::INTEGRATED_GPU => "Integrated GPU",
-          vk::PhysicalDeviceType::DISCRETE_GPU => "Discrete GPU",
-          vk::PhysicalDeviceType::VIRTUAL_GPU => "Virtual GPU",
-          vk::PhysicalDeviceType::CPU => "CPU",
-          _ => "Unknown",
-        },
-        properties.limits
-      );
-
-      is_superset(&instance.get_physical_device_features(*p), device_features)
-        && check_extension_support(instance, p, device_extensions)
-        && check_swapchain_support(p, surface, surface_loader)
-    })
-    .filter_map(|physical_device| {
-      let mut graphics = None;
-      let mut compute = None;
-      let mut transfer = None;
-      for (i, family) in instance
-        .get_physical_device_queue_family_properties(physical_device)
-        .iter()
-        .enumerate()
-      {
-        let supports_surface = unsafe {
-          surface_loader
-            .get_physical_device_surface_support(physical_device, i as u32, *surface)
-            .expect("Failed to query for physical device surface support")
-        };
-        if family.queue_flags.contains(vk::QueueFlags::GRAPHICS) && supports_surface {
-          graphics = Some(i as u32);
-        } else if family.queue_flags.contains(vk::QueueFlags::COMPUTE) {
-          compute = Some(i as u32)
-        } else if family.queue_flags.contains(vk::QueueFlags::TRANSFER) {
-          transfer = Some(i as u32);
-        }
-      }
+    .enumerate()
+    .filter_map(|(index, physical_device)| {
+      let properties = instance.get_physical_device_properties(physical_device);
+      let name = utility::c_char_array_to_string(&properties.device_name);
+      print_device_properties(index, &name, &properties);
 
-      if let Some(graphics) = graphics {
-        if let Some(compute) = compute {
-          Some((
-            physical_device,
-            QueueFamilyIndices {
-              graphics,
-              compute,
-              transfer,
-            },
-          ))
-        } else {
-          panic!("No available compute queue family found")
-        }
-      } else {
-        None
-      }
-    })
-    .min_by_key(|(physical_device, _)| {
-      let t = instance
-        .get_physical_device_properties(*physical_device)
-        .device_type;
-      if t == vk::PhysicalDeviceType::DISCRETE_GPU {
-        0
-      } else if t == vk::PhysicalDeviceType::INTEGRATED_GPU {
-        1
-      } else if t == vk::PhysicalDeviceType::VIRTUAL_GPU {
-        2
-      } else if t == vk::PhysicalDeviceType::CPU {
-        3
-      } else if t == vk::PhysicalDeviceType::OTHER {
-        4
-      } else {
-        panic!()
+      let features = instance.get_physical_device_features(physical_device);
+      let suitable = is_superset(&features, device_features)
+        && check_extension_support(instance, &physical_device, device_extensions)
+        && surface.is_none_or(|(surface, surface_loader)| {
+          check_swapchain_support(&physical_device, &surface, surface_loader)
+        });
+      if !suitable {
+        info!("Physical device {} \"{}\" is not suitable", index, name);
+        return None;
       }
+
+      let families = instance.get_physical_device_queue_family_properties(physical_device);
+      let queue_families = find_queue_families(&families, |i| {
+        surface.is_none_or(|(surface, surface_loader)| unsafe {
+          surface_loader
+            .get_physical_device_surface_support(physical_device, i, surface)
+            .unwrap_or(false)
+        })
+      });
+      let Some(queue_families) = queue_families else {
+        info!(
+          "Physical device {} \"{}\" doesn't have the required queue families",
+          index, name
+        );
+        return None;
+      };
+
+      let memory = instance.get_physical_device_memory_properties(physical_device);
+      let score = DeviceScore::new(&properties, &memory, &features);
+      info!("Physical device {} \"{}\" scored {:?}", index, name, score);
+      Some(Candidate {
+        index,
+        name,
+        physical_device,
+        queue_families,
+        score,
+      })
     })
-    .expect("no device available");
+    .collect();
 
+  let selected = match gpu_override {
+    Some(gpu) => candidates
+      .into_iter()
+      .find(|c| gpu.matches(c.index, &c.name))
+      .ok_or_else(|| RenderError::NoMatchingDevice(gpu.to_string()))?,
+    None => candidates
+      .into_iter()
+      // max_by_key returns the last maximum, ties are resolved by enumeration order instead
+      .rev()
+      .max_by_key(|c| c.score)
+      .ok_or(RenderError::NoSuitableDevice)?,
+  };
+  info!(
+    "Selected physical device {} \"{}\" with queue families {:?}",
+    selected.index, selected.name, selected.queue_families
+  );
+
+  let Candidate {
+    physical_device,
+    queue_families: queue_family,
+    ..
+  } = selected;
   if let Some(transfer) = queue_family.transfer {
     info!("Found an exclusive queue family for transfer operations with index {transfer}");
   } else {
@@ -132,7 +225,91 @@ limits: {:?}
   }
   print_debug_info(instance, physical_device);
 
-  (physical_device, queue_family)
+  let identity = DeviceIdentity::query(instance, physical_device);
+  Ok((physical_device, queue_family, identity))
+}
+
+// the first family of each kind is taken
+// graphics has to support presentation and is preferably also capable of compute, compute is
+// preferably a separate family but can share the graphics one (software implementations like
+// lavapipe only have a single queue family), transfer is only set if there's an exclusive family
+fn find_queue_families(
+  families: &[vk::QueueFamilyProperties],
+  supports_surface: impl Fn(u32) -> bool,
+) -> Option<QueueFamilyIndices> {
+  let find = |f: &dyn Fn(vk::QueueFlags) -> bool| {
+    families
+      .iter()
+      .position(|family| family.queue_count > 0 && f(family.queue_flags))
+      .map(|i| i as u32)
+  };
+  let graphics_compute = vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE;
+
+  let graphics = families
+    .iter()
+    .enumerate()
+    .filter(|(i, family)| {
+      family.queue_count > 0
+        && family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+        && supports_surface(*i as u32)
+    })
+    .min_by_key(|(_, family)| !family.queue_flags.contains(vk::QueueFlags::COMPUTE))
+    .map(|(i, _)| i as u32)?;
+  let compute = find(&|flags| {
+    flags.contains(vk::QueueFlags::COMPUTE) && !flags.contains(vk::QueueFlags::GRAPHICS)
+  })
+  .or_else(|| {
+    families[graphics as usize]
+      .queue_flags
+      .contains(vk::QueueFlags::COMPUTE)
+      .then_some(graphics)
+  })?;
+  let transfer =
+    find(&|flags| flags.contains(vk::QueueFlags::TRANSFER) && !flags.intersects(graphics_compute));
+
+  Some(QueueFamilyIndices {
+    graphics,
+    compute,
+    transfer,
+  })
+}
+
+fn print_device_properties(index: usize, name: &str, properties: &vk::PhysicalDeviceProperties) {
+  info!(
+    "
+Found physical device {} \"{}\":
+api_version: {}.{}.{},
+driver_version: {},
+vendor: {},
+device_id: {},
+device_type: {},
+limits: {:?}
+",
+    index,
+    name,
+    vk::api_version_major(properties.api_version),
+    vk::api_version_minor(properties.api_version),
+    vk::api_version_patch(properties.api_version),
+    properties.driver_version,
+    match properties.vendor_id {
+      0x1002 => "AMD".to_owned(),
+      0x1010 => "ImgTec".to_owned(),
+      0x10DE => "NVIDIA".to_owned(),
+      0x13B5 => "ARM".to_owned(),
+      0x5143 => "Qualcomm".to_owned(),
+      0x8086 => "INTEL".to_owned(),
+      other => format!("Unknown ({})", other),
+    },
+    properties.device_id,
+    match properties.device_type {
+      vk::PhysicalDeviceType::INTEGRATED_GPU => "Integrated GPU",
+      vk::PhysicalDeviceType::DISCRETE_GPU => "Discrete GPU",
+      vk::PhysicalDeviceType::VIRTUAL_GPU => "Virtual GPU",
+      vk::PhysicalDeviceType::CPU => "CPU",
+      _ => "Unknown",
+    },
+    properties.limits
+  );
 }
 
 fn print_debug_info(instance: &ash::Instance, physical_device: vk::PhysicalDevice) {
@@ -161,9 +338,22 @@ fn print_debug_info(instance: &ash::Instance, physical_device: vk::PhysicalDevic
   }
 }
 
-fn is_superset(_a: &vk::PhysicalDeviceFeatures, _b: &vk::PhysicalDeviceFeatures) -> bool {
-  // todo
-  true
+// vk::PhysicalDeviceFeatures only consists of vk::Bool32 fields
+fn feature_flags(features: &vk::PhysicalDeviceFeatures) -> &[vk::Bool32] {
+  unsafe {
+    std::slice::from_raw_parts(
+      (features as *const vk::PhysicalDeviceFeatures) as *const vk::Bool32,
+      std::mem::size_of::<vk::PhysicalDeviceFeatures>() / std::mem::size_of::<vk::Bool32>(),
+    )
+  }
+}
+
+// whether a supports every feature enabled in b
+fn is_superset(a: &vk::PhysicalDeviceFeatures, b: &vk::PhysicalDeviceFeatures) -> bool {
+  feature_flags(a)
+    .iter()
+    .zip(feature_flags(b))
+    .all(|(&a, &b)| a == vk::TRUE || b != vk::TRUE)
 }
 
 fn check_extension_support(
@@ -171,21 +361,22 @@ fn check_extension_support(
   device: &vk::PhysicalDevice,
   extensions: &[String],
 ) -> bool {
-  let available_extensions = unsafe {
-    instance
-      .enumerate_device_extension_properties(*device)
-      .expect("Failed to get device extension properties.")
-  };
+  // devices that fail to report their extensions are skipped
+  let available_extensions =
+    match unsafe { instance.enumerate_device_extension_properties(*device) } {
+      Ok(extensions) => extensions,
+      Err(err) => {
+        warn!("Failed to get device extension properties: {}", err);
+        return false;
+      }
+    };
 
   let mut available_extensions: Vec<String> = available_extensions
     .into_iter()
     .map(|prop| utility::c_char_array_to_string(&prop.extension_name))
     .collect();
 
-  match utility::contains_all(&mut available_extensions, extensions) {
-    Ok(_) => true,
-    Err(_) => false,
-  }
+  utility::contains_all(&mut available_extensions, extensions).is_ok()
 }
 
 unsafe fn check_swapchain_support(
@@ -193,11 +384,135 @@ unsafe fn check_swapchain_support(
   surface: &vk::SurfaceKHR,
   surface_loader: &ash::extensions::khr::Surface,
 ) -> bool {
-  let formats = surface_loader
-    .get_physical_device_surface_formats(*device, *surface)
-    .expect("Failed to query for surface formats.");
-  let present_modes = surface_loader
-    .get_physical_device_surface_present_modes(*device, *surface)
-    .expect("Failed to query for surface present mode.");
-  !formats.is_empty() && !present_modes.is_empty()
+  let formats = surface_loader.get_physical_device_surface_formats(*device, *surface);
+  let present_modes = surface_loader.get_physical_device_surface_present_modes(*device, *surface);
+  match (formats, present_modes) {
+    (Ok(formats), Ok(present_modes)) => !formats.is_empty() && !present_modes.is_empty(),
+    (Err(err), _) | (_, Err(err)) => {
+      warn!("Failed to query for surface support: {}", err);
+      false
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn family(queue_flags: vk::QueueFlags) -> vk::QueueFamilyProperties {
+    vk::QueueFamilyProperties {
+      queue_flags,
+      queue_count: 1,
+      ..Default::default()
+    }
+  }
+
+  fn score(device_type: vk::PhysicalDeviceType, vram: u64) -> DeviceScore {
+    let properties = vk::PhysicalDeviceProperties {
+      device_type,
+      ..Default::default()
+    };
+    let mut memory = vk::PhysicalDeviceMemoryProperties {
+      memory_heap_count: 2,
+      ..Default::default()
+    };
+    memory.memory_heaps[0] = vk::MemoryHeap {
+      size: vram,
+      flags: vk::MemoryHeapFlags::DEVICE_LOCAL,
+    };
+    // host memory is not counted
+    memory.memory_heaps[1] = vk::MemoryHeap {
+      size: 1 << 40,
+      flags: vk::MemoryHeapFlags::empty(),
+    };
+    DeviceScore::new(&properties, &memory, &vk::PhysicalDeviceFeatures::default())
+  }
+
+  #[test]
+  fn first_matching_queue_families_are_taken() {
+    let graphics = vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER;
+    let compute = vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER;
+    let families = [
+      family(graphics),
+      family(compute),
+      family(compute),
+      family(vk::QueueFlags::TRANSFER),
+      family(vk::QueueFlags::TRANSFER),
+    ];
+    assert_eq!(
+      find_queue_families(&families, |_| true),
+      Some(QueueFamilyIndices {
+        graphics: 0,
+        compute: 1,
+        transfer: Some(3),
+      })
+    );
+  }
+
+  #[test]
+  fn compute_can_share_the_graphics_family() {
+    let families = [family(
+      vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER,
+    )];
+    assert_eq!(
+      find_queue_families(&families, |_| true),
+      Some(QueueFamilyIndices {
+        graphics: 0,
+        compute: 0,
+        transfer: None,
+      })
+    );
+  }
+
+  #[test]
+  fn graphics_family_has_to_support_presentation() {
+    let flags = vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE;
+    let families = [family(flags), family(flags)];
+    assert_eq!(
+      find_queue_families(&families, |i| i == 1).map(|f| f.graphics),
+      Some(1)
+    );
+    assert_eq!(find_queue_families(&families, |_| false), None);
+  }
+
+  #[test]
+  fn discrete_gpus_are_preferred() {
+    let discrete = score(vk::PhysicalDeviceType::DISCRETE_GPU, 1 << 30);
+    let integrated = score(vk::PhysicalDeviceType::INTEGRATED_GPU, 16 << 30);
+    let cpu = score(vk::PhysicalDeviceType::CPU, 0);
+    assert!(discrete > integrated);
+    assert!(integrated > cpu);
+    assert_eq!(integrated.vram, 16 << 30);
+    assert!(score(vk::PhysicalDeviceType::DISCRETE_GPU, 8 << 30) > discrete);
+  }
+
+  #[test]
+  fn required_features_have_to_be_supported() {
+    let required = vk::PhysicalDeviceFeatures {
+      multi_draw_indirect: vk::TRUE,
+      ..Default::default()
+    };
+    let supported = vk::PhysicalDeviceFeatures {
+      multi_draw_indirect: vk::TRUE,
+      geometry_shader: vk::TRUE,
+      ..Default::default()
+    };
+    assert!(is_superset(&supported, &required));
+    assert!(!is_superset(&required, &supported));
+    assert!(!is_superset(
+      &vk::PhysicalDeviceFeatures::default(),
+      &required
+    ));
+  }
+
+  #[test]
+  fn gpu_override_by_index_or_name() {
+    assert_eq!(GpuOverride::parse("1"), GpuOverride::Index(1));
+    let name = GpuOverride::parse("geforce");
+    assert_eq!(name, GpuOverride::Name("geforce".to_owned()));
+    assert!(name.matches(0, "NVIDIA GeForce RTX 3060"));
+    assert!(!name.matches(0, "llvmpipe (LLVM 15.0.7, 256 bits)"));
+    assert!(GpuOverride::Index(1).matches(1, "llvmpipe"));
+    assert!(!GpuOverride::Index(1).matches(0, "llvmpipe"));
+  }
 }
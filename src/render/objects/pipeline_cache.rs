@@ -0,0 +1,219 @@
+use std::{
+  ffi::c_void,
+  path::{Path, PathBuf},
+  ptr,
+};
+
+use ash::vk;
+use log::{info, warn};
+
+use super::DeviceIdentity;
+use crate::render::error::{RenderResult, VkResultExt};
+
+// relative to the user cache directory
+const CACHE_FILE: &str = "ash_boilerplate/pipeline_cache.bin";
+
+// the file starts with its own header, followed by the data returned by the driver
+const MAGIC: [u8; 4] = *b"ABPC";
+const FILE_HEADER_SIZE: usize = MAGIC.len() + vk::UUID_SIZE + 4;
+// VkPipelineCacheHeaderVersionOne
+const VK_HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+// pipelines created through the cache get reused across runs by saving it to the user cache
+// directory when the renderer is dropped
+pub struct PipelineCache {
+  pub cache: vk::PipelineCache,
+  path: Option<PathBuf>,
+  identity: DeviceIdentity,
+}
+
+impl PipelineCache {
+  pub fn load(device: &ash::Device, identity: DeviceIdentity) -> RenderResult<Self> {
+    let path = dirs::cache_dir().map(|dir| dir.join(CACHE_FILE));
+    if path.is_none() {
+      warn!("No user cache directory found, the pipeline cache will not be persisted");
+    }
+    let data = path
+      .as_deref()
+      .and_then(|path| read_cache_file(path, &identity));
+
+    let initial_data = data.as_deref().unwrap_or(&[]);
+    let create_info = vk::PipelineCacheCreateInfo {
+      s_type: vk::StructureType::PIPELINE_CACHE_CREATE_INFO,
+      p_next: ptr::null(),
+      flags: vk::PipelineCacheCreateFlags::empty(),
+      initial_data_size: initial_data.len(),
+      p_initial_data: initial_data.as_ptr() as *const c_void,
+    };
+    let cache = unsafe {
+      device
+        .create_pipeline_cache(&create_info, None)
+        .context("Failed to create pipeline cache")?
+    };
+
+    Ok(Self {
+      cache,
+      path,
+      identity,
+    })
+  }
+
+  fn save(&self, device: &ash::Device) {
+    let Some(path) = &self.path else {
+      return;
+    };
+    let data = match unsafe { device.get_pipeline_cache_data(self.cache) } {
+      Ok(data) => data,
+      Err(err) => {
+        warn!("Failed to get pipeline cache data: {}", err);
+        return;
+      }
+    };
+
+    let mut file = file_header(&self.identity);
+    file.extend_from_slice(&data);
+    // written to a temporary file first so that an interrupted write can't leave a corrupt cache
+    let tmp_path = path.with_extension("tmp");
+    let result = path
+      .parent()
+      .map_or(Ok(()), std::fs::create_dir_all)
+      .and_then(|_| std::fs::write(&tmp_path, &file))
+      .and_then(|_| std::fs::rename(&tmp_path, path));
+    match result {
+      Ok(()) => info!("Saved {} bytes of pipeline cache to {:?}", file.len(), path),
+      Err(err) => warn!("Failed to save pipeline cache to {:?}: {}", path, err),
+    }
+  }
+
+  pub unsafe fn destroy_self(&mut self, device: &ash::Device) {
+    self.save(device);
+    device.destroy_pipeline_cache(self.cache, None);
+  }
+}
+
+fn read_cache_file(path: &Path, identity: &DeviceIdentity) -> Option<Vec<u8>> {
+  let file = match std::fs::read(path) {
+    Ok(file) => file,
+    Err(err) => {
+      info!("No pipeline cache loaded from {:?}: {}", path, err);
+      return None;
+    }
+  };
+  match cache_data(&file, identity) {
+    Some(data) => {
+      info!(
+        "Loaded {} bytes of pipeline cache from {:?}",
+        data.len(),
+        path
+      );
+      Some(data.to_vec())
+    }
+    None => {
+      info!(
+        "Ignoring pipeline cache at {:?} made by another device or driver",
+        path
+      );
+      None
+    }
+  }
+}
+
+fn file_header(identity: &DeviceIdentity) -> Vec<u8> {
+  let mut header = Vec::with_capacity(FILE_HEADER_SIZE);
+  header.extend_from_slice(&MAGIC);
+  header.extend_from_slice(&identity.device_uuid);
+  header.extend_from_slice(&identity.driver_version.to_le_bytes());
+  header
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+  u32::from_le_bytes(bytes[offset..(offset + 4)].try_into().unwrap())
+}
+
+// returns the driver data of a cache file, if it was created by the same device and driver
+fn cache_data<'a>(file: &'a [u8], identity: &DeviceIdentity) -> Option<&'a [u8]> {
+  if file.len() < FILE_HEADER_SIZE || file[..FILE_HEADER_SIZE] != file_header(identity) {
+    return None;
+  }
+
+  // the driver should reject incompatible data by itself, but not all of them do
+  let data = &file[FILE_HEADER_SIZE..];
+  if data.len() < VK_HEADER_SIZE {
+    return None;
+  }
+  let header_size = read_u32(data, 0) as usize;
+  let header_version = read_u32(data, 4);
+  let valid = header_size >= VK_HEADER_SIZE
+    && header_version == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+    && read_u32(data, 8) == identity.vendor_id
+    && read_u32(data, 12) == identity.device_id
+    && data[16..VK_HEADER_SIZE] == identity.pipeline_cache_uuid;
+  valid.then_some(data)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn identity() -> DeviceIdentity {
+    DeviceIdentity {
+      vendor_id: 0x10DE,
+      device_id: 42,
+      driver_version: 7,
+      device_uuid: [1; vk::UUID_SIZE],
+      pipeline_cache_uuid: [2; vk::UUID_SIZE],
+    }
+  }
+
+  fn cache_file(file_identity: &DeviceIdentity, vk_identity: &DeviceIdentity) -> Vec<u8> {
+    let mut file = file_header(file_identity);
+    file.extend_from_slice(&(VK_HEADER_SIZE as u32).to_le_bytes());
+    file.extend_from_slice(&(vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32).to_le_bytes());
+    file.extend_from_slice(&vk_identity.vendor_id.to_le_bytes());
+    file.extend_from_slice(&vk_identity.device_id.to_le_bytes());
+    file.extend_from_slice(&vk_identity.pipeline_cache_uuid);
+    file.extend_from_slice(&[9, 9, 9]);
+    file
+  }
+
+  #[test]
+  fn accepts_cache_of_the_same_device() {
+    let file = cache_file(&identity(), &identity());
+    let data = cache_data(&file, &identity()).unwrap();
+    assert_eq!(data.len(), VK_HEADER_SIZE + 3);
+    assert_eq!(&data[VK_HEADER_SIZE..], [9, 9, 9]);
+  }
+
+  #[test]
+  fn rejects_another_device_or_driver() {
+    let other_device = DeviceIdentity {
+      device_uuid: [3; vk::UUID_SIZE],
+      ..identity()
+    };
+    let other_driver = DeviceIdentity {
+      driver_version: 8,
+      ..identity()
+    };
+    for other in [other_device, other_driver] {
+      let file = cache_file(&other, &identity());
+      assert!(cache_data(&file, &identity()).is_none());
+    }
+  }
+
+  #[test]
+  fn rejects_mismatched_driver_header() {
+    let other = DeviceIdentity {
+      pipeline_cache_uuid: [3; vk::UUID_SIZE],
+      ..identity()
+    };
+    let file = cache_file(&identity(), &other);
+    assert!(cache_data(&file, &identity()).is_none());
+  }
+
+  #[test]
+  fn rejects_truncated_files() {
+    let file = cache_file(&identity(), &identity());
+    assert!(cache_data(&file[..FILE_HEADER_SIZE + 8], &identity()).is_none());
+    assert!(cache_data(&file[..2], &identity()).is_none());
+  }
+}
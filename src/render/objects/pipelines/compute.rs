@@ -3,7 +3,32 @@ use std::{ffi::CString, ptr};
 use ash::vk;
 use cgmath::Matrix4;
 
-use crate::render::{objects::DescriptorSets, shaders::ComputeShaders};
+use crate::render::{
+  error::{PipelineResultExt, RenderResult, VkResultExt},
+  objects::DescriptorSets,
+  shaders::{ComputeShaders, PipelineInterface, ShaderCode},
+};
+
+// see PushConstantData in instance.comp
+#[repr(C)]
+pub struct InstancePushConstants {
+  pub projection_view: Matrix4<f32>,
+  // amount of input instances, the dispatch can have more invocations
+  pub inst_count: u32,
+  // index of the first processed instance in the transformed instance buffer
+  pub inst_offset: u32,
+  // range of draws the processed instances belong to
+  pub draw_offset: u32,
+  pub draw_count: u32,
+}
+
+// local_size_x of instance.comp
+pub const INSTANCE_WORKGROUP_SIZE: u32 = 64;
+
+// amount of workgroups needed for one invocation per instance
+pub fn instance_workgroup_count(inst_count: u32) -> u32 {
+  inst_count.div_ceil(INSTANCE_WORKGROUP_SIZE)
+}
 
 pub struct ComputePipelines {
   pub layout: vk::PipelineLayout,
@@ -11,8 +36,27 @@ pub struct ComputePipelines {
 }
 
 impl ComputePipelines {
-  pub fn create(device: &ash::Device, descriptor_sets: &DescriptorSets) -> Self {
-    let mut shaders = ComputeShaders::load(device);
+  pub fn create(
+    device: &ash::Device,
+    descriptor_sets: &DescriptorSets,
+    cache: vk::PipelineCache,
+    shader_code: &ShaderCode,
+  ) -> RenderResult<Self> {
+    let push_constant_ranges = [vk::PushConstantRange {
+      stage_flags: vk::ShaderStageFlags::COMPUTE,
+      offset: 0,
+      size: std::mem::size_of::<InstancePushConstants>() as u32,
+    }];
+    ComputeShaders::check(
+      shader_code,
+      &PipelineInterface {
+        set_layouts: &[&descriptor_sets.layouts.inst.bindings],
+        push_constant_ranges: &push_constant_ranges,
+        vertex_attributes: &[],
+      },
+    )?;
+
+    let mut shaders = ComputeShaders::load(device, shader_code)?;
     let main_function_name = CString::new("main").unwrap(); // the beginning function name in shader code.
 
     let stage = vk::PipelineShaderStageCreateInfo {
@@ -25,11 +69,6 @@ impl ComputePipelines {
       stage: vk::ShaderStageFlags::COMPUTE,
     };
 
-    let push_constant_ranges = [vk::PushConstantRange {
-      stage_flags: vk::ShaderStageFlags::COMPUTE,
-      offset: 0,
-      size: std::mem::size_of::<Matrix4<f32>>() as u32,
-    }];
     let set_layouts = [descriptor_sets.layouts.inst.layout];
     let layout_create_info = vk::PipelineLayoutCreateInfo {
       s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
@@ -44,8 +83,11 @@ impl ComputePipelines {
     let layout = unsafe {
       device
         .create_pipeline_layout(&layout_create_info, None)
-        .expect("Failed to create pipeline layout!")
+        .context("Failed to create pipeline layout")
     };
+    let layout = layout.inspect_err(|_| {
+      unsafe { shaders.destroy_self(device) };
+    })?;
 
     let create_infos = [vk::ComputePipelineCreateInfo {
       s_type: vk::StructureType::COMPUTE_PIPELINE_CREATE_INFO,
@@ -59,19 +101,22 @@ impl ComputePipelines {
 
     let pipelines = unsafe {
       device
-        .create_compute_pipelines(vk::PipelineCache::null(), &create_infos, None)
-        .expect("Failed to create compute pipelines")
+        .create_compute_pipelines(cache, &create_infos, None)
+        .destroy_created_on_err(device)
+        .context("Failed to create compute pipelines")
     };
-    let mut pipelines_iter = pipelines.into_iter();
 
     unsafe {
       shaders.destroy_self(device);
     }
+    let pipelines = pipelines.inspect_err(|_| {
+      unsafe { device.destroy_pipeline_layout(layout, None) };
+    })?;
 
-    Self {
+    Ok(Self {
       layout,
-      inst: pipelines_iter.next().unwrap(),
-    }
+      inst: pipelines[0],
+    })
   }
 
   pub unsafe fn destroy_self(&mut self, device: &ash::Device) {
@@ -1,17 +1,17 @@
-use std::ptr;
+use std::{ffi::c_void, ptr};
 
 use ash::vk;
 
 use crate::render::{
+  error::{PipelineResultExt, RenderResult, VkResultExt},
   objects::{
     vertices::{
       enumerate_attribute_descriptions, enumerate_binding_descriptions,
       get_pipeline_vertex_input_state_ci,
     },
-    ColorVertex, Vertex,
+    ColorVertex, DescriptorSets, RenderInstance, TexVertex, Vertex,
   },
-  shaders::{self, GraphicsShader},
-  MatrixInstance,
+  shaders::{self, GraphicsShader, PipelineInterface, ShaderCode},
 };
 
 use super::get_no_multisample_state_ci;
@@ -19,49 +19,110 @@ use super::get_no_multisample_state_ci;
 pub struct GraphicsPipelines {
   pub layout: vk::PipelineLayout,
   pub main: vk::Pipeline,
+  // textured models, uses the texture array descriptor set
+  pub tex_layout: vk::PipelineLayout,
+  pub tex: vk::Pipeline,
 }
 
 impl GraphicsPipelines {
   pub fn create(
     device: &ash::Device,
-    swapchain_extent: vk::Extent2D,
     render_pass: vk::RenderPass,
-  ) -> Self {
+    descriptor_sets: &DescriptorSets,
+    cache: vk::PipelineCache,
+    shader_code: &ShaderCode,
+  ) -> RenderResult<Self> {
     // Note: for some reason rust distinguishes between "_" and "_named" for variables that are created
     // but not used. In my tests, it seems that "_" are dropped right away while "_named" stay until the
     // end of scope. This is really important because most creation info have pointers (not references) to
     // other objects which should only be destroyed after these pointers get used (as to not make them dangling)
 
-    let mut shader = shaders::plain::Shader::load(device);
+    let (vertex_input_state_ci, _binding_descriptions, attribute_descriptions) =
+      get_pipeline_vertex_input_state_ci!(ColorVertex, RenderInstance,);
+    let (tex_vertex_input_state_ci, _tex_binding_descriptions, tex_attribute_descriptions) =
+      get_pipeline_vertex_input_state_ci!(TexVertex, RenderInstance,);
+
+    let no_resources = PipelineInterface {
+      set_layouts: &[],
+      push_constant_ranges: &[],
+      vertex_attributes: &[],
+    };
+    shaders::plain::Shader::check(
+      shader_code,
+      &PipelineInterface {
+        vertex_attributes: &attribute_descriptions,
+        ..no_resources
+      },
+      &no_resources,
+    )?;
+    let tex_set_layouts: [&[vk::DescriptorSetLayoutBinding]; 1] =
+      [&descriptor_sets.layouts.texture.bindings];
+    shaders::tex_plain::Shader::check(
+      shader_code,
+      &PipelineInterface {
+        set_layouts: &tex_set_layouts,
+        vertex_attributes: &tex_attribute_descriptions,
+        ..no_resources
+      },
+      &PipelineInterface {
+        set_layouts: &tex_set_layouts,
+        ..no_resources
+      },
+    )?;
+
+    let mut shader = shaders::plain::Shader::load(device, shader_code)?;
     let (shader_stages, _shader_func_name) = shader.get_pipeline_shader_creation_info();
+    let mut tex_shader =
+      shaders::tex_plain::Shader::load(device, shader_code).inspect_err(|_| {
+        unsafe { shader.destroy_self(device) };
+      })?;
+    let (mut tex_shader_stages, _tex_shader_func_name) =
+      tex_shader.get_pipeline_shader_creation_info();
+    // size of the texture array in the fragment shader
+    let texture_count = descriptor_sets.layouts.texture.descriptor_count as u32;
+    let tex_spec_entries = [vk::SpecializationMapEntry {
+      constant_id: 0,
+      offset: 0,
+      size: std::mem::size_of::<u32>(),
+    }];
+    let tex_spec_info = vk::SpecializationInfo {
+      map_entry_count: tex_spec_entries.len() as u32,
+      p_map_entries: tex_spec_entries.as_ptr(),
+      data_size: std::mem::size_of::<u32>(),
+      p_data: &texture_count as *const u32 as *const c_void,
+    };
+    tex_shader_stages[1].p_specialization_info = &tex_spec_info;
 
-    let (vertex_input_state_ci, _binding_descriptions, _attribute_descriptions) =
-      get_pipeline_vertex_input_state_ci!(ColorVertex, MatrixInstance,);
     let input_assembly_state_ci = super::get_default_input_assembly_state_ci();
 
-    let (viewport_state_ci, _viewport, _scissor) = super::get_viewport_state_ci(swapchain_extent);
-    let rasterization_state_ci = super::get_no_depth_rasterization_state_ci();
+    let viewport_state_ci = super::get_dynamic_viewport_state_ci();
+    let dynamic_state_ci = super::get_dynamic_state_ci();
+    let rasterization_state_ci = super::get_rasterization_state_ci();
     let multisample_state_ci = get_no_multisample_state_ci();
-
-    let (color_blend_state_ci, _color_blend_attachment) = super::get_no_blend_color_blend_state_ci();
-
-    let layout_create_info = vk::PipelineLayoutCreateInfo {
-      s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
-      p_next: ptr::null(),
-      flags: vk::PipelineLayoutCreateFlags::empty(),
-      set_layout_count: 0,
-      p_set_layouts: ptr::null(),
-      push_constant_range_count: 0,
-      p_push_constant_ranges: ptr::null(),
+    let depth_stencil_state_ci = super::get_depth_stencil_state_ci();
+
+    let (color_blend_state_ci, _color_blend_attachment) =
+      super::get_no_blend_color_blend_state_ci();
+
+    let layouts = create_layout(device, &[]).and_then(|layout| {
+      create_layout(device, &[descriptor_sets.layouts.texture.layout])
+        .map(|tex_layout| (layout, tex_layout))
+        .inspect_err(|_| {
+          unsafe { device.destroy_pipeline_layout(layout, None) };
+        })
+    });
+    let (layout, tex_layout) = match layouts {
+      Ok(layouts) => layouts,
+      Err(err) => {
+        unsafe {
+          shader.destroy_self(device);
+          tex_shader.destroy_self(device);
+        }
+        return Err(err);
+      }
     };
 
-    let layout = unsafe {
-      device
-        .create_pipeline_layout(&layout_create_info, None)
-        .expect("Failed to create pipeline layout")
-    };
-
-    let create_infos = [vk::GraphicsPipelineCreateInfo {
+    let base_create_info = vk::GraphicsPipelineCreateInfo {
       s_type: vk::StructureType::GRAPHICS_PIPELINE_CREATE_INFO,
       p_next: ptr::null(),
       flags: vk::PipelineCreateFlags::empty(),
@@ -73,34 +134,72 @@ impl GraphicsPipelines {
       p_viewport_state: &viewport_state_ci,
       p_rasterization_state: &rasterization_state_ci,
       p_multisample_state: &multisample_state_ci,
-      p_depth_stencil_state: ptr::null(),
+      p_depth_stencil_state: &depth_stencil_state_ci,
       p_color_blend_state: &color_blend_state_ci,
-      p_dynamic_state: ptr::null(),
+      p_dynamic_state: &dynamic_state_ci,
       layout,
       render_pass,
       subpass: 0,
       base_pipeline_handle: vk::Pipeline::null(),
       base_pipeline_index: -1,
-    }];
+    };
+    let mut tex_create_info = base_create_info;
+    tex_create_info.stage_count = tex_shader_stages.len() as u32;
+    tex_create_info.p_stages = tex_shader_stages.as_ptr();
+    tex_create_info.p_vertex_input_state = &tex_vertex_input_state_ci;
+    tex_create_info.layout = tex_layout;
+
+    let create_infos = [base_create_info, tex_create_info];
 
     let pipelines = unsafe {
       device
-        .create_graphics_pipelines(vk::PipelineCache::null(), &create_infos, None)
-        .expect("Failed to create graphics pipelines")
+        .create_graphics_pipelines(cache, &create_infos, None)
+        .destroy_created_on_err(device)
+        .context("Failed to create graphics pipelines")
     };
 
     unsafe {
       shader.destroy_self(device);
+      tex_shader.destroy_self(device);
     }
+    let pipelines = pipelines.inspect_err(|_| unsafe {
+      device.destroy_pipeline_layout(layout, None);
+      device.destroy_pipeline_layout(tex_layout, None);
+    })?;
 
-    Self {
+    Ok(Self {
       layout,
       main: pipelines[0],
-    }
+      tex_layout,
+      tex: pipelines[1],
+    })
   }
 
   pub unsafe fn destroy_self(&mut self, device: &ash::Device) {
     device.destroy_pipeline(self.main, None);
+    device.destroy_pipeline(self.tex, None);
     device.destroy_pipeline_layout(self.layout, None);
+    device.destroy_pipeline_layout(self.tex_layout, None);
+  }
+}
+
+fn create_layout(
+  device: &ash::Device,
+  set_layouts: &[vk::DescriptorSetLayout],
+) -> RenderResult<vk::PipelineLayout> {
+  let layout_create_info = vk::PipelineLayoutCreateInfo {
+    s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
+    p_next: ptr::null(),
+    flags: vk::PipelineLayoutCreateFlags::empty(),
+    set_layout_count: set_layouts.len() as u32,
+    p_set_layouts: set_layouts.as_ptr(),
+    push_constant_range_count: 0,
+    p_push_constant_ranges: ptr::null(),
+  };
+
+  unsafe {
+    device
+      .create_pipeline_layout(&layout_create_info, None)
+      .context("Failed to create pipeline layout")
   }
 }
@@ -8,9 +8,13 @@ use std::{
 
 use ash::vk;
 
-use self::{compute::ComputePipelines, graphics::GraphicsPipelines};
+#[cfg(test)]
+pub use compute::INSTANCE_WORKGROUP_SIZE;
+pub use compute::{instance_workgroup_count, ComputePipelines, InstancePushConstants};
+pub use graphics::GraphicsPipelines;
 
 use super::DescriptorSets;
+use crate::render::{error::RenderResult, shaders::ShaderCode};
 
 pub struct Pipelines {
   pub graphics: GraphicsPipelines,
@@ -20,27 +24,47 @@ pub struct Pipelines {
 impl Pipelines {
   pub fn new(
     device: &ash::Device,
-    swapchain_extent: vk::Extent2D,
     render_pass: vk::RenderPass,
     descriptor_sets: &DescriptorSets,
-  ) -> Self {
-    let graphics = GraphicsPipelines::create(device, swapchain_extent, render_pass);
-    let compute = ComputePipelines::create(device, descriptor_sets);
-
-    Self { graphics, compute }
+    cache: vk::PipelineCache,
+    shader_code: &ShaderCode,
+  ) -> RenderResult<Self> {
+    let mut graphics =
+      GraphicsPipelines::create(device, render_pass, descriptor_sets, cache, shader_code)?;
+    let compute = ComputePipelines::create(device, descriptor_sets, cache, shader_code)
+      .inspect_err(|_| {
+        unsafe { graphics.destroy_self(device) };
+      })?;
+
+    Ok(Self { graphics, compute })
   }
 
+  // only needed when the render pass changes, as the viewport and scissor are dynamic
+  // returns the replaced pipelines, which should be destroyed once no frame uses them
+  // the current pipelines are kept if creating the new ones fails
   pub fn recreate_main(
     &mut self,
     device: &ash::Device,
-    swapchain_extent: vk::Extent2D,
     render_pass: vk::RenderPass,
-  ) {
-    // TODO: reimplement with pipeline cache
-    unsafe {
-      self.graphics.destroy_self(device);
-    }
-    self.graphics = GraphicsPipelines::create(device, swapchain_extent, render_pass);
+    descriptor_sets: &DescriptorSets,
+    cache: vk::PipelineCache,
+    shader_code: &ShaderCode,
+  ) -> RenderResult<GraphicsPipelines> {
+    let graphics =
+      GraphicsPipelines::create(device, render_pass, descriptor_sets, cache, shader_code)?;
+    Ok(std::mem::replace(&mut self.graphics, graphics))
+  }
+
+  // returns the replaced pipelines, which should be destroyed once no frame uses them
+  pub fn recreate_compute(
+    &mut self,
+    device: &ash::Device,
+    descriptor_sets: &DescriptorSets,
+    cache: vk::PipelineCache,
+    shader_code: &ShaderCode,
+  ) -> RenderResult<ComputePipelines> {
+    let compute = ComputePipelines::create(device, descriptor_sets, cache, shader_code)?;
+    Ok(std::mem::replace(&mut self.compute, compute))
   }
 
   pub unsafe fn destroy_self(&mut self, device: &ash::Device) {
@@ -59,42 +83,33 @@ fn get_default_input_assembly_state_ci() -> vk::PipelineInputAssemblyStateCreate
   }
 }
 
-fn get_viewport_state_ci(
-  swapchain_extent: vk::Extent2D,
-) -> (
-  vk::PipelineViewportStateCreateInfo,
-  Pin<Box<vk::Viewport>>,
-  Pin<Box<vk::Rect2D>>,
-) {
-  // Make sure viewport and scissor location not change
-  let viewport = Box::pin(vk::Viewport {
-    x: 0.0,
-    y: 0.0,
-    width: swapchain_extent.width as f32,
-    height: swapchain_extent.height as f32,
-    min_depth: 0.0,
-    max_depth: 1.0,
-  });
-
-  let scissor = Box::pin(vk::Rect2D {
-    offset: vk::Offset2D { x: 0, y: 0 },
-    extent: swapchain_extent,
-  });
+// viewport and scissor are set while recording, so pipelines don't depend on the target extent
+static DYNAMIC_STATES: [vk::DynamicState; 2] =
+  [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
 
-  let viewport_state_ci = vk::PipelineViewportStateCreateInfo {
+fn get_dynamic_viewport_state_ci() -> vk::PipelineViewportStateCreateInfo {
+  vk::PipelineViewportStateCreateInfo {
     s_type: vk::StructureType::PIPELINE_VIEWPORT_STATE_CREATE_INFO,
     p_next: ptr::null(),
     flags: vk::PipelineViewportStateCreateFlags::empty(),
     scissor_count: 1,
-    p_scissors: addr_of!(*scissor),
+    p_scissors: ptr::null(),
     viewport_count: 1,
-    p_viewports: addr_of!(*viewport),
-  };
+    p_viewports: ptr::null(),
+  }
+}
 
-  (viewport_state_ci, viewport, scissor)
+fn get_dynamic_state_ci() -> vk::PipelineDynamicStateCreateInfo {
+  vk::PipelineDynamicStateCreateInfo {
+    s_type: vk::StructureType::PIPELINE_DYNAMIC_STATE_CREATE_INFO,
+    p_next: ptr::null(),
+    flags: vk::PipelineDynamicStateCreateFlags::empty(),
+    dynamic_state_count: DYNAMIC_STATES.len() as u32,
+    p_dynamic_states: DYNAMIC_STATES.as_ptr(),
+  }
 }
 
-fn get_no_depth_rasterization_state_ci() -> vk::PipelineRasterizationStateCreateInfo {
+fn get_rasterization_state_ci() -> vk::PipelineRasterizationStateCreateInfo {
   vk::PipelineRasterizationStateCreateInfo {
     s_type: vk::StructureType::PIPELINE_RASTERIZATION_STATE_CREATE_INFO,
     p_next: ptr::null(),
@@ -112,6 +127,33 @@ fn get_no_depth_rasterization_state_ci() -> vk::PipelineRasterizationStateCreate
   }
 }
 
+fn get_depth_stencil_state_ci() -> vk::PipelineDepthStencilStateCreateInfo {
+  let no_stencil_op = vk::StencilOpState {
+    fail_op: vk::StencilOp::KEEP,
+    pass_op: vk::StencilOp::KEEP,
+    depth_fail_op: vk::StencilOp::KEEP,
+    compare_op: vk::CompareOp::ALWAYS,
+    compare_mask: 0,
+    write_mask: 0,
+    reference: 0,
+  };
+
+  vk::PipelineDepthStencilStateCreateInfo {
+    s_type: vk::StructureType::PIPELINE_DEPTH_STENCIL_STATE_CREATE_INFO,
+    p_next: ptr::null(),
+    flags: vk::PipelineDepthStencilStateCreateFlags::empty(),
+    depth_test_enable: vk::TRUE,
+    depth_write_enable: vk::TRUE,
+    depth_compare_op: vk::CompareOp::LESS,
+    depth_bounds_test_enable: vk::FALSE,
+    stencil_test_enable: vk::FALSE,
+    front: no_stencil_op,
+    back: no_stencil_op,
+    min_depth_bounds: 0.0,
+    max_depth_bounds: 1.0,
+  }
+}
+
 fn get_no_multisample_state_ci() -> vk::PipelineMultisampleStateCreateInfo {
   vk::PipelineMultisampleStateCreateInfo {
     s_type: vk::StructureType::PIPELINE_MULTISAMPLE_STATE_CREATE_INFO,
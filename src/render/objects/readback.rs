@@ -0,0 +1,44 @@
+use ash::vk;
+
+use super::{
+  allocator::{Allocation, Allocator},
+  buffers::{create_buffer_with_sharing_exclusive, HOST_MEMORY_PROPERTY_FLAGS},
+};
+use crate::render::error::RenderResult;
+
+// host visible buffer that an image can be copied to in order to be read by the cpu
+pub struct ReadbackBuffer {
+  pub buffer: vk::Buffer,
+  allocation: Allocation,
+  size: u64,
+}
+
+impl ReadbackBuffer {
+  pub fn create(device: &ash::Device, allocator: &mut Allocator, size: u64) -> RenderResult<Self> {
+    let buffer =
+      create_buffer_with_sharing_exclusive(device, size, vk::BufferUsageFlags::TRANSFER_DST)?;
+    let allocation = match allocator.bind_buffer(device, buffer, HOST_MEMORY_PROPERTY_FLAGS) {
+      Ok(allocation) => allocation,
+      Err(err) => {
+        unsafe { device.destroy_buffer(buffer, None) };
+        return Err(err);
+      }
+    };
+
+    Ok(Self {
+      buffer,
+      allocation,
+      size,
+    })
+  }
+
+  // reads all buffer contents, should only be called after the copy has finished
+  pub unsafe fn read(&self) -> Vec<u8> {
+    std::slice::from_raw_parts(self.allocation.mapped_ptr(), self.size as usize).to_vec()
+  }
+
+  pub unsafe fn destroy_self(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+    device.destroy_buffer(self.buffer, None);
+    allocator.free(device, self.allocation);
+  }
+}
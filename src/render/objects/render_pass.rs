@@ -2,23 +2,48 @@ use std::ptr;
 
 use ash::vk;
 
-pub fn create_render_pass(device: &ash::Device, surface_format: vk::Format) -> vk::RenderPass {
+use crate::render::error::{RenderResult, VkResultExt};
+
+// final_layout depends on what happens to the image after rendering (presentation or a transfer)
+pub fn create_render_pass(
+  device: &ash::Device,
+  format: vk::Format,
+  depth_format: vk::Format,
+  final_layout: vk::ImageLayout,
+) -> RenderResult<vk::RenderPass> {
   let color_attachment = vk::AttachmentDescription {
     flags: vk::AttachmentDescriptionFlags::empty(),
-    format: surface_format,
+    format,
     samples: vk::SampleCountFlags::TYPE_1,
     load_op: vk::AttachmentLoadOp::CLEAR,
     store_op: vk::AttachmentStoreOp::STORE,
     stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
     stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
     initial_layout: vk::ImageLayout::UNDEFINED,
-    final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+    final_layout,
+  };
+
+  // depth values are only needed during the render pass
+  let depth_attachment = vk::AttachmentDescription {
+    flags: vk::AttachmentDescriptionFlags::empty(),
+    format: depth_format,
+    samples: vk::SampleCountFlags::TYPE_1,
+    load_op: vk::AttachmentLoadOp::CLEAR,
+    store_op: vk::AttachmentStoreOp::DONT_CARE,
+    stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+    stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+    initial_layout: vk::ImageLayout::UNDEFINED,
+    final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
   };
 
   let color_attachment_ref = vk::AttachmentReference {
     attachment: 0,
     layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
   };
+  let depth_attachment_ref = vk::AttachmentReference {
+    attachment: 1,
+    layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+  };
 
   let subpass = vk::SubpassDescription {
     flags: vk::SubpassDescriptionFlags::empty(),
@@ -28,20 +53,25 @@ pub fn create_render_pass(device: &ash::Device, surface_format: vk::Format) -> v
     color_attachment_count: 1,
     p_color_attachments: &color_attachment_ref,
     p_resolve_attachments: ptr::null(),
-    p_depth_stencil_attachment: ptr::null(),
+    p_depth_stencil_attachment: &depth_attachment_ref,
     preserve_attachment_count: 0,
     p_preserve_attachments: ptr::null(),
   };
 
-  let render_pass_attachments = [color_attachment];
+  let render_pass_attachments = [color_attachment, depth_attachment];
 
   let subpass_dependencies = [vk::SubpassDependency {
     src_subpass: vk::SUBPASS_EXTERNAL,
     dst_subpass: 0,
-    src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-    dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-    src_access_mask: vk::AccessFlags::empty(),
-    dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+    // the depth buffer is shared between frames, so previous depth writes have to finish first
+    src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+      | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+    dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+      | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+    src_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+    dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+      | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+      | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
     dependency_flags: vk::DependencyFlags::empty(),
   }];
 
@@ -60,6 +90,6 @@ pub fn create_render_pass(device: &ash::Device, surface_format: vk::Format) -> v
   unsafe {
     device
       .create_render_pass(&renderpass_create_info, None)
-      .expect("Failed to create render pass!")
+      .context("Failed to create render pass")
   }
 }
@@ -0,0 +1,68 @@
+use ash::vk;
+
+use super::{offscreen::OFFSCREEN_USAGE, Allocator, OffscreenTarget, Swapchains};
+
+// where the main render pass writes its color attachment to
+// only a single target exists per renderer
+// the surface itself belongs to the renderer, as it outlives device recreations
+#[allow(clippy::large_enum_variant)]
+pub enum RenderTarget {
+  Surface {
+    surface: vk::SurfaceKHR,
+    loader: ash::extensions::khr::Surface,
+    swapchains: Swapchains,
+  },
+  Offscreen(OffscreenTarget),
+}
+
+impl RenderTarget {
+  pub fn get_format(&self) -> vk::Format {
+    match self {
+      Self::Surface { swapchains, .. } => swapchains.get_format(),
+      Self::Offscreen(target) => target.format,
+    }
+  }
+
+  pub fn get_extent(&self) -> vk::Extent2D {
+    match self {
+      Self::Surface { swapchains, .. } => swapchains.get_extent(),
+      Self::Offscreen(target) => target.extent,
+    }
+  }
+
+  pub fn get_image_views(&self) -> &[vk::ImageView] {
+    match self {
+      Self::Surface { swapchains, .. } => swapchains.get_image_views(),
+      Self::Offscreen(target) => target.image_views(),
+    }
+  }
+
+  pub fn get_images(&self) -> &[vk::Image] {
+    match self {
+      Self::Surface { swapchains, .. } => swapchains.get_images(),
+      Self::Offscreen(target) => std::slice::from_ref(&target.image),
+    }
+  }
+
+  pub fn get_image_usage(&self) -> vk::ImageUsageFlags {
+    match self {
+      Self::Surface { swapchains, .. } => swapchains.get_image_usage(),
+      Self::Offscreen(_) => OFFSCREEN_USAGE,
+    }
+  }
+
+  // layout images are left in after the render pass
+  pub fn final_layout(&self) -> vk::ImageLayout {
+    match self {
+      Self::Surface { .. } => vk::ImageLayout::PRESENT_SRC_KHR,
+      Self::Offscreen(_) => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+    }
+  }
+
+  pub unsafe fn destroy_self(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+    match self {
+      Self::Surface { swapchains, .. } => swapchains.destroy_self(device),
+      Self::Offscreen(target) => target.destroy_self(device, allocator),
+    }
+  }
+}
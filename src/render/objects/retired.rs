@@ -0,0 +1,118 @@
+use std::collections::VecDeque;
+
+use ash::vk;
+
+use super::{
+  pipelines::{ComputePipelines, GraphicsPipelines},
+  swapchain::Swapchain,
+  Allocator, DepthBuffer, ModelGeometry, RenderTarget,
+};
+
+// resources replaced by a swapchain recreation, a shader reload or newly loaded models, which
+// frames in flight may still be using
+#[derive(Default)]
+pub struct RetiredResources {
+  pub swapchain: Option<Swapchain>,
+  pub framebuffers: Vec<vk::Framebuffer>,
+  pub render_pass: Option<vk::RenderPass>,
+  pub depth_buffer: Option<DepthBuffer>,
+  pub graphics_pipelines: Option<GraphicsPipelines>,
+  pub compute_pipelines: Option<ComputePipelines>,
+  pub geometry: Option<ModelGeometry>,
+}
+
+impl RetiredResources {
+  pub unsafe fn destroy_self(
+    &mut self,
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    target: &RenderTarget,
+  ) {
+    for &framebuffer in self.framebuffers.iter() {
+      device.destroy_framebuffer(framebuffer, None);
+    }
+    if let Some(pipelines) = &mut self.graphics_pipelines {
+      pipelines.destroy_self(device);
+    }
+    if let Some(pipelines) = &mut self.compute_pipelines {
+      pipelines.destroy_self(device);
+    }
+    if let Some(render_pass) = self.render_pass {
+      device.destroy_render_pass(render_pass, None);
+    }
+    if let Some(depth_buffer) = &mut self.depth_buffer {
+      depth_buffer.destroy_self(device, allocator);
+    }
+    if let Some(geometry) = &mut self.geometry {
+      geometry.destroy_self(device, allocator);
+    }
+    if let Some(swapchain) = &mut self.swapchain {
+      match target {
+        RenderTarget::Surface { swapchains, .. } => swapchains.destroy_retired(device, swapchain),
+        RenderTarget::Offscreen(_) => panic!("Headless renderer has no swapchain"),
+      }
+    }
+  }
+}
+
+// items retired before frame n are released once n frames have finished
+pub struct RetirementQueue<T> {
+  queue: VecDeque<(u64, T)>,
+}
+
+impl<T> RetirementQueue<T> {
+  pub fn new() -> Self {
+    Self {
+      queue: VecDeque::new(),
+    }
+  }
+
+  // frame is the number of the first frame that doesn't use item
+  pub fn retire(&mut self, frame: u64, item: T) {
+    self.queue.push_back((frame, item));
+  }
+
+  // items that are no longer used once finished_frame_count frames have finished
+  pub fn release(&mut self, finished_frame_count: u64) -> Vec<T> {
+    let mut released = Vec::new();
+    while let Some((frame, _)) = self.queue.front() {
+      if *frame > finished_frame_count {
+        break;
+      }
+      released.push(self.queue.pop_front().unwrap().1);
+    }
+    released
+  }
+
+  pub fn release_all(&mut self) -> Vec<T> {
+    self.queue.drain(..).map(|(_, item)| item).collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn items_wait_for_earlier_frames() {
+    let mut queue = RetirementQueue::new();
+    queue.retire(3, "a");
+    queue.retire(3, "b");
+    queue.retire(5, "c");
+
+    assert!(queue.release(2).is_empty());
+    assert_eq!(queue.release(4), ["a", "b"]);
+    assert!(queue.release(4).is_empty());
+    assert_eq!(queue.release(5), ["c"]);
+  }
+
+  #[test]
+  fn release_all_ignores_frames() {
+    let mut queue = RetirementQueue::new();
+    queue.retire(10, 1);
+    queue.retire(20, 2);
+
+    assert_eq!(queue.release_all(), [1, 2]);
+    assert!(queue.release(u64::MAX).is_empty());
+  }
+}
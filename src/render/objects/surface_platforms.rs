@@ -1,5 +1,9 @@
 use ash::vk;
 
+#[cfg(all(unix, not(target_os = "android"), not(target_os = "macos")))]
+use crate::render::error::RenderError;
+use crate::render::error::{RenderResult, VkResultExt};
+
 #[cfg(target_os = "windows")]
 use ash::extensions::khr::Win32Surface;
 #[cfg(all(unix, not(target_os = "android"), not(target_os = "macos")))]
@@ -26,7 +30,7 @@ pub fn required_extension_names() -> Vec<*const i8> {
   ]
 }
 
-#[cfg(all(windows))]
+#[cfg(windows)]
 pub fn required_extension_names() -> Vec<*const i8> {
   vec![
     Surface::name().as_ptr(),
@@ -43,12 +47,16 @@ pub unsafe fn create_surface(
   entry: &ash::Entry,
   instance: &ash::Instance,
   window: &winit::window::Window,
-) -> Result<vk::SurfaceKHR, vk::Result> {
+) -> RenderResult<vk::SurfaceKHR> {
   use std::ptr;
   use winit::platform::x11::WindowExtX11;
 
-  let x11_display = window.xlib_display().unwrap();
-  let x11_window = window.xlib_window().unwrap();
+  // the xlib surface extension is the only one enabled on linux
+  let (Some(x11_display), Some(x11_window)) = (window.xlib_display(), window.xlib_window()) else {
+    return Err(RenderError::Surface(
+      "Window is not an X11 window".to_owned(),
+    ));
+  };
   let x11_create_info = vk::XlibSurfaceCreateInfoKHR {
     s_type: vk::StructureType::XLIB_SURFACE_CREATE_INFO_KHR,
     p_next: ptr::null(),
@@ -57,7 +65,9 @@ pub unsafe fn create_surface(
     dpy: x11_display as *mut vk::Display,
   };
   let xlib_surface_loader = XlibSurface::new(entry, instance);
-  xlib_surface_loader.create_xlib_surface(&x11_create_info, None)
+  xlib_surface_loader
+    .create_xlib_surface(&x11_create_info, None)
+    .context("Failed to create surface")
 }
 
 #[cfg(target_os = "macos")]
@@ -65,7 +75,7 @@ pub unsafe fn create_surface<E: EntryV1_0, I: InstanceV1_0>(
   entry: &ash::Entry,
   instance: &ash::Instance,
   window: &winit::window::Window,
-) -> Result<vk::SurfaceKHR, vk::Result> {
+) -> RenderResult<vk::SurfaceKHR> {
   use std::{mem, os::raw::c_void, ptr};
   use winit::platform::macos::WindowExtMacOS;
 
@@ -91,7 +101,9 @@ pub unsafe fn create_surface<E: EntryV1_0, I: InstanceV1_0>(
   };
 
   let macos_surface_loader = MacOSSurface::new(entry, instance);
-  macos_surface_loader.create_mac_os_surface_mvk(&create_info, None)
+  macos_surface_loader
+    .create_mac_os_surface_mvk(&create_info, None)
+    .context("Failed to create surface")
 }
 
 #[cfg(target_os = "windows")]
@@ -99,7 +111,7 @@ pub unsafe fn create_surface<E: EntryV1_0, I: InstanceV1_0>(
   entry: &ash::Entry,
   instance: &ash::Instance,
   window: &winit::window::Window,
-) -> Result<vk::SurfaceKHR, vk::Result> {
+) -> RenderResult<vk::SurfaceKHR> {
   use std::{os::raw::c_void, ptr};
   use winapi::{shared::windef::HWND, um::libloaderapi::GetModuleHandleW};
   use winit::platform::windows::WindowExtWindows;
@@ -114,5 +126,7 @@ pub unsafe fn create_surface<E: EntryV1_0, I: InstanceV1_0>(
     hwnd: hwnd as *const c_void,
   };
   let win32_surface_loader = Win32Surface::new(entry, instance);
-  win32_surface_loader.create_win32_surface(&win32_create_info, None)
+  win32_surface_loader
+    .create_win32_surface(&win32_create_info, None)
+    .context("Failed to create surface")
 }
@@ -5,12 +5,14 @@ use log::info;
 use num::clamp;
 use winit::dpi::PhysicalSize;
 
-use crate::ENABLE_VSYNC;
+use crate::{
+  render::error::{RenderError, RenderResult, VkResultExt},
+  ENABLE_VSYNC,
+};
 
 pub struct Swapchains {
   loader: ash::extensions::khr::Swapchain,
   current: Swapchain,
-  old: Option<Swapchain>,
 }
 
 impl Swapchains {
@@ -21,23 +23,19 @@ impl Swapchains {
     surface: vk::SurfaceKHR,
     surface_loader: &ash::extensions::khr::Surface,
     window_size: &PhysicalSize<u32>,
-  ) -> Self {
+  ) -> RenderResult<Self> {
     let loader = ash::extensions::khr::Swapchain::new(instance, device);
 
     let current = Swapchain::create(
       physical_device,
-      &device,
+      device,
       surface,
       surface_loader,
       &loader,
       window_size,
-    );
+    )?;
 
-    Self {
-      loader,
-      current,
-      old: None,
-    }
+    Ok(Self { loader, current })
   }
 
   pub unsafe fn acquire_next_image(
@@ -47,6 +45,9 @@ impl Swapchains {
     self.current.acquire_next_image(semaphore, &self.loader)
   }
 
+  // the old swapchain is retired and returned, images already acquired from it can still be
+  // presented until it gets destroyed
+  // the current swapchain is kept if creating the new one fails
   pub unsafe fn recreate_swapchain(
     &mut self,
     physical_device: vk::PhysicalDevice,
@@ -54,18 +55,15 @@ impl Swapchains {
     surface: vk::SurfaceKHR,
     surface_loader: &ash::extensions::khr::Surface,
     window_size: &PhysicalSize<u32>,
-  ) -> RecreationChanges {
-    let (old, changes) = self.current.recreate(
+  ) -> RenderResult<(Swapchain, RecreationChanges)> {
+    self.current.recreate(
       physical_device,
       device,
       surface,
       surface_loader,
       &self.loader,
       window_size,
-    );
-
-    self.old = Some(old);
-    changes
+    )
   }
 
   pub unsafe fn queue_present(
@@ -88,17 +86,11 @@ impl Swapchains {
     unsafe { self.loader.queue_present(present_queue, &present_info) }
   }
 
-  pub fn destroy_old(&mut self, device: &ash::Device) {
-    if let Some(old) = &mut self.old {
-      unsafe {
-        old.destroy_self(device, &self.loader);
-      }
-      self.old = None;
-    }
+  pub unsafe fn destroy_retired(&self, device: &ash::Device, retired: &mut Swapchain) {
+    retired.destroy_self(device, &self.loader);
   }
 
   pub unsafe fn destroy_self(&mut self, device: &ash::Device) {
-    self.destroy_old(device);
     self.current.destroy_self(device, &self.loader);
   }
 
@@ -113,14 +105,24 @@ impl Swapchains {
   pub fn get_image_views(&self) -> &Vec<vk::ImageView> {
     &self.current.image_views
   }
+
+  pub fn get_images(&self) -> &[vk::Image] {
+    &self.current.images
+  }
+
+  pub fn get_image_usage(&self) -> vk::ImageUsageFlags {
+    self.current.usage
+  }
 }
 
-struct Swapchain {
+pub struct Swapchain {
   obj: vk::SwapchainKHR,
-  pub _images: Vec<vk::Image>,
+  pub images: Vec<vk::Image>,
   pub format: vk::Format,
   pub extent: vk::Extent2D,
   pub image_views: Vec<vk::ImageView>,
+  // depends on what the surface supports
+  pub usage: vk::ImageUsageFlags,
 }
 
 pub struct RecreationChanges {
@@ -136,16 +138,17 @@ impl Swapchain {
     surface_loader: &ash::extensions::khr::Surface,
     swapchain_loader: &ash::extensions::khr::Swapchain,
     window_size: &PhysicalSize<u32>,
-  ) -> Self {
+  ) -> RenderResult<Self> {
     let capabilities = unsafe {
       surface_loader
         .get_physical_device_surface_capabilities(physical_device, surface)
-        .expect("Failed to query for surface capabilities.")
+        .context("Failed to query for surface capabilities")?
     };
 
-    let image_format = select_swapchain_image_format(physical_device, surface, surface_loader);
-    let present_mode = select_swapchain_present_mode(physical_device, surface, surface_loader);
+    let image_format = select_swapchain_image_format(physical_device, surface, surface_loader)?;
+    let present_mode = select_swapchain_present_mode(physical_device, surface, surface_loader)?;
     let extent = get_swapchain_extent(&capabilities, window_size);
+    let usage = get_image_usage(&capabilities);
 
     let image_count = if capabilities.max_image_count > 0 {
       (capabilities.min_image_count + 1).min(capabilities.max_image_count)
@@ -153,7 +156,7 @@ impl Swapchain {
       capabilities.min_image_count + 1
     };
 
-    let queue_family_indices = vec![];
+    let queue_family_indices = [];
 
     let swapchain_create_info = vk::SwapchainCreateInfoKHR {
       s_type: vk::StructureType::SWAPCHAIN_CREATE_INFO_KHR,
@@ -164,7 +167,7 @@ impl Swapchain {
       image_color_space: image_format.color_space,
       image_format: image_format.format,
       image_extent: extent,
-      image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+      image_usage: usage,
       image_sharing_mode: vk::SharingMode::EXCLUSIVE,
       p_queue_family_indices: queue_family_indices.as_ptr(),
       queue_family_index_count: queue_family_indices.len() as u32,
@@ -179,24 +182,25 @@ impl Swapchain {
     let swapchain = unsafe {
       swapchain_loader
         .create_swapchain(&swapchain_create_info, None)
-        .expect("Failed to create Swapchain!")
+        .context("Failed to create swapchain")?
     };
 
     let images = unsafe {
       swapchain_loader
         .get_swapchain_images(swapchain)
-        .expect("Failed to get Swapchain Images.")
+        .context("Failed to get swapchain images")?
     };
 
-    let image_views = create_image_views(device, image_format.format, &images);
+    let image_views = create_image_views(device, image_format.format, &images)?;
 
-    Self {
+    Ok(Self {
       obj: swapchain,
-      _images: images,
+      images,
       format: image_format.format,
       extent,
       image_views,
-    }
+      usage,
+    })
   }
 
   pub fn recreate(
@@ -207,17 +211,18 @@ impl Swapchain {
     surface_loader: &ash::extensions::khr::Surface,
     swapchain_loader: &ash::extensions::khr::Swapchain,
     window_size: &PhysicalSize<u32>,
-  ) -> (Self, RecreationChanges) {
+  ) -> RenderResult<(Self, RecreationChanges)> {
     info!("Recreating swapchain");
     let capabilities = unsafe {
       surface_loader
         .get_physical_device_surface_capabilities(physical_device, surface)
-        .expect("Failed to query for surface capabilities.")
+        .context("Failed to query for surface capabilities")?
     };
 
-    let image_format = select_swapchain_image_format(physical_device, surface, surface_loader);
-    let present_mode = select_swapchain_present_mode(physical_device, surface, surface_loader);
+    let image_format = select_swapchain_image_format(physical_device, surface, surface_loader)?;
+    let present_mode = select_swapchain_present_mode(physical_device, surface, surface_loader)?;
     let extent = get_swapchain_extent(&capabilities, window_size);
+    let usage = get_image_usage(&capabilities);
 
     let changes = RecreationChanges {
       format: image_format.format != self.format,
@@ -230,7 +235,7 @@ impl Swapchain {
       capabilities.min_image_count + 1
     };
 
-    let queue_family_indices = vec![];
+    let queue_family_indices = [];
 
     let swapchain_create_info = vk::SwapchainCreateInfoKHR {
       s_type: vk::StructureType::SWAPCHAIN_CREATE_INFO_KHR,
@@ -241,7 +246,7 @@ impl Swapchain {
       image_color_space: image_format.color_space,
       image_format: image_format.format,
       image_extent: extent,
-      image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+      image_usage: usage,
       image_sharing_mode: vk::SharingMode::EXCLUSIVE,
       p_queue_family_indices: queue_family_indices.as_ptr(),
       queue_family_index_count: queue_family_indices.len() as u32,
@@ -256,28 +261,29 @@ impl Swapchain {
     let swapchain = unsafe {
       swapchain_loader
         .create_swapchain(&swapchain_create_info, None)
-        .expect("Failed to create Swapchain!")
+        .context("Failed to create swapchain")?
     };
 
     let images = unsafe {
       swapchain_loader
         .get_swapchain_images(swapchain)
-        .expect("Failed to get Swapchain Images.")
+        .context("Failed to get swapchain images")?
     };
 
-    let image_views = create_image_views(device, image_format.format, &images);
+    let image_views = create_image_views(device, image_format.format, &images)?;
 
     let mut old = Self {
       obj: swapchain,
-      _images: images,
+      images,
       format: image_format.format,
       extent,
       image_views,
+      usage,
     };
 
     std::mem::swap(&mut old, self);
 
-    (old, changes)
+    Ok((old, changes))
   }
 
   pub unsafe fn acquire_next_image(
@@ -285,7 +291,7 @@ impl Swapchain {
     semaphore: vk::Semaphore,
     loader: &ash::extensions::khr::Swapchain,
   ) -> Result<(u32, bool), vk::Result> {
-    loader.acquire_next_image(self.obj, std::u64::MAX, semaphore, vk::Fence::null())
+    loader.acquire_next_image(self.obj, u64::MAX, semaphore, vk::Fence::null())
   }
 
   pub unsafe fn destroy_self(
@@ -304,48 +310,63 @@ fn select_swapchain_image_format(
   physical_device: vk::PhysicalDevice,
   surface: vk::SurfaceKHR,
   surface_loader: &ash::extensions::khr::Surface,
-) -> vk::SurfaceFormatKHR {
+) -> RenderResult<vk::SurfaceFormatKHR> {
   let formats = unsafe {
     surface_loader
       .get_physical_device_surface_formats(physical_device, surface)
-      .expect("Failed to query for surface formats.")
+      .context("Failed to query for surface formats")?
   };
 
   for available_format in formats.iter() {
     if available_format.format == vk::Format::B8G8R8A8_SRGB
       && available_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
     {
-      return available_format.clone();
+      return Ok(*available_format);
     }
   }
-  formats[0].clone()
+  formats
+    .first()
+    .copied()
+    .ok_or(RenderError::NoSupportedFormat("surface"))
 }
 
 fn select_swapchain_present_mode(
   physical_device: vk::PhysicalDevice,
   surface: vk::SurfaceKHR,
   surface_loader: &ash::extensions::khr::Surface,
-) -> vk::PresentModeKHR {
+) -> RenderResult<vk::PresentModeKHR> {
   let present_modes = unsafe {
     surface_loader
       .get_physical_device_surface_present_modes(physical_device, surface)
-      .expect("Failed to query for surface present mode.")
+      .context("Failed to query for surface present modes")?
   };
 
   if ENABLE_VSYNC {
     if present_modes.contains(&vk::PresentModeKHR::IMMEDIATE) {
-      return vk::PresentModeKHR::IMMEDIATE;
+      return Ok(vk::PresentModeKHR::IMMEDIATE);
     }
     if present_modes.contains(&vk::PresentModeKHR::FIFO_RELAXED) {
-      return vk::PresentModeKHR::FIFO_RELAXED;
+      return Ok(vk::PresentModeKHR::FIFO_RELAXED);
     }
   }
 
   if present_modes.contains(&vk::PresentModeKHR::MAILBOX) {
-    return vk::PresentModeKHR::MAILBOX;
+    return Ok(vk::PresentModeKHR::MAILBOX);
   }
 
-  vk::PresentModeKHR::FIFO
+  Ok(vk::PresentModeKHR::FIFO)
+}
+
+// images are also used as a transfer source (if supported) so that frames can be captured
+fn get_image_usage(capabilities: &vk::SurfaceCapabilitiesKHR) -> vk::ImageUsageFlags {
+  let mut usage = vk::ImageUsageFlags::COLOR_ATTACHMENT;
+  if capabilities
+    .supported_usage_flags
+    .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+  {
+    usage |= vk::ImageUsageFlags::TRANSFER_SRC;
+  }
+  usage
 }
 
 fn get_swapchain_extent(
@@ -353,7 +374,7 @@ fn get_swapchain_extent(
   size: &PhysicalSize<u32>,
 ) -> vk::Extent2D {
   println!("{} {}", size.width, size.height);
-  if capabilities.current_extent.width != u32::max_value() {
+  if capabilities.current_extent.width != u32::MAX {
     capabilities.current_extent
   } else {
     vk::Extent2D {
@@ -374,8 +395,8 @@ fn get_swapchain_extent(
 fn create_image_views(
   device: &ash::Device,
   format: vk::Format,
-  images: &Vec<vk::Image>,
-) -> Vec<vk::ImageView> {
+  images: &[vk::Image],
+) -> RenderResult<Vec<vk::ImageView>> {
   let mut views = Vec::with_capacity(images.len());
 
   for &image in images.iter() {
@@ -404,10 +425,10 @@ fn create_image_views(
     let view = unsafe {
       device
         .create_image_view(&imageview_create_info, None)
-        .expect("Failed to create Image View!")
+        .context("Failed to create swapchain image view")?
     };
     views.push(view);
   }
 
-  views
+  Ok(views)
 }
@@ -0,0 +1,156 @@
+use std::{path::PathBuf, ptr};
+
+use ash::vk;
+use image::{Rgba, RgbaImage};
+use log::info;
+
+use super::{
+  allocator::{Allocation, Allocator},
+  images::{create_image, create_image_view},
+  uploads::{StagingBuffer, UploadDst, UploadOperation, Uploader},
+};
+use crate::render::error::{RenderError, RenderResult, VkResultExt};
+
+const TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+pub struct Texture {
+  image: vk::Image,
+  allocation: Allocation,
+  pub view: vk::ImageView,
+}
+
+impl Texture {
+  // creates the texture along with the operation that uploads data into it
+  // it can't be sampled until the upload completes
+  pub fn stage(
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    graphics_family: u32,
+    data: &RgbaImage,
+  ) -> RenderResult<(Self, UploadOperation)> {
+    let extent = vk::Extent2D {
+      width: data.width(),
+      height: data.height(),
+    };
+    let (image, allocation) = create_image(
+      device,
+      allocator,
+      extent,
+      TEXTURE_FORMAT,
+      vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+    )?;
+    let view = create_image_view(device, image, TEXTURE_FORMAT, vk::ImageAspectFlags::COLOR)?;
+    let operation = UploadOperation {
+      staging: StagingBuffer::new(device, allocator, data.as_raw())?,
+      dst: UploadDst::Image { image, extent },
+      dst_family: graphics_family,
+      dst_stage: vk::PipelineStageFlags::FRAGMENT_SHADER,
+      dst_access: vk::AccessFlags::SHADER_READ,
+    };
+    Ok((
+      Self {
+        image,
+        allocation,
+        view,
+      },
+      operation,
+    ))
+  }
+
+  pub unsafe fn destroy_self(&self, device: &ash::Device, allocator: &mut Allocator) {
+    device.destroy_image_view(self.view, None);
+    device.destroy_image(self.image, None);
+    allocator.free(device, self.allocation);
+  }
+}
+
+// sampled images loaded from disk, all sharing the same sampler
+pub struct Textures {
+  pub textures: Vec<Texture>,
+  // white texture bound to the slots of textures that are still loading, so that the whole array
+  // can be sampled from
+  pub placeholder: Texture,
+  pub sampler: vk::Sampler,
+}
+
+impl Textures {
+  // decodes the images and uploads them on the transfer queue, waiting until they can be sampled
+  // by graphics_family
+  pub fn load(
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    uploader: &mut Uploader,
+    graphics_family: u32,
+    paths: &[PathBuf],
+  ) -> RenderResult<Self> {
+    let mut textures = Vec::with_capacity(paths.len());
+    let mut operations = Vec::with_capacity(paths.len() + 1);
+    for path in paths {
+      info!("Loading texture {:?}", path);
+      let data = image::open(path)
+        .map_err(|error| RenderError::Texture {
+          path: path.clone(),
+          error,
+        })?
+        .to_rgba8();
+      let (texture, operation) = Texture::stage(device, allocator, graphics_family, &data)?;
+      textures.push(texture);
+      operations.push(operation);
+    }
+
+    let white = RgbaImage::from_pixel(1, 1, Rgba([u8::MAX; 4]));
+    let (placeholder, operation) = Texture::stage(device, allocator, graphics_family, &white)?;
+    operations.push(operation);
+
+    unsafe {
+      let upload = uploader.submit(device, allocator, operations)?;
+      uploader.wait(device, allocator, upload)?;
+    }
+
+    let sampler = create_sampler(device)?;
+
+    Ok(Self {
+      textures,
+      placeholder,
+      sampler,
+    })
+  }
+
+  pub unsafe fn destroy_self(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+    device.destroy_sampler(self.sampler, None);
+    for texture in self.textures.iter() {
+      texture.destroy_self(device, allocator);
+    }
+    self.placeholder.destroy_self(device, allocator);
+  }
+}
+
+fn create_sampler(device: &ash::Device) -> RenderResult<vk::Sampler> {
+  let create_info = vk::SamplerCreateInfo {
+    s_type: vk::StructureType::SAMPLER_CREATE_INFO,
+    p_next: ptr::null(),
+    flags: vk::SamplerCreateFlags::empty(),
+    mag_filter: vk::Filter::LINEAR,
+    min_filter: vk::Filter::LINEAR,
+    mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+    address_mode_u: vk::SamplerAddressMode::REPEAT,
+    address_mode_v: vk::SamplerAddressMode::REPEAT,
+    address_mode_w: vk::SamplerAddressMode::REPEAT,
+    mip_lod_bias: 0.0,
+    // the anisotropy device feature is not enabled
+    anisotropy_enable: vk::FALSE,
+    max_anisotropy: 1.0,
+    compare_enable: vk::FALSE,
+    compare_op: vk::CompareOp::ALWAYS,
+    min_lod: 0.0,
+    max_lod: 0.0,
+    border_color: vk::BorderColor::INT_OPAQUE_BLACK,
+    unnormalized_coordinates: vk::FALSE,
+  };
+
+  unsafe {
+    device
+      .create_sampler(&create_info, None)
+      .context("Failed to create sampler")
+  }
+}
@@ -0,0 +1,366 @@
+use std::ptr;
+
+use ash::vk;
+use log::debug;
+
+use super::{
+  allocator::{Allocation, Allocator},
+  buffers::{create_buffer_with_sharing_exclusive, HOST_MEMORY_PROPERTY_FLAGS},
+  command_buffer_pools::TransferCommandBufferPool,
+  QueueFamilyIndices, Queues,
+};
+use crate::render::error::{RenderResult, VkResultExt};
+
+// host memory holding data to be uploaded
+pub struct StagingBuffer {
+  pub buffer: vk::Buffer,
+  allocation: Allocation,
+}
+
+impl StagingBuffer {
+  pub fn new(device: &ash::Device, allocator: &mut Allocator, data: &[u8]) -> RenderResult<Self> {
+    let staging = Self::uninit(device, allocator, data.len() as u64)?;
+    unsafe {
+      ptr::copy_nonoverlapping(data.as_ptr(), staging.mapped_ptr(), data.len());
+    }
+    Ok(staging)
+  }
+
+  // contents have to be written through mapped_ptr
+  pub fn uninit(device: &ash::Device, allocator: &mut Allocator, size: u64) -> RenderResult<Self> {
+    let buffer =
+      create_buffer_with_sharing_exclusive(device, size, vk::BufferUsageFlags::TRANSFER_SRC)?;
+    let allocation = match allocator.bind_buffer(device, buffer, HOST_MEMORY_PROPERTY_FLAGS) {
+      Ok(allocation) => allocation,
+      Err(err) => {
+        unsafe { device.destroy_buffer(buffer, None) };
+        return Err(err);
+      }
+    };
+    Ok(Self { buffer, allocation })
+  }
+
+  pub fn mapped_ptr(&self) -> *mut u8 {
+    self.allocation.mapped_ptr()
+  }
+
+  unsafe fn destroy_self(&self, device: &ash::Device, allocator: &mut Allocator) {
+    device.destroy_buffer(self.buffer, None);
+    allocator.free(device, self.allocation);
+  }
+}
+
+pub enum UploadDst {
+  Buffer {
+    buffer: vk::Buffer,
+    regions: Vec<vk::BufferCopy>,
+  },
+  // the whole color image, which ends up in SHADER_READ_ONLY_OPTIMAL
+  Image {
+    image: vk::Image,
+    extent: vk::Extent2D,
+  },
+}
+
+// copies a staging buffer into a resource with exclusive sharing, owned by dst_family afterwards
+pub struct UploadOperation {
+  pub staging: StagingBuffer,
+  pub dst: UploadDst,
+  pub dst_family: u32,
+  // first use of the destination
+  pub dst_stage: vk::PipelineStageFlags,
+  pub dst_access: vk::AccessFlags,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UploadId(u64);
+
+// the family of a destination acquiring it from the transfer family
+struct Acquire {
+  family: u32,
+  command_buffer: vk::CommandBuffer,
+  // signaled by the release submission, only waited upon once it's known to be signaled
+  released: vk::Semaphore,
+  finished: vk::Fence,
+  submitted: bool,
+}
+
+struct PendingUpload {
+  id: UploadId,
+  operations: Vec<UploadOperation>,
+  command_buffer: vk::CommandBuffer,
+  finished: vk::Fence,
+  acquires: Vec<Acquire>,
+}
+
+impl PendingUpload {
+  // fences the upload is currently waiting on
+  fn fences(&self) -> Vec<vk::Fence> {
+    if self.acquires.iter().all(|acquire| acquire.submitted) {
+      let mut fences: Vec<vk::Fence> = self.acquires.iter().map(|a| a.finished).collect();
+      fences.push(self.finished);
+      fences
+    } else {
+      vec![self.finished]
+    }
+  }
+}
+
+// copies data into device local resources on the transfer queue, if there's a separate one
+// uploads are tracked with fences and polled, so that the graphics queue never waits on them:
+// acquire barriers of other families are only submitted after the copies have finished
+pub struct Uploader {
+  pool: TransferCommandBufferPool,
+  queue: vk::Queue,
+  // queues of the families that acquire uploads
+  acquire_queues: Vec<(u32, vk::Queue)>,
+  pending: Vec<PendingUpload>,
+  // finished uploads that have not been returned by poll yet
+  completed: Vec<UploadId>,
+  next_id: u64,
+}
+
+impl Uploader {
+  pub fn new(
+    device: &ash::Device,
+    queue_families: &QueueFamilyIndices,
+    queues: &Queues,
+  ) -> RenderResult<Self> {
+    let pool = TransferCommandBufferPool::create(device, queue_families)?;
+    let queue = queues.transfer.unwrap_or(queues.graphics);
+    Ok(Self {
+      pool,
+      queue,
+      acquire_queues: vec![
+        (queue_families.graphics, queues.graphics),
+        (queue_families.compute, queues.compute),
+      ],
+      pending: Vec::new(),
+      completed: Vec::new(),
+      next_id: 0,
+    })
+  }
+
+  // the destinations shouldn't be used until the upload completes
+  pub unsafe fn submit(
+    &mut self,
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    operations: Vec<UploadOperation>,
+  ) -> RenderResult<UploadId> {
+    let id = UploadId(self.next_id);
+    self.next_id += 1;
+
+    let mut families: Vec<u32> = operations
+      .iter()
+      .map(|op| op.dst_family)
+      .filter(|&family| family != self.pool.family)
+      .collect();
+    families.sort();
+    families.dedup();
+
+    // handles are filled in as they get created, so that everything created so far can be
+    // destroyed if anything fails
+    let mut upload = PendingUpload {
+      id,
+      operations,
+      command_buffer: vk::CommandBuffer::null(),
+      finished: vk::Fence::null(),
+      acquires: Vec::with_capacity(families.len()),
+    };
+    if let Err(err) = self.record_and_submit(device, &mut upload, families) {
+      self.destroy_upload(device, allocator, upload);
+      return Err(err);
+    }
+    debug!("Submitted upload {:?}", id);
+    self.pending.push(upload);
+    Ok(id)
+  }
+
+  unsafe fn record_and_submit(
+    &self,
+    device: &ash::Device,
+    upload: &mut PendingUpload,
+    families: Vec<u32>,
+  ) -> RenderResult<()> {
+    upload.command_buffer = self.pool.record_release(device, &upload.operations)?;
+    upload.finished = create_fence(device)?;
+    for family in families {
+      upload.acquires.push(Acquire {
+        family,
+        command_buffer: vk::CommandBuffer::null(),
+        released: vk::Semaphore::null(),
+        finished: vk::Fence::null(),
+        submitted: false,
+      });
+      let acquire = upload.acquires.last_mut().unwrap();
+      acquire.command_buffer = self
+        .pool
+        .record_acquire(device, family, &upload.operations)?;
+      acquire.released = create_semaphore(device)?;
+      acquire.finished = create_fence(device)?;
+    }
+
+    let signal_semaphores: Vec<vk::Semaphore> =
+      upload.acquires.iter().map(|a| a.released).collect();
+    let submit_info = vk::SubmitInfo {
+      s_type: vk::StructureType::SUBMIT_INFO,
+      p_next: ptr::null(),
+      wait_semaphore_count: 0,
+      p_wait_semaphores: ptr::null(),
+      p_wait_dst_stage_mask: ptr::null(),
+      command_buffer_count: 1,
+      p_command_buffers: &upload.command_buffer,
+      signal_semaphore_count: signal_semaphores.len() as u32,
+      p_signal_semaphores: signal_semaphores.as_ptr(),
+    };
+    device
+      .queue_submit(self.queue, &[submit_info], upload.finished)
+      .context("Failed to submit upload")
+  }
+
+  // advances pending uploads without blocking, returning the ones that completed since the last
+  // poll
+  pub unsafe fn poll(
+    &mut self,
+    device: &ash::Device,
+    allocator: &mut Allocator,
+  ) -> RenderResult<Vec<UploadId>> {
+    self.advance(device, allocator)?;
+    Ok(std::mem::take(&mut self.completed))
+  }
+
+  // blocks until upload id completes, it is still returned by the next poll
+  pub unsafe fn wait(
+    &mut self,
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    id: UploadId,
+  ) -> RenderResult<()> {
+    while let Some(upload) = self.pending.iter().find(|upload| upload.id == id) {
+      device
+        .wait_for_fences(&upload.fences(), true, u64::MAX)
+        .context("Failed to wait for upload")?;
+      self.advance(device, allocator)?;
+    }
+    Ok(())
+  }
+
+  unsafe fn advance(
+    &mut self,
+    device: &ash::Device,
+    allocator: &mut Allocator,
+  ) -> RenderResult<()> {
+    let mut i = 0;
+    while i < self.pending.len() {
+      let upload = &mut self.pending[i];
+      if !is_signaled(device, upload.finished)? {
+        i += 1;
+        continue;
+      }
+
+      // the copies have finished, so waiting on the semaphores doesn't block the other queues
+      for acquire in upload.acquires.iter_mut().filter(|a| !a.submitted) {
+        let queue = self
+          .acquire_queues
+          .iter()
+          .find(|(family, _)| *family == acquire.family)
+          .unwrap()
+          .1;
+        let wait_stage = vk::PipelineStageFlags::ALL_COMMANDS;
+        let submit_info = vk::SubmitInfo {
+          s_type: vk::StructureType::SUBMIT_INFO,
+          p_next: ptr::null(),
+          wait_semaphore_count: 1,
+          p_wait_semaphores: &acquire.released,
+          p_wait_dst_stage_mask: &wait_stage,
+          command_buffer_count: 1,
+          p_command_buffers: &acquire.command_buffer,
+          signal_semaphore_count: 0,
+          p_signal_semaphores: ptr::null(),
+        };
+        device
+          .queue_submit(queue, &[submit_info], acquire.finished)
+          .context("Failed to submit upload acquisition")?;
+        acquire.submitted = true;
+      }
+
+      let mut acquired = true;
+      for acquire in upload.acquires.iter() {
+        acquired &= is_signaled(device, acquire.finished)?;
+      }
+      if acquired {
+        let upload = self.pending.swap_remove(i);
+        debug!("Upload {:?} completed", upload.id);
+        self.completed.push(upload.id);
+        self.destroy_upload(device, allocator, upload);
+      } else {
+        i += 1;
+      }
+    }
+    Ok(())
+  }
+
+  // handles that weren't created yet are null, which destroying and freeing ignore
+  unsafe fn destroy_upload(
+    &self,
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    upload: PendingUpload,
+  ) {
+    self
+      .pool
+      .free(device, self.pool.family, upload.command_buffer);
+    device.destroy_fence(upload.finished, None);
+    for acquire in upload.acquires {
+      self
+        .pool
+        .free(device, acquire.family, acquire.command_buffer);
+      device.destroy_semaphore(acquire.released, None);
+      device.destroy_fence(acquire.finished, None);
+    }
+    for op in upload.operations {
+      op.staging.destroy_self(device, allocator);
+    }
+  }
+
+  // the device should be idle
+  pub unsafe fn destroy_self(&mut self, device: &ash::Device, allocator: &mut Allocator) {
+    for upload in std::mem::take(&mut self.pending) {
+      self.destroy_upload(device, allocator, upload);
+    }
+    self.pool.destroy_self(device);
+  }
+}
+
+unsafe fn is_signaled(device: &ash::Device, fence: vk::Fence) -> RenderResult<bool> {
+  device
+    .get_fence_status(fence)
+    .context("Failed to get upload fence status")
+}
+
+fn create_fence(device: &ash::Device) -> RenderResult<vk::Fence> {
+  let create_info = vk::FenceCreateInfo {
+    s_type: vk::StructureType::FENCE_CREATE_INFO,
+    p_next: ptr::null(),
+    flags: vk::FenceCreateFlags::empty(),
+  };
+  unsafe {
+    device
+      .create_fence(&create_info, None)
+      .context("Failed to create fence")
+  }
+}
+
+fn create_semaphore(device: &ash::Device) -> RenderResult<vk::Semaphore> {
+  let create_info = vk::SemaphoreCreateInfo {
+    s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
+    p_next: ptr::null(),
+    flags: vk::SemaphoreCreateFlags::empty(),
+  };
+  unsafe {
+    device
+      .create_semaphore(&create_info, None)
+      .context("Failed to create semaphore")
+  }
+}
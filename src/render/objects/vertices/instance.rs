@@ -22,7 +22,20 @@ impl MatrixInstance {
   }
 }
 
-impl Vertex for MatrixInstance {
+// transformed instance read by the graphics pipelines, written by the instance compute shader
+// only ever constructed on the device
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct RenderInstance {
+  pub matrix: Matrix4<f32>,
+  // index into the texture array, unused by color models
+  pub texture_i: u32,
+  // std430 struct size is a multiple of its alignment (the one of mat4 columns)
+  pub _padding: [u32; 3],
+}
+
+impl Vertex for RenderInstance {
   fn get_binding_description(binding: u32) -> vk::VertexInputBindingDescription {
     vk::VertexInputBindingDescription {
       binding,
@@ -60,10 +73,27 @@ impl Vertex for MatrixInstance {
         format: vk::Format::R32G32B32A32_SFLOAT,
         offset: offset_of!(Self, matrix) as u32 + offset_of!(Matrix4<f32>, w) as u32,
       },
+      vk::VertexInputAttributeDescription {
+        location: start_location + 4,
+        binding,
+        format: vk::Format::R32_UINT,
+        offset: offset_of!(Self, texture_i) as u32,
+      },
     ]
   }
 
   fn attribute_size() -> u32 {
-    4
+    5
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn render_instance_matches_shader_layout() {
+    assert_eq!(std::mem::size_of::<RenderInstance>(), 80);
+    assert_eq!(offset_of!(RenderInstance, texture_i), 64);
   }
 }
@@ -4,7 +4,7 @@ mod tex;
 
 use ash::vk;
 pub use color::ColorVertex;
-pub use instance::MatrixInstance;
+pub use instance::{MatrixInstance, RenderInstance};
 pub use tex::TexVertex;
 
 pub trait Vertex {
@@ -56,7 +56,7 @@ macro_rules! get_pipeline_vertex_input_state_ci {
   ($($vertices:tt,)+) => {
     {
       let binding_descriptions = Box::pin(enumerate_binding_descriptions!($($vertices,)+));
-      let attribute_descriptions: Vec<vk::VertexInputAttributeDescription> = 
+      let attribute_descriptions: Vec<vk::VertexInputAttributeDescription> =
         enumerate_attribute_descriptions!($($vertices,)+)
         .into_iter()
         .flatten()
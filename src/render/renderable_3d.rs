@@ -1,10 +1,13 @@
 use cgmath::{EuclideanSpace, Euler, Matrix4, Point3, Rad, Vector3};
 
+use super::ModelHandle;
+
 pub trait RenderableIn3d {
   fn ren(&self) -> &Renderable3dObject;
+  #[allow(dead_code)]
   fn into_ren(self) -> Renderable3dObject;
   fn ren_mut(&mut self) -> &mut Renderable3dObject;
-  fn model_i(&self) -> usize;
+  fn model_i(&self) -> ModelHandle;
 }
 
 /// Object information suitable for rendering in 3D. Caches certain matrices
@@ -1,61 +1,117 @@
 use super::{
   camera::RenderCamera,
-  models::ModelProperties,
+  capture::CapturedFrame,
+  error::{RenderError, RenderResult, VkResultExt},
+  models::{AssetLoader, LoadState, ModelProperties},
   objects::{
-    self, Buffers, CommandBufferPools, DescriptorSets, InstProperties, Pipelines,
-    QueueFamilyIndices, Queues, Swapchains,
+    self, Allocator, AllocatorStats, Buffers, CommandBufferPools, DepthBuffer, DescriptorSets,
+    GpuOverride, IndirectDraws, InstProperties, InstancePushConstants, ModelGeometry,
+    OffscreenTarget, PipelineCache, Pipelines, QueueFamilyIndices, Queues, ReadbackBuffer,
+    RenderTarget, RetiredResources, RetirementQueue, Swapchains, Texture, Textures, UploadId,
+    Uploader,
   },
-  MatrixInstance, Models, DEVICE_EXTENSIONS,
+  shaders::{ReloadedShaders, ShaderCode},
+  MatrixInstance, ModelHandle, Models, DEVICE_EXTENSIONS,
 };
 
-use crate::{INITIAL_WINDOW_HEIGHT, INITIAL_WINDOW_WIDTH, WINDOW_TITLE};
-use ash::vk;
-use winit::{event_loop::EventLoop, window::Window};
+use crate::static_scene::StaticScene;
+use ash::{extensions::khr::Surface, vk};
+use image::RgbaImage;
+use log::{error, info, warn};
+use std::ptr;
+use winit::{dpi::PhysicalSize, window::Window};
 
+#[cfg(test)]
+use super::objects::{IndirectDraw, RenderInstance};
 #[cfg(feature = "vulkan_vl")]
 use super::{objects::DebugUtils, VALIDATION_LAYERS};
 #[cfg(feature = "vulkan_vl")]
-use log::info;
-#[cfg(feature = "vulkan_vl")]
 use std::ffi::{c_char, CStr};
 
 pub struct Renderer {
   _entry: ash::Entry,
-  pub window: Window,
   instance: ash::Instance,
   #[cfg(feature = "vulkan_vl")]
   debug_utils: DebugUtils,
+  // the surface outlives device recreations
+  surface: Option<(vk::SurfaceKHR, Surface)>,
+  // compiled shaders, replaced by hot reloading
+  shader_code: ShaderCode,
+  // kept to upload them again after a device loss
+  models: Models,
+  scene: StaticScene,
+  model_props: Vec<ModelProperties>,
+  // state of each model as drawn, loaded models stay pending until their upload completes
+  model_states: Vec<LoadState>,
+  // loads pending models in the background, dropped once none are left
+  loader: Option<AssetLoader>,
+  // textures decoded by the loader that haven't been uploaded yet, following models.textures
+  loaded_textures: Vec<RgbaImage>,
+  initial_dyn_inst_count: u64,
+  frames_in_flight: usize,
+  // kept so that a recreated device is selected the same way
+  gpu_override: Option<GpuOverride>,
+  dev: DeviceObjects,
+  // set when recreating the device failed, in which case its objects are already destroyed
+  lost: bool,
+  // the next submit reports a device loss, so that recovery can be tested
+  #[cfg(test)]
+  fake_device_lost: bool,
+}
+
+// everything created from the logical device, destroyed and created again when it gets lost
+struct DeviceObjects {
   physical_device: vk::PhysicalDevice,
-  _queue_family_indices: QueueFamilyIndices,
-  pub device: ash::Device,
-  pub queues: Queues,
-  surface: vk::SurfaceKHR,
-  surface_loader: ash::extensions::khr::Surface,
-  pub swapchains: Swapchains,
-  pub pipelines: Pipelines,
+  queue_family_indices: QueueFamilyIndices,
+  device: ash::Device,
+  queues: Queues,
+  allocator: Allocator,
+  target: RenderTarget,
+  pipeline_cache: PipelineCache,
+  pipelines: Pipelines,
   descriptor_sets: DescriptorSets,
   render_pass: vk::RenderPass,
+  depth_buffer: DepthBuffer,
   framebuffers: Vec<vk::Framebuffer>,
-  pub command_buffer_pools: CommandBufferPools,
+  command_buffer_pools: CommandBufferPools,
+  uploader: Uploader,
   buffers: Buffers,
+  textures: Textures,
+  // models that finished loading, swapped in once uploaded
+  model_upload: Option<ModelUpload>,
+  // frames whose texture set doesn't contain every uploaded texture
+  outdated_texture_sets: Vec<bool>,
+  // replaced by swapchain recreations and waiting for the frames that use them to finish
+  retired: RetirementQueue<RetiredResources>,
+}
+
+// geometry of every model loaded when the upload was submitted, along with the textures loaded
+// since the previous one
+struct ModelUpload {
+  id: UploadId,
+  geometry: ModelGeometry,
+  textures: Vec<Texture>,
   model_props: Vec<ModelProperties>,
+  model_states: Vec<LoadState>,
 }
 
 #[cfg(all(feature = "link_vulkan", feature = "load_vulkan"))]
 compile_error!("Cannot load and link Vulkan at the same time");
 
 #[allow(unreachable_code)]
-unsafe fn get_entry() -> ash::Entry {
+unsafe fn get_entry() -> RenderResult<ash::Entry> {
   #[cfg(feature = "link_vulkan")]
-  return ash::Entry::linked();
+  return Ok(ash::Entry::linked());
   #[cfg(feature = "load_vulkan")]
-  return ash::Entry::load().expect("Failed to load entry");
+  return Ok(ash::Entry::load()?);
   panic!("No compile feature was included for accessing Vulkan");
 }
 
 #[cfg(feature = "vulkan_vl")]
-fn check_validation_layers_support(entry: &ash::Entry) -> Result<(), &'static CStr> {
-  let properties: Vec<vk::LayerProperties> = entry.enumerate_instance_layer_properties().unwrap();
+fn check_validation_layers_support(entry: &ash::Entry) -> RenderResult<()> {
+  let properties: Vec<vk::LayerProperties> = entry
+    .enumerate_instance_layer_properties()
+    .context("Failed to enumerate instance layers")?;
   let mut available: Vec<&CStr> = properties
     .iter()
     .map(|p| {
@@ -69,208 +125,594 @@ fn check_validation_layers_support(entry: &ash::Entry) -> Result<(), &'static CS
 
   info!("System available validation layers: {:?}", available);
 
-  for name in VALIDATION_LAYERS {
-    if let Err(_) = available.binary_search_by(|&av| av.cmp(name)) {
-      return Err(name);
-    }
+  let missing: Vec<String> = VALIDATION_LAYERS
+    .iter()
+    .filter(|name| available.binary_search_by(|av| av.cmp(name)).is_err())
+    .map(|name| name.to_string_lossy().into_owned())
+    .collect();
+  if !missing.is_empty() {
+    return Err(RenderError::MissingLayers(missing));
   }
   Ok(())
 }
 
 impl Renderer {
-  pub fn new(event_loop: &EventLoop<()>, max_dyn_inst_count: u64) -> Self {
-    let entry: ash::Entry = unsafe { get_entry() };
+  pub fn new(
+    window: &Window,
+    models: Models,
+    scene: &StaticScene,
+    initial_dyn_inst_count: u64,
+    frames_in_flight: usize,
+    gpu_override: Option<GpuOverride>,
+  ) -> RenderResult<Self> {
+    let window_size = window.inner_size();
+    let extent = vk::Extent2D {
+      width: window_size.width,
+      height: window_size.height,
+    };
+    Self::create(
+      Some(window),
+      extent,
+      models,
+      scene,
+      initial_dyn_inst_count,
+      frames_in_flight,
+      gpu_override,
+    )
+  }
+
+  // renders to an offscreen image instead of a window surface
+  pub fn new_headless(
+    extent: vk::Extent2D,
+    models: Models,
+    scene: &StaticScene,
+    initial_dyn_inst_count: u64,
+    frames_in_flight: usize,
+    gpu_override: Option<GpuOverride>,
+  ) -> RenderResult<Self> {
+    Self::create(
+      None,
+      extent,
+      models,
+      scene,
+      initial_dyn_inst_count,
+      frames_in_flight,
+      gpu_override,
+    )
+  }
+
+  // whether a device can be created on this machine, used to skip tests that require one
+  #[cfg(test)]
+  pub fn is_available() -> bool {
+    #[cfg(feature = "load_vulkan")]
+    let entry = match unsafe { ash::Entry::load() } {
+      Ok(entry) => entry,
+      Err(_) => return false,
+    };
+    #[cfg(not(feature = "load_vulkan"))]
+    let entry = unsafe { get_entry() }.unwrap();
+
+    #[cfg(feature = "vulkan_vl")]
+    if check_validation_layers_support(&entry).is_err() {
+      return false;
+    }
+
+    let app_info = vk::ApplicationInfo {
+      s_type: vk::StructureType::APPLICATION_INFO,
+      api_version: vk::API_VERSION_1_3,
+      p_application_name: ptr::null(),
+      application_version: 0,
+      p_engine_name: ptr::null(),
+      engine_version: 0,
+      p_next: ptr::null(),
+    };
+    let create_info = vk::InstanceCreateInfo {
+      s_type: vk::StructureType::INSTANCE_CREATE_INFO,
+      p_next: ptr::null(),
+      p_application_info: &app_info,
+      pp_enabled_layer_names: ptr::null(),
+      enabled_layer_count: 0,
+      pp_enabled_extension_names: ptr::null(),
+      enabled_extension_count: 0,
+      flags: vk::InstanceCreateFlags::empty(),
+    };
+    unsafe {
+      let instance = match entry.create_instance(&create_info, None) {
+        Ok(instance) => instance,
+        Err(_) => return false,
+      };
+      let available = instance
+        .enumerate_physical_devices()
+        .is_ok_and(|devices| !devices.is_empty());
+      instance.destroy_instance(None);
+      available
+    }
+  }
+
+  fn create(
+    window: Option<&Window>,
+    extent: vk::Extent2D,
+    models: Models,
+    scene: &StaticScene,
+    initial_dyn_inst_count: u64,
+    frames_in_flight: usize,
+    gpu_override: Option<GpuOverride>,
+  ) -> RenderResult<Self> {
+    let entry: ash::Entry = unsafe { get_entry()? };
 
     #[cfg(feature = "vulkan_vl")]
-    check_validation_layers_support(&entry)
-      .unwrap_or_else(|name| panic!("The validation layer {:?} was not found", name));
+    check_validation_layers_support(&entry)?;
     #[cfg(feature = "vulkan_vl")]
     let vl_pointers: Vec<*const c_char> =
       VALIDATION_LAYERS.iter().map(|name| name.as_ptr()).collect();
     #[cfg(feature = "vulkan_vl")]
     let debug_create_info = DebugUtils::get_debug_messenger_create_info();
 
-    let window = Self::init_window(event_loop);
-
     #[cfg(feature = "vulkan_vl")]
-    let instance = objects::create_instance(&entry, &window, &vl_pointers, &debug_create_info);
+    let instance = objects::create_instance(&entry, window, &vl_pointers, &debug_create_info)?;
     #[cfg(not(feature = "vulkan_vl"))]
-    let instance = objects::create_instance(&entry, &window);
+    let instance = objects::create_instance(&entry, window)?;
 
     #[cfg(feature = "vulkan_vl")]
-    let debug_utils = DebugUtils::setup(&entry, &instance, debug_create_info);
+    let debug_utils = DebugUtils::setup(&entry, &instance, debug_create_info)?;
 
-    let (surface, surface_loader) = objects::create_surface(&entry, &instance, &window);
-
-    let device_features = vk::PhysicalDeviceFeatures::default();
-    let device_extensions: Vec<String> = DEVICE_EXTENSIONS.iter().map(|x| x.to_string()).collect();
-    let (physical_device, queue_family_indices) = unsafe {
-      objects::select_physical_device(
-        &instance,
-        &surface,
-        &surface_loader,
-        &device_extensions,
-        &device_features,
-      )
-    };
+    let surface = window
+      .map(|window| objects::create_surface(&entry, &instance, window))
+      .transpose()?;
 
-    #[cfg(feature = "vulkan_vl")]
-    let (logical_device, queues) = objects::create_logical_device(
-      &instance,
-      &physical_device,
-      &device_features,
-      &device_extensions,
-      &queue_family_indices,
-      &vl_pointers,
-    );
-    #[cfg(not(feature = "vulkan_vl"))]
-    let (logical_device, queues) = objects::create_logical_device(
+    let shader_code = ShaderCode::default();
+    let dev = DeviceObjects::create(
       &instance,
-      &physical_device,
-      &device_features,
-      &device_extensions,
-      &queue_family_indices,
-    );
+      surface
+        .as_ref()
+        .map(|(surface, surface_loader)| (*surface, surface_loader)),
+      extent,
+      &models,
+      scene,
+      &shader_code,
+      initial_dyn_inst_count,
+      frames_in_flight,
+      gpu_override.as_ref(),
+    )?;
 
-    let swapchains = Swapchains::new(
-      &instance,
-      physical_device,
-      &logical_device,
+    let loader = if models.pending().next().is_some() {
+      let loader = AssetLoader::spawn();
+      for (handle, path) in models.pending() {
+        loader.request(handle, path.to_owned());
+      }
+      Some(loader)
+    } else {
+      None
+    };
+
+    Ok(Self {
+      _entry: entry,
+      instance,
+      #[cfg(feature = "vulkan_vl")]
+      debug_utils,
       surface,
-      &surface_loader,
-      &window.inner_size(),
-    );
+      shader_code,
+      model_props: models.properties(),
+      model_states: models.states(),
+      loader,
+      loaded_textures: Vec::new(),
+      models,
+      scene: scene.clone(),
+      initial_dyn_inst_count,
+      frames_in_flight,
+      gpu_override,
+      dev,
+      lost: false,
+      #[cfg(test)]
+      fake_device_lost: false,
+    })
+  }
 
-    let render_pass = objects::create_render_pass(&logical_device, swapchains.get_format());
+  pub fn device(&self) -> &ash::Device {
+    &self.dev.device
+  }
 
-    let mut descriptor_sets = DescriptorSets::new(&logical_device);
+  // whether recreating a lost device failed, leaving the renderer unusable
+  pub fn is_lost(&self) -> bool {
+    self.lost
+  }
 
-    let pipelines = Pipelines::new(
-      &logical_device,
-      swapchains.get_extent(),
-      render_pass,
-      &descriptor_sets,
-    );
+  // waits for all submitted work, failures are only logged as this is done before destroying
+  pub fn wait_idle(&self) {
+    if self.lost {
+      return;
+    }
+    if let Err(err) = unsafe { self.dev.device.device_wait_idle() } {
+      warn!("Failed to wait on device: {}", err);
+    }
+  }
 
-    let framebuffers = objects::create_framebuffers(
-      &logical_device,
-      render_pass,
-      &swapchains.get_image_views(),
-      &swapchains.get_extent(),
-    );
+  // destroys everything created from the lost device and creates it again from the same instance
+  // and surface, uploading the models and the static scene again
+  // objects created by the caller from the old device, like semaphores, have to be destroyed
+  // beforehand and created again afterwards
+  pub unsafe fn recover_lost_device(&mut self) -> RenderResult<()> {
+    warn!("Device lost, recreating it");
+    // returns right away if the device is really lost
+    self.wait_idle();
+    let extent = self.dev.target.get_extent();
+    self.dev.destroy_self();
+    self.lost = true;
 
-    let mut command_buffer_pools =
-      CommandBufferPools::create(&logical_device, &queue_family_indices);
-    let models = Models::load();
-    let buffers = Buffers::create(
-      &instance,
-      &logical_device,
-      physical_device,
-      &queue_family_indices,
-      &queues,
-      &mut command_buffer_pools,
-      &models,
-      max_dyn_inst_count,
-    );
+    self.dev = DeviceObjects::create(
+      &self.instance,
+      self
+        .surface
+        .as_ref()
+        .map(|(surface, surface_loader)| (*surface, surface_loader)),
+      extent,
+      &self.models,
+      &self.scene,
+      &self.shader_code,
+      self.initial_dyn_inst_count,
+      self.frames_in_flight,
+      self.gpu_override.as_ref(),
+    )?;
+    // every model loaded so far got uploaded along with the device objects
+    self.model_props = self.models.properties();
+    self.model_states = self.models.states();
+    self.loaded_textures.clear();
+    self.lost = false;
+    info!("Device recreated");
+    Ok(())
+  }
 
-    descriptor_sets
-      .pool
-      .update_all_inst_static(&logical_device, &buffers);
+  // makes the next submit fail as if the device was lost
+  #[cfg(test)]
+  pub fn fake_device_lost(&mut self) {
+    self.fake_device_lost = true;
+  }
 
-    Self {
-      _entry: entry,
-      window,
-      instance,
-      #[cfg(feature = "vulkan_vl")]
-      debug_utils,
-      physical_device,
-      _queue_family_indices: queue_family_indices,
-      device: logical_device,
-      queues,
-      surface,
-      surface_loader,
-      swapchains,
-      pipelines,
-      render_pass,
-      framebuffers,
-      buffers,
-      command_buffer_pools,
-      descriptor_sets,
-      model_props: models.into_properties(),
+  #[cfg(test)]
+  fn take_fake_device_lost(&mut self) -> RenderResult<()> {
+    if std::mem::take(&mut self.fake_device_lost) {
+      return Err(vk::Result::ERROR_DEVICE_LOST).context("Fake device loss");
     }
+    Ok(())
+  }
+
+  // models that finished loading are pending until they are drawn
+  pub fn model_state(&self, handle: ModelHandle) -> LoadState {
+    match self.models.state(handle) {
+      LoadState::Ready => self.model_states[handle.index()],
+      state => state,
+    }
+  }
+
+  pub fn memory_stats(&self) -> AllocatorStats {
+    self.dev.allocator.stats()
   }
 
-  fn init_window(event_loop: &EventLoop<()>) -> Window {
-    winit::window::WindowBuilder::new()
-      .with_title(WINDOW_TITLE)
-      .with_inner_size(winit::dpi::LogicalSize::new(
-        INITIAL_WINDOW_WIDTH,
-        INITIAL_WINDOW_HEIGHT,
-      ))
-      .build(event_loop)
-      .expect("Failed to create window.")
+  fn swapchains(&mut self) -> &mut Swapchains {
+    match &mut self.dev.target {
+      RenderTarget::Surface { swapchains, .. } => swapchains,
+      RenderTarget::Offscreen(_) => panic!("Headless renderer has no swapchain"),
+    }
   }
 
   pub unsafe fn record_main_command_buffer(
     &mut self,
     i: usize,
     framebuffer_i: usize,
-    dyn_inst_props: &Vec<InstProperties>,
-  ) {
-    self.command_buffer_pools.main.record(
+    draws: &IndirectDraws,
+  ) -> RenderResult<()> {
+    self.dev.command_buffer_pools.main.record(
       i,
-      &self.device,
-      self.render_pass,
-      self.framebuffers[framebuffer_i],
-      self.swapchains.get_extent(),
-      &self.pipelines,
-      &self.buffers,
-      &self.model_props,
-      dyn_inst_props,
-    );
+      &self.dev.device,
+      self.dev.render_pass,
+      self.dev.framebuffers[framebuffer_i],
+      self.dev.target.get_extent(),
+      &self.dev.pipelines,
+      &self.dev.buffers,
+      self.dev.descriptor_sets.pool.textures[i],
+      draws.color_count as u32,
+      draws.textured_count() as u32,
+    )
+  }
+
+  // push constants of the static and the dynamic instance dispatches
+  pub(super) fn inst_push_constants(
+    &self,
+    camera: &RenderCamera,
+    dyn_inst_count: u32,
+    dyn_draw_count: u32,
+  ) -> (InstancePushConstants, InstancePushConstants) {
+    let static_inst_count = self.dev.buffers.local_constant.inst.count;
+    let static_draw_count = self.dev.buffers.local_constant.inst.props.len() as u32;
+    let inst_static = InstancePushConstants {
+      projection_view: camera.projection_view(),
+      inst_count: static_inst_count,
+      inst_offset: 0,
+      draw_offset: 0,
+      draw_count: static_draw_count,
+    };
+    // dynamic instances and draws come after the static ones
+    let inst_dyn = InstancePushConstants {
+      projection_view: camera.projection_view(),
+      inst_count: dyn_inst_count,
+      inst_offset: static_inst_count,
+      draw_offset: static_draw_count,
+      draw_count: dyn_draw_count,
+    };
+    (inst_static, inst_dyn)
   }
 
-  pub unsafe fn record_inst_static_comm_buffer(&mut self, i: usize, camera: &RenderCamera) {
-    self.command_buffer_pools.compute.record_inst_static(
+  pub unsafe fn record_inst_static_comm_buffer(
+    &mut self,
+    i: usize,
+    push_constants: &InstancePushConstants,
+  ) -> RenderResult<()> {
+    self.dev.command_buffer_pools.compute.record_inst_static(
       i,
-      &self.device,
-      &self.pipelines,
-      &self.buffers,
-      &self.descriptor_sets,
-      &camera.projection_view(),
-    );
+      &self.dev.device,
+      &self.dev.pipelines,
+      &self.dev.descriptor_sets,
+      push_constants,
+    )
   }
 
   pub unsafe fn record_inst_dyn_comm_buffer(
     &mut self,
     i: usize,
-    camera: &RenderCamera,
-    dyn_inst_count: u32,
-  ) {
-    self.command_buffer_pools.compute.record_inst_dyn(
+    push_constants: &InstancePushConstants,
+  ) -> RenderResult<()> {
+    self.dev.command_buffer_pools.compute.record_inst_dyn(
       i,
-      &self.device,
-      &self.pipelines,
-      &self.descriptor_sets,
-      &camera.projection_view(),
-      dyn_inst_count,
+      &self.dev.device,
+      &self.dev.pipelines,
+      &self.dev.descriptor_sets,
+      push_constants,
     )
   }
 
-  pub fn get_aspect_ratio(&self) -> f32 {
-    let window_size = self.window.inner_size();
-    window_size.width as f32 / window_size.height as f32
+  // one draw for each static and dynamic instance range, with instance counts left at 0 to be
+  // filled by the compute shader
+  pub(super) fn indirect_draws(&self, dyn_inst_props: &[InstProperties]) -> IndirectDraws {
+    let static_inst_count = self.dev.buffers.local_constant.inst.count;
+    let ranges = self
+      .dev
+      .buffers
+      .local_constant
+      .inst
+      .props
+      .iter()
+      .map(|inst_p| (inst_p, 0))
+      .chain(
+        dyn_inst_props
+          .iter()
+          .map(|inst_p| (inst_p, static_inst_count)),
+      )
+      .map(|(inst_p, base_offset)| {
+        (
+          &self.model_props[inst_p.model_i],
+          base_offset + inst_p.inst_offset,
+          inst_p.inst_count,
+        )
+      });
+    IndirectDraws::new(ranges)
   }
 
-  pub unsafe fn update_instance_data(&mut self, i: usize, data: &Vec<MatrixInstance>) {
-    self.buffers.update_instance_data(i, &self.device, data);
+  // records all command buffers of frame i and updates its dynamic instance data
+  pub unsafe fn record_frame(
+    &mut self,
+    i: usize,
+    framebuffer_i: usize,
+    camera: &RenderCamera,
+    dyn_instances: &[MatrixInstance],
+    dyn_inst_props: &[InstProperties],
+  ) -> RenderResult<()> {
+    // frame i is not in use, so its descriptor sets can be updated and its dynamic instance buffers
+    // reallocated
+    if std::mem::take(&mut self.dev.outdated_texture_sets[i]) {
+      self
+        .dev
+        .descriptor_sets
+        .pool
+        .update_textures(i, &self.dev.device, &self.dev.textures);
+    }
+
+    if self.dev.buffers.reserve_dyn_instances(
+      i,
+      &self.dev.device,
+      &mut self.dev.allocator,
+      &self.dev.queue_family_indices,
+      dyn_instances.len() as u64,
+    )? {
+      self
+        .dev
+        .descriptor_sets
+        .pool
+        .update_inst_static(i, &self.dev.device, &self.dev.buffers);
+    }
+
+    let draws = self.indirect_draws(dyn_inst_props);
+    self.dev.buffers.update_draws(i, &draws.draws);
+
+    let (static_push_constants, dyn_push_constants) = self.inst_push_constants(
+      camera,
+      dyn_instances.len() as u32,
+      dyn_inst_props.len() as u32,
+    );
+
+    self.record_main_command_buffer(i, framebuffer_i, &draws)?;
+    self.record_inst_static_comm_buffer(i, &static_push_constants)?;
+
+    self.update_inst_dyn_descriptor_set(i, dyn_instances.len() as u64);
+    self.update_instance_data(i, dyn_instances);
+    self.record_inst_dyn_comm_buffer(i, &dyn_push_constants)
+  }
+
+  // receives models loaded in the background and uploads them, frame is the number of the next
+  // frame to be recorded
+  // models that finished uploading are drawn starting from that frame, while the geometry they
+  // replace is retired
+  pub unsafe fn stream_models(&mut self, frame: u64) -> RenderResult<()> {
+    self.receive_loaded_models();
+
+    // frees the staging memory of finished uploads and submits the acquisitions of copied ones
+    let completed = self
+      .dev
+      .uploader
+      .poll(&self.dev.device, &mut self.dev.allocator)?;
+    if let Some(upload) = self
+      .dev
+      .model_upload
+      .take_if(|upload| completed.contains(&upload.id))
+    {
+      let retired = RetiredResources {
+        geometry: Some(std::mem::replace(
+          &mut self.dev.buffers.local_constant.geometry,
+          upload.geometry,
+        )),
+        ..Default::default()
+      };
+      self.dev.retired.retire(frame, retired);
+      self.dev.textures.textures.extend(upload.textures);
+      self.dev.outdated_texture_sets.fill(true);
+      self.model_props = upload.model_props;
+      self.model_states = upload.model_states;
+    }
+
+    // models that finish loading while an upload is in flight wait for the next one
+    let outdated = self.models.handles().any(|handle| {
+      self.models.state(handle) == LoadState::Ready
+        && self.model_states[handle.index()] != LoadState::Ready
+    });
+    if outdated && self.dev.model_upload.is_none() {
+      self.submit_model_upload()?;
+    }
+    Ok(())
+  }
+
+  fn receive_loaded_models(&mut self) {
+    let loader = match &self.loader {
+      Some(loader) => loader,
+      None => return,
+    };
+    for loaded in loader.receive() {
+      match loaded.result {
+        Ok((mesh, texture)) => {
+          self.models.finish_loading(loaded.handle, mesh);
+          self.loaded_textures.extend(texture);
+        }
+        Err(err) => {
+          error!("{}", err);
+          self.models.fail(loaded.handle);
+        }
+      }
+    }
+    if self.models.pending().next().is_none() {
+      info!("Finished loading models");
+      self.loader = None;
+    }
+  }
+
+  // uploads the geometry of every loaded model, as their offsets change, but only new textures
+  unsafe fn submit_model_upload(&mut self) -> RenderResult<()> {
+    let (geometry, mut operations) = ModelGeometry::stage(
+      &self.dev.device,
+      &mut self.dev.allocator,
+      &self.dev.queue_family_indices,
+      &self.models,
+    )?;
+    let mut textures = Vec::with_capacity(self.loaded_textures.len());
+    for data in self.loaded_textures.drain(..) {
+      let (texture, operation) = Texture::stage(
+        &self.dev.device,
+        &mut self.dev.allocator,
+        self.dev.queue_family_indices.graphics,
+        &data,
+      )?;
+      textures.push(texture);
+      operations.push(operation);
+    }
+    let id = self
+      .dev
+      .uploader
+      .submit(&self.dev.device, &mut self.dev.allocator, operations)?;
+    self.dev.model_upload = Some(ModelUpload {
+      id,
+      geometry,
+      textures,
+      model_props: self.models.properties(),
+      model_states: self.models.states(),
+    });
+    Ok(())
+  }
+
+  pub unsafe fn submit_compute(
+    &mut self,
+    i: usize,
+    signal_semaphore: vk::Semaphore,
+  ) -> RenderResult<()> {
+    #[cfg(test)]
+    self.take_fake_device_lost()?;
+
+    let signal_semaphores = [signal_semaphore];
+    // in theory these can be changed to execute on different queues (concurrently)
+    let command_buffers = [
+      self.dev.command_buffer_pools.compute.inst_static[i],
+      self.dev.command_buffer_pools.compute.inst_dyn[i],
+    ];
+    let submit_infos = [vk::SubmitInfo {
+      s_type: vk::StructureType::SUBMIT_INFO,
+      p_next: ptr::null(),
+      wait_semaphore_count: 0,
+      p_wait_semaphores: ptr::null(),
+      p_wait_dst_stage_mask: ptr::null(),
+      command_buffer_count: command_buffers.len() as u32,
+      p_command_buffers: command_buffers.as_ptr(),
+      signal_semaphore_count: signal_semaphores.len() as u32,
+      p_signal_semaphores: signal_semaphores.as_ptr(),
+    }];
+    self
+      .dev
+      .device
+      .queue_submit(self.dev.queues.compute, &submit_infos, vk::Fence::null())
+      .context("Failed to submit compute work")
+  }
+
+  pub unsafe fn submit_graphics(
+    &self,
+    i: usize,
+    wait_semaphores: &[vk::Semaphore],
+    wait_stages: &[vk::PipelineStageFlags],
+    signal_semaphores: &[vk::Semaphore],
+    fence: vk::Fence,
+  ) -> RenderResult<()> {
+    assert_eq!(wait_semaphores.len(), wait_stages.len());
+    let submit_infos = [vk::SubmitInfo {
+      s_type: vk::StructureType::SUBMIT_INFO,
+      p_next: ptr::null(),
+      wait_semaphore_count: wait_semaphores.len() as u32,
+      p_wait_semaphores: wait_semaphores.as_ptr(),
+      p_wait_dst_stage_mask: wait_stages.as_ptr(),
+      command_buffer_count: 1,
+      p_command_buffers: &self.dev.command_buffer_pools.main.command_buffer(i),
+      signal_semaphore_count: signal_semaphores.len() as u32,
+      p_signal_semaphores: signal_semaphores.as_ptr(),
+    }];
+    self
+      .dev
+      .device
+      .queue_submit(self.dev.queues.graphics, &submit_infos, fence)
+      .context("Failed to submit graphics work")
+  }
+
+  pub unsafe fn update_instance_data(&mut self, i: usize, data: &[MatrixInstance]) {
+    self.dev.buffers.update_instance_data(i, data);
   }
 
   pub unsafe fn acquire_next_image(
     &mut self,
     semaphore: vk::Semaphore,
   ) -> Result<(u32, bool), vk::Result> {
-    self.swapchains.acquire_next_image(semaphore)
+    self.swapchains().acquire_next_image(semaphore)
   }
 
   pub unsafe fn queue_present(
@@ -278,79 +720,502 @@ impl Renderer {
     image_index: u32,
     wait_semaphores: &[vk::Semaphore],
   ) -> Result<bool, vk::Result> {
+    let graphics_queue = self.dev.queues.graphics;
     self
-      .swapchains
-      .queue_present(image_index, self.queues.graphics, wait_semaphores)
-  }
-
-  pub unsafe fn recreate_swapchain(&mut self, swapchain_render_finished: vk::Fence) {
-    // TODO:
-    // currenty the code waits for the old swapchain to finish rendering before recreating its dependencies
-    // maybe there is a way to make it continue working while already preparing to acquire and present at the new swapchain
-    // however, marking render_pass and pipeline as "old" and creating new ones seems quite bothersome and not right
-
-    // old swapchain becomes retired
-    let changes = self.swapchains.recreate_swapchain(
-      self.physical_device,
-      &self.device,
-      self.surface,
-      &self.surface_loader,
-      &self.window.inner_size(),
-    );
+      .swapchains()
+      .queue_present(image_index, graphics_queue, wait_semaphores)
+  }
 
-    unsafe {
-      self
-        .device
-        .wait_for_fences(&[swapchain_render_finished], true, std::u64::MAX)
-        .expect("Failed to wait for fence");
-    }
+  // frame is the number of the first frame that will render to the new swapchain
+  // nothing is waited upon: frames in flight keep rendering and presenting to the old swapchain,
+  // whose resources get destroyed by destroy_retired once these frames finish
+  pub unsafe fn recreate_swapchain(
+    &mut self,
+    window_size: &PhysicalSize<u32>,
+    frame: u64,
+  ) -> RenderResult<()> {
+    let mut retired = RetiredResources::default();
+    let result = self.replace_swapchain_resources(window_size, &mut retired);
+    // whatever got replaced before a failure is still used by the frames in flight
+    self.dev.retired.retire(frame, retired);
+    result
+  }
 
-    for &framebuffer in self.framebuffers.iter() {
-      self.device.destroy_framebuffer(framebuffer, None);
-    }
+  unsafe fn replace_swapchain_resources(
+    &mut self,
+    window_size: &PhysicalSize<u32>,
+    retired: &mut RetiredResources,
+  ) -> RenderResult<()> {
+    let (surface, surface_loader, swapchains) = match &mut self.dev.target {
+      RenderTarget::Surface {
+        surface,
+        loader,
+        swapchains,
+      } => (*surface, loader, swapchains),
+      RenderTarget::Offscreen(_) => panic!("Headless renderer has no swapchain"),
+    };
 
+    let (old_swapchain, changes) = swapchains.recreate_swapchain(
+      self.dev.physical_device,
+      &self.dev.device,
+      surface,
+      surface_loader,
+      window_size,
+    )?;
+    retired.swapchain = Some(old_swapchain);
+
+    if changes.format {
+      let render_pass = objects::create_render_pass(
+        &self.dev.device,
+        self.dev.target.get_format(),
+        self.dev.depth_buffer.format,
+        self.dev.target.final_layout(),
+      )?;
+      retired.render_pass = Some(std::mem::replace(&mut self.dev.render_pass, render_pass));
+    }
     if changes.extent {
-      if changes.format {
-        self.device.destroy_render_pass(self.render_pass, None);
-        self.render_pass = objects::create_render_pass(&self.device, self.swapchains.get_format());
+      let depth_buffer = DepthBuffer::create(
+        &self.instance,
+        self.dev.physical_device,
+        &self.dev.device,
+        &mut self.dev.allocator,
+        self.dev.target.get_extent(),
+      )?;
+      retired.depth_buffer = Some(std::mem::replace(&mut self.dev.depth_buffer, depth_buffer));
+    }
+    // pipelines don't depend on the extent, as their viewport and scissor are dynamic
+    if changes.format {
+      retired.graphics_pipelines = Some(self.dev.pipelines.recreate_main(
+        &self.dev.device,
+        self.dev.render_pass,
+        &self.dev.descriptor_sets,
+        self.dev.pipeline_cache.cache,
+        &self.shader_code,
+      )?);
+    }
+
+    let framebuffers = objects::create_framebuffers(
+      &self.dev.device,
+      self.dev.render_pass,
+      self.dev.target.get_image_views(),
+      self.dev.depth_buffer.view,
+      &self.dev.target.get_extent(),
+    )?;
+    retired.framebuffers = std::mem::replace(&mut self.dev.framebuffers, framebuffers);
+    Ok(())
+  }
+
+  // rebuilds the pipelines of recompiled shaders, frame is the number of the first frame that will
+  // use them
+  // if that fails, the previous code and pipelines are kept
+  pub fn reload_shaders(&mut self, reloaded: ReloadedShaders, frame: u64) -> RenderResult<()> {
+    let mut retired = RetiredResources::default();
+    // each group of pipelines is rebuilt with its own new code, which is reverted if that fails
+    // without affecting the other group
+    let graphics = reloaded.graphics.map_or(Ok(()), |code| {
+      self.with_shader_code(code, |renderer| {
+        info!("Rebuilding graphics pipelines");
+        retired.graphics_pipelines = Some(renderer.dev.pipelines.recreate_main(
+          &renderer.dev.device,
+          renderer.dev.render_pass,
+          &renderer.dev.descriptor_sets,
+          renderer.dev.pipeline_cache.cache,
+          &renderer.shader_code,
+        )?);
+        Ok(())
+      })
+    });
+    let compute = reloaded.compute.map_or(Ok(()), |code| {
+      self.with_shader_code(code, |renderer| {
+        info!("Rebuilding compute pipelines");
+        retired.compute_pipelines = Some(renderer.dev.pipelines.recreate_compute(
+          &renderer.dev.device,
+          &renderer.dev.descriptor_sets,
+          renderer.dev.pipeline_cache.cache,
+          &renderer.shader_code,
+        )?);
+        Ok(())
+      })
+    });
+    self.dev.retired.retire(frame, retired);
+
+    match (graphics, compute) {
+      // only one error can be returned, the other one is reported here unless it is a Vulkan error
+      (Err(graphics), Err(compute)) if compute.vk_result().is_some() => {
+        error!("{}", graphics);
+        Err(compute)
       }
-      self
-        .pipelines
-        .recreate_main(&self.device, self.swapchains.get_extent(), self.render_pass);
+      (Err(graphics), Err(compute)) => {
+        error!("{}", compute);
+        Err(graphics)
+      }
+      (graphics, compute) => graphics.and(compute),
     }
-    // kill retired swapchain
-    self.swapchains.destroy_old(&self.device);
-
-    self.framebuffers = objects::create_framebuffers(
-      &self.device,
-      self.render_pass,
-      &self.swapchains.get_image_views(),
-      &self.swapchains.get_extent(),
-    );
   }
 
-  pub fn update_inst_dyn_descriptor_set(&mut self, i: usize, dyn_inst_count: u64) {
+  // replaces shader code for rebuild, and restores the previous code if it fails
+  fn with_shader_code(
+    &mut self,
+    code: Vec<(&'static str, Vec<u8>)>,
+    rebuild: impl FnOnce(&mut Self) -> RenderResult<()>,
+  ) -> RenderResult<()> {
+    let previous_code = self.shader_code.clone();
+    for (name, code) in code {
+      self.shader_code.replace(name, code);
+    }
+    let result = rebuild(self);
+    if result.is_err() {
+      self.shader_code = previous_code;
+    }
+    result
+  }
+
+  // destroys resources that were retired before the last finished frame
+  pub unsafe fn destroy_retired(&mut self, finished_frame_count: u64) {
+    for mut retired in self.dev.retired.release(finished_frame_count) {
+      retired.destroy_self(&self.dev.device, &mut self.dev.allocator, &self.dev.target);
+    }
+  }
+
+  // copies the image at image_index back to the cpu after rendering to it has finished
+  // should be called before the image gets presented
+  pub unsafe fn capture_frame(
+    &mut self,
+    image_index: usize,
+    render_finished: vk::Fence,
+  ) -> RenderResult<CapturedFrame> {
+    let extent = self.dev.target.get_extent();
+    let format = self.dev.target.get_format();
+    let image = self.dev.target.get_images()[image_index];
+
+    if !CapturedFrame::supports(format) {
+      return Err(RenderError::UnsupportedCaptureFormat(format));
+    }
+    if !self
+      .dev
+      .target
+      .get_image_usage()
+      .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+    {
+      return Err(RenderError::CaptureNotSupported);
+    }
     self
-      .descriptor_sets
+      .dev
+      .device
+      .wait_for_fences(&[render_finished], true, u64::MAX)
+      .context("Failed to wait for fence")?;
+
+    // all supported formats have 4 bytes per pixel
+    let size = extent.width as u64 * extent.height as u64 * 4;
+    let data = self.read_back(size, |dev, readback| {
+      dev.command_buffer_pools.main.record_capture(
+        &dev.device,
+        image,
+        dev.target.final_layout(),
+        extent,
+        readback,
+      )
+    })?;
+
+    CapturedFrame::from_raw(format, extent, data)
+  }
+
+  // reads back the draws and transformed instances of frame i, which should have finished
+  #[cfg(test)]
+  pub unsafe fn read_instances(
+    &mut self,
+    i: usize,
+    draw_count: usize,
+    inst_count: usize,
+  ) -> RenderResult<(Vec<IndirectDraw>, Vec<RenderInstance>)> {
+    let draws = self.dev.buffers.host_writable.read_draws(i, draw_count);
+
+    let size = (std::mem::size_of::<RenderInstance>() * inst_count) as u64;
+    let src = self.dev.buffers.local.inst[i].buffer;
+    let instances = self
+      .read_back(size, |dev, readback| {
+        dev
+          .command_buffer_pools
+          .main
+          .record_buffer_readback(&dev.device, src, readback, size)
+      })?
+      .chunks_exact(std::mem::size_of::<RenderInstance>())
+      .map(|bytes| ptr::read_unaligned(bytes.as_ptr() as *const RenderInstance))
+      .collect();
+
+    Ok((draws, instances))
+  }
+
+  // submits the capture command buffer, recorded by record to copy into a temporary host buffer of
+  // size bytes, and returns the buffer contents
+  // the buffer is destroyed whether or not this succeeds
+  unsafe fn read_back(
+    &mut self,
+    size: u64,
+    record: impl FnOnce(&mut DeviceObjects, vk::Buffer) -> RenderResult<()>,
+  ) -> RenderResult<Vec<u8>> {
+    let mut readback = ReadbackBuffer::create(&self.dev.device, &mut self.dev.allocator, size)?;
+    let result = (|| {
+      record(&mut self.dev, readback.buffer)?;
+
+      let submit_infos = [vk::SubmitInfo {
+        s_type: vk::StructureType::SUBMIT_INFO,
+        p_next: ptr::null(),
+        wait_semaphore_count: 0,
+        p_wait_semaphores: ptr::null(),
+        p_wait_dst_stage_mask: ptr::null(),
+        command_buffer_count: 1,
+        p_command_buffers: &self.dev.command_buffer_pools.main.capture,
+        signal_semaphore_count: 0,
+        p_signal_semaphores: ptr::null(),
+      }];
+      self
+        .dev
+        .device
+        .queue_submit(self.dev.queues.graphics, &submit_infos, vk::Fence::null())
+        .context("Failed to submit readback")?;
+      // the buffer can't be destroyed while the copy may still be running
+      self
+        .dev
+        .device
+        .queue_wait_idle(self.dev.queues.graphics)
+        .context("Failed to wait for readback")?;
+      Ok(readback.read())
+    })();
+    readback.destroy_self(&self.dev.device, &mut self.dev.allocator);
+    result
+  }
+
+  pub fn update_inst_dyn_descriptor_set(&mut self, i: usize, dyn_inst_count: u64) {
+    self.dev.descriptor_sets.pool.update_inst_dyn(
+      i,
+      &self.dev.device,
+      &self.dev.buffers,
+      dyn_inst_count,
+    );
+  }
+}
+
+impl DeviceObjects {
+  #[allow(clippy::too_many_arguments)]
+  fn create(
+    instance: &ash::Instance,
+    surface: Option<(vk::SurfaceKHR, &Surface)>,
+    extent: vk::Extent2D,
+    models: &Models,
+    scene: &StaticScene,
+    shader_code: &ShaderCode,
+    initial_dyn_inst_count: u64,
+    frames_in_flight: usize,
+    gpu_override: Option<&GpuOverride>,
+  ) -> RenderResult<Self> {
+    let device_features = vk::PhysicalDeviceFeatures {
+      // indirect draws start at their own range of the instance buffer
+      draw_indirect_first_instance: vk::TRUE,
+      // each pipeline draws all of its models with a single indirect call
+      multi_draw_indirect: vk::TRUE,
+      // textured models index into an array of textures
+      shader_sampled_image_array_dynamic_indexing: vk::TRUE,
+      ..Default::default()
+    };
+    // presentation extensions are not needed when rendering headlessly
+    let device_extensions: Vec<String> = if surface.is_some() {
+      DEVICE_EXTENSIONS.iter().map(|x| x.to_string()).collect()
+    } else {
+      Vec::new()
+    };
+    let (physical_device, queue_family_indices, device_identity) = unsafe {
+      objects::select_physical_device(
+        instance,
+        surface,
+        &device_extensions,
+        &device_features,
+        gpu_override,
+      )?
+    };
+
+    #[cfg(feature = "vulkan_vl")]
+    let vl_pointers: Vec<*const c_char> =
+      VALIDATION_LAYERS.iter().map(|name| name.as_ptr()).collect();
+    #[cfg(feature = "vulkan_vl")]
+    let (logical_device, queues) = objects::create_logical_device(
+      instance,
+      &physical_device,
+      &device_features,
+      &device_extensions,
+      &queue_family_indices,
+      &vl_pointers,
+    )?;
+    #[cfg(not(feature = "vulkan_vl"))]
+    let (logical_device, queues) = objects::create_logical_device(
+      instance,
+      &physical_device,
+      &device_features,
+      &device_extensions,
+      &queue_family_indices,
+    )?;
+
+    let mut allocator = Allocator::new(instance, physical_device);
+
+    let target = match surface {
+      Some((surface, loader)) => {
+        let swapchains = Swapchains::new(
+          instance,
+          physical_device,
+          &logical_device,
+          surface,
+          loader,
+          &PhysicalSize::new(extent.width, extent.height),
+        )?;
+        RenderTarget::Surface {
+          surface,
+          loader: loader.clone(),
+          swapchains,
+        }
+      }
+      None => RenderTarget::Offscreen(OffscreenTarget::create(
+        &logical_device,
+        &mut allocator,
+        extent,
+      )?),
+    };
+
+    let depth_buffer = DepthBuffer::create(
+      instance,
+      physical_device,
+      &logical_device,
+      &mut allocator,
+      target.get_extent(),
+    )?;
+
+    let render_pass = objects::create_render_pass(
+      &logical_device,
+      target.get_format(),
+      depth_buffer.format,
+      target.final_layout(),
+    )?;
+
+    // room is left for the textures of models that are still loading
+    let mut descriptor_sets =
+      DescriptorSets::new(&logical_device, models.texture_capacity(), frames_in_flight)?;
+
+    let pipeline_cache = PipelineCache::load(&logical_device, device_identity)?;
+    let pipelines = Pipelines::new(
+      &logical_device,
+      render_pass,
+      &descriptor_sets,
+      pipeline_cache.cache,
+      shader_code,
+    )?;
+
+    let framebuffers = objects::create_framebuffers(
+      &logical_device,
+      render_pass,
+      target.get_image_views(),
+      depth_buffer.view,
+      &target.get_extent(),
+    )?;
+
+    let command_buffer_pools =
+      CommandBufferPools::create(&logical_device, &queue_family_indices, frames_in_flight)?;
+    let mut uploader = Uploader::new(&logical_device, &queue_family_indices, &queues)?;
+    let buffers = Buffers::create(
+      &logical_device,
+      &mut allocator,
+      &queue_family_indices,
+      &mut uploader,
+      models,
+      scene,
+      initial_dyn_inst_count,
+      frames_in_flight,
+    )?;
+
+    let textures = Textures::load(
+      &logical_device,
+      &mut allocator,
+      &mut uploader,
+      queue_family_indices.graphics,
+      &models.textures,
+    )?;
+
+    info!("GPU memory after initialization:\n{}", allocator.stats());
+
+    descriptor_sets
+      .pool
+      .update_all_inst_static(&logical_device, &buffers);
+    descriptor_sets
       .pool
-      .update_inst_dyn(i, &self.device, &self.buffers, dyn_inst_count);
+      .update_all_textures(&logical_device, &textures);
+
+    Ok(Self {
+      physical_device,
+      queue_family_indices,
+      device: logical_device,
+      queues,
+      allocator,
+      target,
+      pipeline_cache,
+      pipelines,
+      render_pass,
+      depth_buffer,
+      framebuffers,
+      buffers,
+      textures,
+      command_buffer_pools,
+      uploader,
+      descriptor_sets,
+      model_upload: None,
+      outdated_texture_sets: vec![false; frames_in_flight],
+      retired: RetirementQueue::new(),
+    })
+  }
+
+  // the surface is left alive
+  unsafe fn destroy_self(&mut self) {
+    for mut retired in self.retired.release_all() {
+      retired.destroy_self(&self.device, &mut self.allocator, &self.target);
+    }
+    self.command_buffer_pools.destroy_self(&self.device);
+    self
+      .uploader
+      .destroy_self(&self.device, &mut self.allocator);
+    if let Some(mut upload) = self.model_upload.take() {
+      upload
+        .geometry
+        .destroy_self(&self.device, &mut self.allocator);
+      for texture in upload.textures.iter() {
+        texture.destroy_self(&self.device, &mut self.allocator);
+      }
+    }
+    self.buffers.destroy_self(&self.device, &mut self.allocator);
+    self
+      .textures
+      .destroy_self(&self.device, &mut self.allocator);
+    for &framebuffer in self.framebuffers.iter() {
+      self.device.destroy_framebuffer(framebuffer, None);
+    }
+    self.pipelines.destroy_self(&self.device);
+    self.pipeline_cache.destroy_self(&self.device);
+    self.descriptor_sets.destroy_self(&self.device);
+    self.device.destroy_render_pass(self.render_pass, None);
+    self
+      .depth_buffer
+      .destroy_self(&self.device, &mut self.allocator);
+    self.target.destroy_self(&self.device, &mut self.allocator);
+    self.allocator.destroy_self(&self.device);
+    self.device.destroy_device(None);
   }
 }
 
 impl Drop for Renderer {
   fn drop(&mut self) {
     unsafe {
-      self.command_buffer_pools.destroy_self(&self.device);
-      self.buffers.destroy_self(&self.device);
-      for &framebuffer in self.framebuffers.iter() {
-        self.device.destroy_framebuffer(framebuffer, None);
+      if !self.lost {
+        self.dev.destroy_self();
+      }
+      if let Some((surface, loader)) = &self.surface {
+        loader.destroy_surface(*surface, None);
       }
-      self.pipelines.destroy_self(&self.device);
-      self.descriptor_sets.destroy_self(&self.device);
-      self.device.destroy_render_pass(self.render_pass, None);
-      self.swapchains.destroy_self(&self.device);
-      self.device.destroy_device(None);
-      self.surface_loader.destroy_surface(self.surface, None);
       #[cfg(feature = "vulkan_vl")]
       self.debug_utils.destroy_self();
       self.instance.destroy_instance(None);
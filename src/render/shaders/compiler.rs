@@ -0,0 +1,71 @@
+// runs an external GLSL to SPIR-V compiler
+// also included by build.rs, so it can only depend on std
+
+use std::{
+  env,
+  path::{Path, PathBuf},
+  process::Command,
+};
+
+// path of the compiler to use, otherwise glslc or glslangValidator are searched for in PATH and in
+// the Vulkan SDK
+pub const COMPILER_ENV: &str = "SHADER_COMPILER";
+
+pub struct Compiler {
+  path: PathBuf,
+  // glslangValidator takes different arguments than glslc
+  glslang: bool,
+}
+
+impl Compiler {
+  pub fn find() -> Option<Self> {
+    if let Some(path) = env::var_os(COMPILER_ENV) {
+      return Some(Self::new(PathBuf::from(path)));
+    }
+
+    let sdk_bin = env::var_os("VULKAN_SDK").map(|sdk| PathBuf::from(sdk).join("bin"));
+    ["glslc", "glslangValidator"]
+      .into_iter()
+      .flat_map(|name| {
+        let in_sdk = sdk_bin.as_ref().map(|bin| bin.join(name));
+        std::iter::once(PathBuf::from(name)).chain(in_sdk)
+      })
+      .find(|path| {
+        Command::new(path)
+          .arg("--version")
+          .output()
+          .is_ok_and(|output| output.status.success())
+      })
+      .map(Self::new)
+  }
+
+  fn new(path: PathBuf) -> Self {
+    let glslang = path
+      .file_stem()
+      .is_some_and(|stem| stem.to_string_lossy().starts_with("glslang"));
+    Self { path, glslang }
+  }
+
+  pub fn compile(&self, source: &Path, output: &Path) -> Result<(), String> {
+    let mut command = Command::new(&self.path);
+    if self.glslang {
+      command.arg("-V");
+    }
+    let result = command
+      .arg(source)
+      .arg("-o")
+      .arg(output)
+      .output()
+      .map_err(|err| format!("Failed to run {:?}: {}", self.path, err))?;
+    if result.status.success() {
+      Ok(())
+    } else {
+      // glslangValidator reports errors to stdout
+      Err(format!(
+        "{}{}",
+        String::from_utf8_lossy(&result.stdout),
+        String::from_utf8_lossy(&result.stderr)
+      ))
+    }
+  }
+}
@@ -1,20 +1,26 @@
-use std::path::Path;
-
 use ash::vk;
 
-use super::load_shader;
+use super::{load_shader, PipelineInterface, RenderResult, ShaderCode};
+
+#[cfg(test)]
+pub mod reference;
 
-const INSTANCE_SHADER_PATH: &'static str = "./assets/shaders/compute/instance.spv";
+pub(super) const INSTANCE_SHADER: &str = "compute/instance.comp";
 
 pub struct ComputeShaders {
   pub instance: vk::ShaderModule,
 }
 
 impl ComputeShaders {
-  pub fn load(device: &ash::Device) -> Self {
-    Self {
-      instance: load_shader(device, Path::new(INSTANCE_SHADER_PATH)),
-    }
+  // fails if the shader doesn't match its pipeline
+  pub fn check(code: &ShaderCode, instance: &PipelineInterface) -> RenderResult<()> {
+    code.check(INSTANCE_SHADER, instance)
+  }
+
+  pub fn load(device: &ash::Device, code: &ShaderCode) -> RenderResult<Self> {
+    Ok(Self {
+      instance: load_shader(device, code.get(INSTANCE_SHADER))?,
+    })
   }
 
   pub unsafe fn destroy_self(&mut self, device: &ash::Device) {
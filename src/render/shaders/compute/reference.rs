@@ -0,0 +1,214 @@
+// cpu reference implementation of instance.comp, used to check the shader's output in tests
+// invocations run one after another, so visible instances are compacted in input order, while on
+// the device their order within a draw depends on scheduling
+
+use cgmath::{InnerSpace, Matrix, Matrix4, Vector3};
+
+use crate::render::objects::{
+  instance_workgroup_count, IndirectDraw, InstancePushConstants, RenderInstance,
+  INSTANCE_WORKGROUP_SIZE,
+};
+
+// runs every invocation of the dispatch recorded for pc
+// input holds the instances of the dispatch, output and draws the whole buffers
+pub fn dispatch(
+  pc: &InstancePushConstants,
+  input: &[Matrix4<f32>],
+  draws: &mut [IndirectDraw],
+  output: &mut [RenderInstance],
+) {
+  let invocation_count = instance_workgroup_count(pc.inst_count) * INSTANCE_WORKGROUP_SIZE;
+  for i in 0..invocation_count {
+    invocation(pc, i, input, draws, output);
+  }
+}
+
+fn invocation(
+  pc: &InstancePushConstants,
+  i: u32,
+  input: &[Matrix4<f32>],
+  draws: &mut [IndirectDraw],
+  output: &mut [RenderInstance],
+) {
+  if i >= pc.inst_count {
+    return;
+  }
+  let Some(draw_i) = find_draw(pc, draws, pc.inst_offset + i) else {
+    return;
+  };
+
+  let model = input[i as usize];
+  let bounds = draws[draw_i].bounds;
+  let center = (model * Vector3::new(bounds[0], bounds[1], bounds[2]).extend(1.0)).truncate();
+  let scale = model.x.truncate().magnitude().max(
+    model
+      .y
+      .truncate()
+      .magnitude()
+      .max(model.z.truncate().magnitude()),
+  );
+  if !is_visible(&pc.projection_view, center, bounds[3] * scale) {
+    return;
+  }
+
+  let draw = &mut draws[draw_i];
+  let slot = draw.command.instance_count;
+  draw.command.instance_count += 1;
+  let out = &mut output[(draw.command.first_instance + slot) as usize];
+  out.matrix = pc.projection_view * model;
+  out.texture_i = draw.texture_i;
+}
+
+fn find_draw(pc: &InstancePushConstants, draws: &[IndirectDraw], i: u32) -> Option<usize> {
+  let mut low = pc.draw_offset as usize;
+  let mut high = (pc.draw_offset + pc.draw_count) as usize;
+  while low < high {
+    let mid = (low + high) / 2;
+    if draws[draws[mid].search_i as usize].inst_end <= i {
+      low = mid + 1;
+    } else {
+      high = mid;
+    }
+  }
+  if low == (pc.draw_offset + pc.draw_count) as usize {
+    return None;
+  }
+  let draw_i = draws[low].search_i as usize;
+  if draws[draw_i].command.first_instance > i {
+    return None;
+  }
+  Some(draw_i)
+}
+
+fn is_visible(projection_view: &Matrix4<f32>, center: Vector3<f32>, radius: f32) -> bool {
+  let rows = projection_view.transpose();
+  let planes = [
+    rows.w + rows.x,
+    rows.w - rows.x,
+    rows.w + rows.y,
+    rows.w - rows.y,
+    rows.w + rows.z,
+    rows.w - rows.z,
+  ];
+  planes
+    .iter()
+    .all(|plane| plane.truncate().dot(center) + plane.w >= -radius * plane.truncate().magnitude())
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::{SquareMatrix, Zero};
+
+  use crate::render::{models::ModelProperties, objects::IndirectDraws};
+
+  use super::*;
+
+  fn model(texture_i: Option<usize>) -> ModelProperties {
+    ModelProperties {
+      vertex_count: 3,
+      vertex_offset: 0,
+      index_count: 3,
+      index_offset: 0,
+      texture_i,
+      bounds: [0.0, 0.0, 0.0, 0.1],
+    }
+  }
+
+  fn empty_output(len: usize) -> Vec<RenderInstance> {
+    vec![
+      RenderInstance {
+        matrix: Matrix4::zero(),
+        texture_i: u32::MAX,
+        _padding: [0; 3],
+      };
+      len
+    ]
+  }
+
+  // identity projection view, so the frustum is the [-1, 1] cube
+  fn push_constants(inst_count: u32, draw_count: u32) -> InstancePushConstants {
+    InstancePushConstants {
+      projection_view: Matrix4::identity(),
+      inst_count,
+      inst_offset: 0,
+      draw_offset: 0,
+      draw_count,
+    }
+  }
+
+  #[test]
+  fn workgroup_count_covers_all_instances() {
+    assert_eq!(instance_workgroup_count(0), 0);
+    assert_eq!(instance_workgroup_count(1), 1);
+    assert_eq!(instance_workgroup_count(64), 1);
+    assert_eq!(instance_workgroup_count(65), 2);
+    assert_eq!(instance_workgroup_count(200), 4);
+  }
+
+  #[test]
+  fn processes_instances_past_the_first_workgroup() {
+    let color = model(None);
+    let textured = model(Some(1));
+    let mut draws = IndirectDraws::new([(&textured, 0, 120), (&color, 120, 80)].into_iter()).draws;
+    let input: Vec<Matrix4<f32>> = (0..200)
+      .map(|i| Matrix4::from_translation(Vector3::new(i as f32 / 200.0 - 0.5, 0.0, 0.0)))
+      .collect();
+    let mut output = empty_output(200);
+
+    dispatch(&push_constants(200, 2), &input, &mut draws, &mut output);
+
+    // color draws come first
+    assert_eq!(draws[0].command.instance_count, 80);
+    assert_eq!(draws[1].command.instance_count, 120);
+    for (i, out) in output.iter().enumerate() {
+      assert_eq!(out.matrix, input[i]);
+      assert_eq!(out.texture_i, if i < 120 { 1 } else { 0 });
+    }
+  }
+
+  #[test]
+  fn compacts_visible_instances() {
+    let color = model(None);
+    let mut draws = IndirectDraws::new([(&color, 0, 4)].into_iter()).draws;
+    let input: Vec<Matrix4<f32>> = [5.0, 0.0, -5.0, 0.5]
+      .into_iter()
+      .map(|x| Matrix4::from_translation(Vector3::new(x, 0.0, 0.0)))
+      .collect();
+    let mut output = empty_output(4);
+
+    dispatch(&push_constants(4, 1), &input, &mut draws, &mut output);
+
+    assert_eq!(draws[0].command.instance_count, 2);
+    assert_eq!(output[0].matrix, input[1]);
+    assert_eq!(output[1].matrix, input[3]);
+    assert_eq!(output[2].texture_i, u32::MAX);
+  }
+
+  #[test]
+  fn spheres_touching_the_frustum_are_visible() {
+    let center = Vector3::new(1.05, 0.0, 0.0);
+    assert!(is_visible(&Matrix4::identity(), center, 0.1));
+    assert!(!is_visible(&Matrix4::identity(), center, 0.01));
+  }
+
+  #[test]
+  fn dispatch_offsets_select_dynamic_draws() {
+    let color = model(None);
+    let mut draws = IndirectDraws::new([(&color, 0, 3), (&color, 3, 2)].into_iter()).draws;
+    let input = vec![Matrix4::identity(); 2];
+    let mut output = empty_output(5);
+
+    let pc = InstancePushConstants {
+      inst_offset: 3,
+      draw_offset: 1,
+      draw_count: 1,
+      ..push_constants(2, 1)
+    };
+    dispatch(&pc, &input, &mut draws, &mut output);
+
+    assert_eq!(draws[0].command.instance_count, 0);
+    assert_eq!(draws[1].command.instance_count, 2);
+    assert_eq!(output[3].matrix, Matrix4::identity());
+    assert_eq!(output[0].texture_i, u32::MAX);
+  }
+}
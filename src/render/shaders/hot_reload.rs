@@ -0,0 +1,283 @@
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+  time::{Duration, Instant, SystemTime},
+};
+
+use log::{debug, error, info};
+
+use super::{compiler::Compiler, compute, plain, tex_plain};
+
+const SOURCE_DIR: &str = "src/render/shaders";
+const SOURCE_EXTENSIONS: [&str; 4] = ["vert", "frag", "comp", "glsl"];
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PipelineKind {
+  Graphics,
+  Compute,
+}
+
+struct ShaderSource {
+  source: &'static str,
+  // name of the embedded shader it replaces
+  name: &'static str,
+  pipelines: PipelineKind,
+}
+
+const SOURCES: [ShaderSource; 5] = [
+  ShaderSource {
+    source: "src/render/shaders/plain/shader.vert",
+    name: plain::VERT_SHADER,
+    pipelines: PipelineKind::Graphics,
+  },
+  ShaderSource {
+    source: "src/render/shaders/plain/shader.frag",
+    name: plain::FRAG_SHADER,
+    pipelines: PipelineKind::Graphics,
+  },
+  ShaderSource {
+    source: "src/render/shaders/tex_plain/shader.vert",
+    name: tex_plain::VERT_SHADER,
+    pipelines: PipelineKind::Graphics,
+  },
+  ShaderSource {
+    source: "src/render/shaders/tex_plain/shader.frag",
+    name: tex_plain::FRAG_SHADER,
+    pipelines: PipelineKind::Graphics,
+  },
+  ShaderSource {
+    source: "src/render/shaders/compute/instance.comp",
+    name: compute::INSTANCE_SHADER,
+    pipelines: PipelineKind::Compute,
+  },
+];
+
+// new SPIR-V by shader name for each group of pipelines whose shaders were recompiled and should
+// be rebuilt
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReloadedShaders {
+  pub graphics: Option<Vec<(&'static str, Vec<u8>)>>,
+  pub compute: Option<Vec<(&'static str, Vec<u8>)>>,
+}
+
+impl ReloadedShaders {
+  pub fn any(&self) -> bool {
+    self.graphics.is_some() || self.compute.is_some()
+  }
+}
+
+// dev mode helper that recompiles shaders whose sources were modified
+// sources are polled, and each group of pipelines is only reloaded if all of its changed shaders
+// compile, so that the last good pipelines stay in use otherwise
+pub struct ShaderWatcher {
+  compiler: Compiler,
+  modified: HashMap<PathBuf, SystemTime>,
+  last_poll: Instant,
+}
+
+impl ShaderWatcher {
+  // returns None if no shader compiler is installed
+  pub fn new() -> Option<Self> {
+    let Some(compiler) = Compiler::find() else {
+      error!(
+        "No GLSL compiler found for hot reloading, install glslc or glslangValidator or set {}",
+        super::compiler::COMPILER_ENV
+      );
+      return None;
+    };
+    info!("Watching {:?} for shader changes", SOURCE_DIR);
+    Some(Self {
+      compiler,
+      modified: scan_sources(Path::new(SOURCE_DIR)),
+      last_poll: Instant::now(),
+    })
+  }
+
+  pub fn poll(&mut self) -> ReloadedShaders {
+    let mut reloaded = ReloadedShaders::default();
+    if self.last_poll.elapsed() < POLL_INTERVAL {
+      return reloaded;
+    }
+    self.last_poll = Instant::now();
+
+    let modified = scan_sources(Path::new(SOURCE_DIR));
+    let changed = changed_files(&self.modified, &modified);
+    self.modified = modified;
+    if changed.is_empty() {
+      return reloaded;
+    }
+    debug!("Shader sources changed: {:?}", changed);
+
+    let affected = affected_sources(&changed);
+    for pipelines in [PipelineKind::Graphics, PipelineKind::Compute] {
+      if let Some(code) = recompile(&self.compiler, &affected, pipelines) {
+        match pipelines {
+          PipelineKind::Graphics => reloaded.graphics = Some(code),
+          PipelineKind::Compute => reloaded.compute = Some(code),
+        }
+      }
+    }
+    reloaded
+  }
+}
+
+// modification times of all shader sources in dir and its subdirectories
+fn scan_sources(dir: &Path) -> HashMap<PathBuf, SystemTime> {
+  let mut modified = HashMap::new();
+  let Ok(entries) = std::fs::read_dir(dir) else {
+    return modified;
+  };
+  for entry in entries.flatten() {
+    let path = entry.path();
+    if path.is_dir() {
+      modified.extend(scan_sources(&path));
+    } else if path.extension().is_some_and(|ext| {
+      SOURCE_EXTENSIONS
+        .iter()
+        .any(|source_ext| ext == *source_ext)
+    }) {
+      if let Ok(time) = entry.metadata().and_then(|metadata| metadata.modified()) {
+        modified.insert(path, time);
+      }
+    }
+  }
+  modified
+}
+
+// files that were created or modified between the two scans
+fn changed_files(
+  old: &HashMap<PathBuf, SystemTime>,
+  new: &HashMap<PathBuf, SystemTime>,
+) -> Vec<PathBuf> {
+  let mut changed: Vec<PathBuf> = new
+    .iter()
+    .filter(|(path, time)| old.get(*path) != Some(time))
+    .map(|(path, _)| path.clone())
+    .collect();
+  changed.sort();
+  changed
+}
+
+// a changed file that isn't a known shader (like an include) may affect any of them
+fn affected_sources(changed: &[PathBuf]) -> Vec<&'static ShaderSource> {
+  let known: Vec<&ShaderSource> = SOURCES
+    .iter()
+    .filter(|source| changed.iter().any(|path| path == Path::new(source.source)))
+    .collect();
+  if known.len() < changed.len() {
+    SOURCES.iter().collect()
+  } else {
+    known
+  }
+}
+
+// compiles the affected shaders of a pipeline group, returning their code only if all of them
+// succeed, in which case the group's pipelines should be rebuilt
+fn recompile(
+  compiler: &Compiler,
+  affected: &[&ShaderSource],
+  pipelines: PipelineKind,
+) -> Option<Vec<(&'static str, Vec<u8>)>> {
+  let sources: Vec<&ShaderSource> = affected
+    .iter()
+    .filter(|source| source.pipelines == pipelines)
+    .copied()
+    .collect();
+  if sources.is_empty() {
+    return None;
+  }
+
+  let mut compiled = Vec::with_capacity(sources.len());
+  for source in sources.iter() {
+    match compile(compiler, source) {
+      Ok(code) => compiled.push((source.name, code)),
+      Err(err) => {
+        error!(
+          "Failed to compile {:?}, keeping the last good {:?} pipelines:\n{}",
+          source.source, pipelines, err
+        );
+        return None;
+      }
+    }
+    info!("Recompiled {:?}", source.source);
+  }
+  Some(compiled)
+}
+
+fn compile(compiler: &Compiler, source: &ShaderSource) -> Result<Vec<u8>, String> {
+  let output = std::env::temp_dir().join(format!(
+    "ash_boilerplate_{}_{}.spv",
+    std::process::id(),
+    source.name.replace('/', "_")
+  ));
+  compiler.compile(Path::new(source.source), &output)?;
+  let code = std::fs::read(&output).map_err(|err| err.to_string());
+  let _ = std::fs::remove_file(&output);
+  code
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::render::shaders::EMBEDDED;
+
+  fn scan(files: &[(&str, u64)]) -> HashMap<PathBuf, SystemTime> {
+    files
+      .iter()
+      .map(|(path, secs)| {
+        (
+          PathBuf::from(path),
+          SystemTime::UNIX_EPOCH + Duration::from_secs(*secs),
+        )
+      })
+      .collect()
+  }
+
+  #[test]
+  fn all_sources_are_watched() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let modified = scan_sources(&root.join(SOURCE_DIR));
+    for source in SOURCES.iter() {
+      assert!(
+        modified.contains_key(&root.join(source.source)),
+        "{:?} is not watched",
+        source.source
+      );
+      assert!(
+        EMBEDDED.iter().any(|(name, _)| *name == source.name),
+        "{:?} is not embedded",
+        source.name
+      );
+    }
+  }
+
+  #[test]
+  fn detects_modified_and_new_files() {
+    let old = scan(&[("a.vert", 1), ("b.frag", 1)]);
+    let new = scan(&[("a.vert", 1), ("b.frag", 2), ("c.glsl", 1)]);
+    assert_eq!(
+      changed_files(&old, &new),
+      [PathBuf::from("b.frag"), PathBuf::from("c.glsl")]
+    );
+    assert!(changed_files(&new, &new).is_empty());
+  }
+
+  #[test]
+  fn changed_shaders_only_affect_themselves() {
+    let changed = [PathBuf::from(SOURCES[4].source)];
+    let affected = affected_sources(&changed);
+    assert_eq!(affected.len(), 1);
+    assert_eq!(affected[0].pipelines, PipelineKind::Compute);
+  }
+
+  #[test]
+  fn unknown_files_affect_all_shaders() {
+    let changed = [
+      PathBuf::from(SOURCES[0].source),
+      PathBuf::from("src/render/shaders/common.glsl"),
+    ];
+    assert_eq!(affected_sources(&changed).len(), SOURCES.len());
+  }
+}
@@ -1,10 +1,73 @@
+mod compiler;
 mod compute;
+mod hot_reload;
 pub mod plain;
+mod reflect;
 pub mod tex_plain;
+#[cfg(test)]
+pub use compute::reference as compute_reference;
 pub use compute::ComputeShaders;
+pub use hot_reload::{ReloadedShaders, ShaderWatcher};
+pub use reflect::PipelineInterface;
+
+use crate::render::error::{RenderError, RenderResult, VkResultExt};
 
 use ash::vk;
-use std::{ffi::CString, path::Path, ptr};
+use std::{collections::HashMap, ffi::CString, io::Cursor, ptr};
+
+// SPIR-V compiled by build.rs, shaders are named by their source path relative to this directory
+macro_rules! embedded_spirv {
+  ($($name:literal),* $(,)?) => {
+    [$(($name, include_bytes!(concat!(env!("OUT_DIR"), "/shaders/", $name, ".spv")) as &[u8])),*]
+  };
+}
+
+const EMBEDDED: [(&str, &[u8]); 5] = embedded_spirv!(
+  "plain/shader.vert",
+  "plain/shader.frag",
+  "tex_plain/shader.vert",
+  "tex_plain/shader.frag",
+  "compute/instance.comp",
+);
+
+// SPIR-V of all shaders, hot reloading replaces the embedded code
+#[derive(Clone, Default)]
+pub struct ShaderCode {
+  reloaded: HashMap<&'static str, Vec<u8>>,
+}
+
+impl ShaderCode {
+  pub fn get(&self, name: &str) -> &[u8] {
+    if let Some(code) = self.reloaded.get(name) {
+      return code;
+    }
+    EMBEDDED
+      .iter()
+      .find(|(embedded_name, _)| *embedded_name == name)
+      .map(|(_, code)| *code)
+      .unwrap_or_else(|| panic!("No shader named {:?}", name))
+  }
+
+  pub fn replace(&mut self, name: &'static str, code: Vec<u8>) {
+    self.reloaded.insert(name, code);
+  }
+
+  // fails if the pipeline doesn't provide the resources the shader uses
+  pub fn check(&self, name: &str, pipeline: &PipelineInterface) -> RenderResult<()> {
+    let interface = reflect::ShaderInterface::reflect(self.get(name)).map_err(|err| {
+      RenderError::Shader(format!("Failed to reflect shader {:?}: {}", name, err))
+    })?;
+    let mismatches = interface.mismatches(pipeline);
+    if !mismatches.is_empty() {
+      return Err(RenderError::Shader(format!(
+        "Shader {:?} doesn't match its pipeline:\n  {}",
+        name,
+        mismatches.join("\n  ")
+      )));
+    }
+    Ok(())
+  }
+}
 
 pub trait GraphicsShader {
   fn get_vert(&self) -> vk::ShaderModule;
@@ -41,33 +104,68 @@ pub trait GraphicsShader {
   }
 }
 
-pub fn load_shader(device: &ash::Device, shader_path: &Path) -> vk::ShaderModule {
-  let code = read_shader_code(shader_path);
-  create_shader_module(device, code)
+pub fn load_shader(device: &ash::Device, code: &[u8]) -> RenderResult<vk::ShaderModule> {
+  // embedded bytes are not aligned to words
+  let code = ash::util::read_spv(&mut Cursor::new(code))
+    .map_err(|err| RenderError::Shader(format!("Failed to read SPIR-V: {}", err)))?;
+  create_shader_module(device, &code)
 }
 
-fn read_shader_code(shader_path: &Path) -> Vec<u8> {
-  use std::{fs::File, io::Read};
-
-  let spv_file =
-    File::open(shader_path).expect(&format!("Failed to find spv file at {:?}", shader_path));
-  let bytes_code: Vec<u8> = spv_file.bytes().filter_map(|byte| byte.ok()).collect();
-
-  bytes_code
-}
-
-fn create_shader_module(device: &ash::Device, code: Vec<u8>) -> vk::ShaderModule {
+fn create_shader_module(device: &ash::Device, code: &[u32]) -> RenderResult<vk::ShaderModule> {
   let shader_module_create_info = vk::ShaderModuleCreateInfo {
     s_type: vk::StructureType::SHADER_MODULE_CREATE_INFO,
     p_next: ptr::null(),
     flags: vk::ShaderModuleCreateFlags::empty(),
-    code_size: code.len(),
-    p_code: code.as_ptr() as *const u32,
+    code_size: std::mem::size_of_val(code),
+    p_code: code.as_ptr(),
   };
 
   unsafe {
     device
       .create_shader_module(&shader_module_create_info, None)
-      .expect("Failed to create shader module")
+      .context("Failed to create shader module")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn embedded_shaders_are_spirv() {
+    for (name, code) in EMBEDDED {
+      let words = ash::util::read_spv(&mut Cursor::new(code))
+        .unwrap_or_else(|err| panic!("{:?} is not SPIR-V: {}", name, err));
+      assert_eq!(
+        words[0], 0x07230203,
+        "{:?} has the wrong magic number",
+        name
+      );
+    }
+  }
+
+  #[test]
+  fn reloaded_code_replaces_embedded() {
+    let mut code = ShaderCode::default();
+    assert_eq!(code.get(plain::VERT_SHADER), EMBEDDED[0].1);
+    code.replace(plain::VERT_SHADER, vec![1, 2, 3, 4]);
+    assert_eq!(code.get(plain::VERT_SHADER), [1, 2, 3, 4]);
+    assert_eq!(code.get(plain::FRAG_SHADER), EMBEDDED[1].1);
+  }
+
+  #[test]
+  fn mismatched_pipeline_is_an_error() {
+    let code = ShaderCode::default();
+    let no_resources = PipelineInterface {
+      set_layouts: &[],
+      push_constant_ranges: &[],
+      vertex_attributes: &[],
+    };
+    // the instance shader uses a descriptor set and push constants
+    let err = code
+      .check(compute::INSTANCE_SHADER, &no_resources)
+      .unwrap_err();
+    assert!(matches!(err, RenderError::Shader(_)));
+    assert!(err.to_string().contains(compute::INSTANCE_SHADER));
   }
 }
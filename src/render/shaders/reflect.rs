@@ -0,0 +1,593 @@
+// reads the resources a SPIR-V module uses, so that the hand written descriptor set layouts,
+// push constant ranges and vertex attributes can be checked against the shaders
+
+use std::collections::HashMap;
+
+use ash::vk;
+use rspirv::{
+  dr::{Instruction, Module, Operand},
+  spirv::{Decoration, Dim, ExecutionModel, Op, StorageClass},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorBinding {
+  pub set: u32,
+  pub binding: u32,
+  pub descriptor_type: vk::DescriptorType,
+  // None if the array length is a specialization constant
+  pub count: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VertexInput {
+  pub location: u32,
+  pub format: vk::Format,
+}
+
+// resources used by the entry point of a shader module
+#[derive(Debug)]
+pub struct ShaderInterface {
+  pub stage: vk::ShaderStageFlags,
+  pub bindings: Vec<DescriptorBinding>,
+  // size of the push constant block, which always starts at offset 0 when written in GLSL
+  pub push_constant_size: Option<u32>,
+  // only filled for vertex shaders, matrices take a location per column
+  pub vertex_inputs: Vec<VertexInput>,
+}
+
+// what a pipeline provides to one of its shader stages
+pub struct PipelineInterface<'a> {
+  // bindings of each descriptor set layout, indexed by set
+  pub set_layouts: &'a [&'a [vk::DescriptorSetLayoutBinding]],
+  pub push_constant_ranges: &'a [vk::PushConstantRange],
+  pub vertex_attributes: &'a [vk::VertexInputAttributeDescription],
+}
+
+impl ShaderInterface {
+  pub fn reflect(code: &[u8]) -> Result<Self, String> {
+    let module = rspirv::dr::load_bytes(code).map_err(|err| format!("Invalid SPIR-V: {}", err))?;
+    let reflection = Reflection::new(&module);
+
+    let model = module
+      .entry_points
+      .first()
+      .ok_or("No entry point")?
+      .operands[0]
+      .unwrap_execution_model();
+    let stage = match model {
+      ExecutionModel::Vertex => vk::ShaderStageFlags::VERTEX,
+      ExecutionModel::Fragment => vk::ShaderStageFlags::FRAGMENT,
+      ExecutionModel::GLCompute => vk::ShaderStageFlags::COMPUTE,
+      other => return Err(format!("Unsupported execution model {:?}", other)),
+    };
+
+    let mut interface = Self {
+      stage,
+      bindings: Vec::new(),
+      push_constant_size: None,
+      vertex_inputs: Vec::new(),
+    };
+    for variable in module
+      .types_global_values
+      .iter()
+      .filter(|inst| inst.class.opcode == Op::Variable)
+    {
+      let id = variable.result_id.unwrap();
+      let storage_class = variable.operands[0].unwrap_storage_class();
+      // pointer type to the variable's type
+      let ty = reflection.operand_id(variable.result_type.unwrap(), 1)?;
+      match storage_class {
+        StorageClass::Uniform | StorageClass::UniformConstant | StorageClass::StorageBuffer => {
+          let (Some(set), Some(binding)) = (
+            reflection.decoration(id, Decoration::DescriptorSet),
+            reflection.decoration(id, Decoration::Binding),
+          ) else {
+            return Err(format!("Resource %{} has no set or binding", id));
+          };
+          let (descriptor_type, count) = reflection.descriptor_type(ty, storage_class)?;
+          interface.bindings.push(DescriptorBinding {
+            set,
+            binding,
+            descriptor_type,
+            count,
+          });
+        }
+        StorageClass::PushConstant => {
+          interface.push_constant_size = Some(reflection.size(ty)?);
+        }
+        StorageClass::Input if model == ExecutionModel::Vertex => {
+          if reflection
+            .decorations
+            .contains_key(&(id, Decoration::BuiltIn))
+          {
+            continue;
+          }
+          let location = reflection
+            .decoration(id, Decoration::Location)
+            .ok_or_else(|| format!("Input %{} has no location", id))?;
+          for (i, format) in reflection.formats(ty)?.into_iter().enumerate() {
+            interface.vertex_inputs.push(VertexInput {
+              location: location + i as u32,
+              format,
+            });
+          }
+        }
+        _ => {}
+      }
+    }
+    interface.bindings.sort_by_key(|b| (b.set, b.binding));
+    interface.vertex_inputs.sort_by_key(|input| input.location);
+    Ok(interface)
+  }
+
+  // all the ways the pipeline doesn't provide what the shader uses
+  pub fn mismatches(&self, pipeline: &PipelineInterface) -> Vec<String> {
+    let mut mismatches = Vec::new();
+
+    for binding in self.bindings.iter() {
+      let layout_binding = pipeline
+        .set_layouts
+        .get(binding.set as usize)
+        .and_then(|set| set.iter().find(|b| b.binding == binding.binding));
+      let Some(layout_binding) = layout_binding else {
+        mismatches.push(format!(
+          "set {} binding {} ({:?}) is missing from the descriptor set layouts",
+          binding.set, binding.binding, binding.descriptor_type
+        ));
+        continue;
+      };
+      if !descriptor_types_match(binding.descriptor_type, layout_binding.descriptor_type) {
+        mismatches.push(format!(
+          "set {} binding {} is {:?} in the shader but {:?} in the layout",
+          binding.set, binding.binding, binding.descriptor_type, layout_binding.descriptor_type
+        ));
+      }
+      if binding
+        .count
+        .is_some_and(|count| layout_binding.descriptor_count < count)
+      {
+        mismatches.push(format!(
+          "set {} binding {} has {} descriptors in the shader but {} in the layout",
+          binding.set,
+          binding.binding,
+          binding.count.unwrap(),
+          layout_binding.descriptor_count
+        ));
+      }
+      if !layout_binding.stage_flags.contains(self.stage) {
+        mismatches.push(format!(
+          "set {} binding {} is not visible to the {:?} stage",
+          binding.set, binding.binding, self.stage
+        ));
+      }
+    }
+
+    if let Some(size) = self.push_constant_size {
+      let covered = pipeline
+        .push_constant_ranges
+        .iter()
+        .filter(|range| range.stage_flags.contains(self.stage) && range.offset == 0)
+        .map(|range| range.size)
+        .max();
+      match covered {
+        None => mismatches.push(format!(
+          "the shader uses {} bytes of push constants but no range is visible to the {:?} stage",
+          size, self.stage
+        )),
+        Some(covered) if covered < size => mismatches.push(format!(
+          "the shader uses {} bytes of push constants but the range only has {}",
+          size, covered
+        )),
+        Some(_) => {}
+      }
+    }
+
+    for input in self.vertex_inputs.iter() {
+      let attribute = pipeline
+        .vertex_attributes
+        .iter()
+        .find(|attribute| attribute.location == input.location);
+      match attribute {
+        None => mismatches.push(format!(
+          "vertex input location {} ({:?}) has no attribute",
+          input.location, input.format
+        )),
+        Some(attribute) if attribute.format != input.format => mismatches.push(format!(
+          "vertex input location {} is {:?} in the shader but {:?} in the attribute",
+          input.location, input.format, attribute.format
+        )),
+        Some(_) => {}
+      }
+    }
+
+    mismatches
+  }
+}
+
+// dynamic buffers are declared the same way as regular ones in shaders
+fn descriptor_types_match(shader: vk::DescriptorType, layout: vk::DescriptorType) -> bool {
+  shader == layout
+    || matches!(
+      (shader, layout),
+      (
+        vk::DescriptorType::UNIFORM_BUFFER,
+        vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC
+      ) | (
+        vk::DescriptorType::STORAGE_BUFFER,
+        vk::DescriptorType::STORAGE_BUFFER_DYNAMIC
+      )
+    )
+}
+
+// lookups into the types and annotations of a module
+struct Reflection<'a> {
+  definitions: HashMap<u32, &'a Instruction>,
+  // first literal of each decoration, 0 for decorations without one
+  decorations: HashMap<(u32, Decoration), u32>,
+  member_decorations: HashMap<(u32, u32, Decoration), u32>,
+}
+
+impl<'a> Reflection<'a> {
+  fn new(module: &'a Module) -> Self {
+    let definitions = module
+      .types_global_values
+      .iter()
+      .filter_map(|inst| inst.result_id.map(|id| (id, inst)))
+      .collect();
+
+    let literal = |operand: Option<&Operand>| match operand {
+      Some(Operand::LiteralInt32(value)) => *value,
+      _ => 0,
+    };
+    let mut decorations = HashMap::new();
+    let mut member_decorations = HashMap::new();
+    for annotation in module.annotations.iter() {
+      let operands = &annotation.operands;
+      match annotation.class.opcode {
+        Op::Decorate => {
+          let value = match operands.get(2) {
+            // builtins aren't literals
+            Some(Operand::BuiltIn(builtin)) => *builtin as u32,
+            operand => literal(operand),
+          };
+          decorations.insert(
+            (operands[0].unwrap_id_ref(), operands[1].unwrap_decoration()),
+            value,
+          );
+        }
+        Op::MemberDecorate => {
+          member_decorations.insert(
+            (
+              operands[0].unwrap_id_ref(),
+              operands[1].unwrap_literal_int32(),
+              operands[2].unwrap_decoration(),
+            ),
+            literal(operands.get(3)),
+          );
+        }
+        _ => {}
+      }
+    }
+
+    Self {
+      definitions,
+      decorations,
+      member_decorations,
+    }
+  }
+
+  fn definition(&self, id: u32) -> Result<&'a Instruction, String> {
+    self
+      .definitions
+      .get(&id)
+      .copied()
+      .ok_or_else(|| format!("Missing definition of %{}", id))
+  }
+
+  fn operand_id(&self, id: u32, operand: usize) -> Result<u32, String> {
+    match self.definition(id)?.operands.get(operand) {
+      Some(Operand::IdRef(id)) => Ok(*id),
+      _ => Err(format!("Operand {} of %{} is not an id", operand, id)),
+    }
+  }
+
+  fn operand_literal(&self, id: u32, operand: usize) -> Result<u32, String> {
+    match self.definition(id)?.operands.get(operand) {
+      Some(Operand::LiteralInt32(value)) => Ok(*value),
+      _ => Err(format!("Operand {} of %{} is not a literal", operand, id)),
+    }
+  }
+
+  fn decoration(&self, id: u32, decoration: Decoration) -> Option<u32> {
+    self.decorations.get(&(id, decoration)).copied()
+  }
+
+  // None if the length is a specialization constant
+  fn array_length(&self, ty: u32) -> Result<Option<u32>, String> {
+    let length = self.definition(self.operand_id(ty, 1)?)?;
+    match (length.class.opcode, length.operands.first()) {
+      (Op::Constant, Some(Operand::LiteralInt32(length))) => Ok(Some(*length)),
+      (Op::SpecConstant, _) => Ok(None),
+      _ => Err(format!("Unsupported length of array %{}", ty)),
+    }
+  }
+
+  fn descriptor_type(
+    &self,
+    ty: u32,
+    storage_class: StorageClass,
+  ) -> Result<(vk::DescriptorType, Option<u32>), String> {
+    let inst = self.definition(ty)?;
+    let descriptor_type = match inst.class.opcode {
+      Op::TypeArray => {
+        let (descriptor_type, count) =
+          self.descriptor_type(self.operand_id(ty, 0)?, storage_class)?;
+        let length = self.array_length(ty)?;
+        return Ok((descriptor_type, count.zip(length).map(|(a, b)| a * b)));
+      }
+      Op::TypeSampler => vk::DescriptorType::SAMPLER,
+      Op::TypeSampledImage => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+      Op::TypeImage => {
+        // Sampled is 2 for images used without a sampler
+        let storage = self.operand_literal(ty, 5)? == 2;
+        match (inst.operands[1].unwrap_dim(), storage) {
+          (Dim::DimBuffer, false) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+          (Dim::DimBuffer, true) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+          (Dim::DimSubpassData, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+          (_, false) => vk::DescriptorType::SAMPLED_IMAGE,
+          (_, true) => vk::DescriptorType::STORAGE_IMAGE,
+        }
+      }
+      // before SPIR-V 1.3 storage buffers are uniform blocks decorated with BufferBlock
+      Op::TypeStruct
+        if storage_class == StorageClass::StorageBuffer
+          || self
+            .decorations
+            .contains_key(&(ty, Decoration::BufferBlock)) =>
+      {
+        vk::DescriptorType::STORAGE_BUFFER
+      }
+      Op::TypeStruct => vk::DescriptorType::UNIFORM_BUFFER,
+      other => return Err(format!("Unsupported resource type {:?}", other)),
+    };
+    Ok((descriptor_type, Some(1)))
+  }
+
+  // size in bytes of an explicitly laid out type, like a push constant block
+  fn size(&self, ty: u32) -> Result<u32, String> {
+    let inst = self.definition(ty)?;
+    match inst.class.opcode {
+      Op::TypeInt | Op::TypeFloat => Ok(self.operand_literal(ty, 0)? / 8),
+      Op::TypeVector | Op::TypeMatrix => {
+        Ok(self.size(self.operand_id(ty, 0)?)? * self.operand_literal(ty, 1)?)
+      }
+      Op::TypeArray => {
+        let length = self
+          .array_length(ty)?
+          .ok_or_else(|| format!("Array %{} has no fixed size", ty))?;
+        let stride = match self.decoration(ty, Decoration::ArrayStride) {
+          Some(stride) => stride,
+          None => self.size(self.operand_id(ty, 0)?)?,
+        };
+        Ok(stride * length)
+      }
+      Op::TypeStruct => {
+        let mut size = 0;
+        for (i, operand) in inst.operands.iter().enumerate() {
+          let member = i as u32;
+          let offset = self
+            .member_decorations
+            .get(&(ty, member, Decoration::Offset))
+            .copied()
+            .ok_or_else(|| format!("Member {} of %{} has no offset", member, ty))?;
+          let member_ty = operand.unwrap_id_ref();
+          // matrix columns can be padded
+          let member_size = match (
+            self.definition(member_ty)?.class.opcode,
+            self
+              .member_decorations
+              .get(&(ty, member, Decoration::MatrixStride)),
+          ) {
+            (Op::TypeMatrix, Some(stride)) => stride * self.operand_literal(member_ty, 1)?,
+            _ => self.size(member_ty)?,
+          };
+          size = size.max(offset + member_size);
+        }
+        Ok(size)
+      }
+      other => Err(format!("Unsupported type {:?} in a block", other)),
+    }
+  }
+
+  // vertex attribute formats of an input, one per location
+  fn formats(&self, ty: u32) -> Result<Vec<vk::Format>, String> {
+    let inst = self.definition(ty)?;
+    match inst.class.opcode {
+      Op::TypeMatrix => {
+        let column = self.format(self.operand_id(ty, 0)?)?;
+        Ok(vec![column; self.operand_literal(ty, 1)? as usize])
+      }
+      Op::TypeArray => {
+        let length = self
+          .array_length(ty)?
+          .ok_or_else(|| format!("Array %{} has no fixed size", ty))?;
+        let element = self.formats(self.operand_id(ty, 0)?)?;
+        Ok(element.repeat(length as usize))
+      }
+      _ => Ok(vec![self.format(ty)?]),
+    }
+  }
+
+  fn format(&self, ty: u32) -> Result<vk::Format, String> {
+    let inst = self.definition(ty)?;
+    let (component, count) = match inst.class.opcode {
+      Op::TypeVector => (self.operand_id(ty, 0)?, self.operand_literal(ty, 1)?),
+      _ => (ty, 1),
+    };
+    let component_inst = self.definition(component)?;
+    let width = self.operand_literal(component, 0)?;
+    let formats = match (component_inst.class.opcode, width) {
+      (Op::TypeFloat, 32) => [
+        vk::Format::R32_SFLOAT,
+        vk::Format::R32G32_SFLOAT,
+        vk::Format::R32G32B32_SFLOAT,
+        vk::Format::R32G32B32A32_SFLOAT,
+      ],
+      (Op::TypeInt, 32) if self.operand_literal(component, 1)? == 0 => [
+        vk::Format::R32_UINT,
+        vk::Format::R32G32_UINT,
+        vk::Format::R32G32B32_UINT,
+        vk::Format::R32G32B32A32_UINT,
+      ],
+      (Op::TypeInt, 32) => [
+        vk::Format::R32_SINT,
+        vk::Format::R32G32_SINT,
+        vk::Format::R32G32B32_SINT,
+        vk::Format::R32G32B32A32_SINT,
+      ],
+      (other, width) => {
+        return Err(format!(
+          "Unsupported vertex input component {:?} of width {}",
+          other, width
+        ))
+      }
+    };
+    formats
+      .get(count as usize - 1)
+      .copied()
+      .ok_or_else(|| format!("Unsupported vector size {}", count))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::render::{
+    objects::{ColorVertex, RenderInstance, TexVertex, Vertex},
+    shaders::EMBEDDED,
+  };
+
+  fn interface(name: &str) -> ShaderInterface {
+    let code = EMBEDDED
+      .iter()
+      .find(|(embedded_name, _)| *embedded_name == name)
+      .unwrap()
+      .1;
+    ShaderInterface::reflect(code).unwrap()
+  }
+
+  fn attribute(location: u32, format: vk::Format) -> vk::VertexInputAttributeDescription {
+    vk::VertexInputAttributeDescription {
+      location,
+      binding: 0,
+      format,
+      offset: 0,
+    }
+  }
+
+  #[test]
+  fn reflects_vertex_inputs() {
+    let interface = interface("tex_plain/shader.vert");
+    assert_eq!(interface.stage, vk::ShaderStageFlags::VERTEX);
+    let formats: Vec<(u32, vk::Format)> = interface
+      .vertex_inputs
+      .iter()
+      .map(|input| (input.location, input.format))
+      .collect();
+    assert_eq!(
+      formats,
+      [
+        (0, vk::Format::R32G32B32_SFLOAT),
+        (1, vk::Format::R32G32B32_SFLOAT),
+        (2, vk::Format::R32G32_SFLOAT),
+        (3, vk::Format::R32G32B32A32_SFLOAT),
+        (4, vk::Format::R32G32B32A32_SFLOAT),
+        (5, vk::Format::R32G32B32A32_SFLOAT),
+        (6, vk::Format::R32G32B32A32_SFLOAT),
+        (7, vk::Format::R32_UINT),
+      ]
+    );
+  }
+
+  #[test]
+  fn reflects_descriptors_and_push_constants() {
+    let compute = interface("compute/instance.comp");
+    assert_eq!(compute.push_constant_size, Some(80));
+    assert_eq!(compute.bindings.len(), 3);
+    assert!(compute
+      .bindings
+      .iter()
+      .all(|b| b.set == 0 && b.descriptor_type == vk::DescriptorType::STORAGE_BUFFER));
+
+    // the texture array is sized by a specialization constant
+    let frag = interface("tex_plain/shader.frag");
+    assert_eq!(
+      frag.bindings,
+      [DescriptorBinding {
+        set: 0,
+        binding: 0,
+        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        count: None,
+      }]
+    );
+    assert_eq!(frag.push_constant_size, None);
+  }
+
+  #[test]
+  fn vertex_types_match_shaders() {
+    let pipelines = [
+      (
+        "plain/shader.vert",
+        ColorVertex::get_attribute_descriptions(0, 0),
+      ),
+      (
+        "tex_plain/shader.vert",
+        TexVertex::get_attribute_descriptions(0, 0),
+      ),
+    ];
+    for (name, mut attributes) in pipelines {
+      let instance_location = attributes.len() as u32;
+      attributes.extend(RenderInstance::get_attribute_descriptions(
+        instance_location,
+        1,
+      ));
+      let mismatches = interface(name).mismatches(&PipelineInterface {
+        set_layouts: &[],
+        push_constant_ranges: &[],
+        vertex_attributes: &attributes,
+      });
+      assert!(mismatches.is_empty(), "{}: {:?}", name, mismatches);
+    }
+  }
+
+  #[test]
+  fn reports_mismatches() {
+    let vert = interface("plain/shader.vert");
+    let attributes = [
+      attribute(0, vk::Format::R32G32B32_SFLOAT),
+      attribute(1, vk::Format::R32G32_SFLOAT),
+    ];
+    let mismatches = vert.mismatches(&PipelineInterface {
+      set_layouts: &[],
+      push_constant_ranges: &[],
+      vertex_attributes: &attributes,
+    });
+    // wrong color format and four missing matrix columns
+    assert_eq!(mismatches.len(), 5, "{:?}", mismatches);
+    assert!(mismatches[0].contains("location 1"));
+
+    let compute = interface("compute/instance.comp");
+    let small_range = [vk::PushConstantRange {
+      stage_flags: vk::ShaderStageFlags::COMPUTE,
+      offset: 0,
+      size: 64,
+    }];
+    let mismatches = compute.mismatches(&PipelineInterface {
+      set_layouts: &[],
+      push_constant_ranges: &small_range,
+      vertex_attributes: &[],
+    });
+    // three missing bindings and a too small push constant range
+    assert_eq!(mismatches.len(), 4, "{:?}", mismatches);
+  }
+}
@@ -1,11 +1,9 @@
-use std::path::Path;
-
 use ash::vk;
 
-use super::{load_shader, GraphicsShader};
+use super::{load_shader, GraphicsShader, PipelineInterface, RenderResult, ShaderCode};
 
-const VERT_SHADER_PATH: &'static str = "./assets/shaders/tex_plain/vert.spv";
-const FRAG_SHADER_PATH: &'static str = "./assets/shaders/tex_plain/frag.spv";
+pub(super) const VERT_SHADER: &str = "tex_plain/shader.vert";
+pub(super) const FRAG_SHADER: &str = "tex_plain/shader.frag";
 
 pub struct Shader {
   vert: vk::ShaderModule,
@@ -13,11 +11,22 @@ pub struct Shader {
 }
 
 impl Shader {
-  pub fn load(device: &ash::Device) -> Self {
-    Self {
-      vert: load_shader(device, Path::new(VERT_SHADER_PATH)),
-      frag: load_shader(device, Path::new(FRAG_SHADER_PATH)),
-    }
+  // fails if the shaders don't match their pipeline
+  pub fn check(
+    code: &ShaderCode,
+    vert: &PipelineInterface,
+    frag: &PipelineInterface,
+  ) -> RenderResult<()> {
+    code.check(VERT_SHADER, vert)?;
+    code.check(FRAG_SHADER, frag)
+  }
+
+  pub fn load(device: &ash::Device, code: &ShaderCode) -> RenderResult<Self> {
+    let vert = load_shader(device, code.get(VERT_SHADER))?;
+    let frag = load_shader(device, code.get(FRAG_SHADER)).inspect_err(|_| {
+      unsafe { device.destroy_shader_module(vert, None) };
+    })?;
+    Ok(Self { vert, frag })
   }
 
   pub unsafe fn destroy_self(&mut self, device: &ash::Device) {
@@ -1,8 +1,14 @@
 #version 450
 
-layout(location = 0) in vec3 in_color;
+// set to the amount of loaded textures when creating the pipeline
+layout(constant_id = 0) const uint TEXTURE_COUNT = 1;
+layout(set = 0, binding = 0) uniform sampler2D textures[TEXTURE_COUNT];
+
+layout(location = 0) in vec2 frag_tex;
+layout(location = 1) flat in uint frag_texture_i;
 layout(location = 0) out vec4 out_color;
 
 void main() {
-  out_color = vec4(in_color, 1.0);
+  // constant within each draw
+  out_color = texture(textures[frag_texture_i], frag_tex);
 }
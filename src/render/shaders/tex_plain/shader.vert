@@ -7,10 +7,13 @@ layout(location = 2) in vec2 tex;
 
 // instance
 layout(location = 3) in mat4 matrix;
+layout(location = 7) in uint texture_i;
 
-layout(location = 0) out vec3 frag_color;
+layout(location = 0) out vec2 frag_tex;
+layout(location = 1) flat out uint frag_texture_i;
 
 void main() {
   gl_Position = matrix * vec4(pos, 1.0);
-  frag_color = normal;
+  frag_tex = tex;
+  frag_texture_i = texture_i;
 }
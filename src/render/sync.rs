@@ -1,24 +1,51 @@
 use std::{
+  collections::BTreeMap,
+  ops::RangeInclusive,
+  path::PathBuf,
   ptr,
   time::{Duration, Instant},
 };
 
 use ash::vk;
-use log::{info, warn};
-use winit::{dpi::PhysicalPosition, event_loop::EventLoop, window::CursorGrabMode};
+use log::{error, info, warn};
+use winit::{
+  dpi::PhysicalPosition,
+  event_loop::EventLoop,
+  window::{CursorGrabMode, Window},
+};
 
-use crate::{objects::Niko, FPS_PRINT_INTERVAL, GPU_PRINT_INTERVAL, PRINT_FPS, PRINT_GPU_WAIT};
+use crate::{
+  static_scene::StaticScene, FPS_PRINT_INTERVAL, GPU_PRINT_INTERVAL, INITIAL_WINDOW_HEIGHT,
+  INITIAL_WINDOW_WIDTH, PRINT_FPS, PRINT_GPU_WAIT, WINDOW_TITLE,
+};
 
 use super::{
   camera::{Camera, RenderCamera},
   cursor::Cursor,
-  objects::InstProperties,
+  error::{RenderError, RenderResult, VkResultExt},
+  objects::{AllocatorStats, GpuOverride, InstProperties},
   renderer::Renderer,
-  MatrixInstance, RenderableIn3d,
+  shaders::ShaderWatcher,
+  LoadState, MatrixInstance, ModelHandle, Models, RenderableIn3d,
 };
 
-// only 2 will work
-pub const FRAMES_IN_FLIGHT: usize = 2;
+// frames that can be recorded while previous ones are still being rendered
+// more frames in flight can increase throughput at the cost of input latency
+pub const FRAMES_IN_FLIGHT_RANGE: RangeInclusive<usize> = 1..=4;
+pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
+
+pub fn parse_frames_in_flight(arg: &str) -> Result<usize, String> {
+  let count = arg
+    .parse()
+    .map_err(|_| format!("Frames in flight should be an integer, got {:?}", arg))?;
+  if !FRAMES_IN_FLIGHT_RANGE.contains(&count) {
+    return Err(format!(
+      "Frames in flight should be in {:?}, got {}",
+      FRAMES_IN_FLIGHT_RANGE, count
+    ));
+  }
+  Ok(count)
+}
 
 pub const INITIAL_CAMERA_FOV: f32 = 0.8;
 pub const CAMERA_SENTIVITY: f32 = 0.0003;
@@ -31,7 +58,7 @@ struct Frame {
 }
 
 impl Frame {
-  pub fn new(device: &ash::Device) -> Self {
+  pub fn new(device: &ash::Device) -> RenderResult<Self> {
     let semaphore_create_info = vk::SemaphoreCreateInfo {
       s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
       p_next: ptr::null(),
@@ -41,17 +68,17 @@ impl Frame {
     let image_available = unsafe {
       device
         .create_semaphore(&semaphore_create_info, None)
-        .expect("Failed to create Semaphore Object!")
+        .context("Failed to create semaphore")?
     };
     let render_finished = unsafe {
       device
         .create_semaphore(&semaphore_create_info, None)
-        .expect("Failed to create Semaphore Object!")
+        .context("Failed to create semaphore")?
     };
     let instance_compute_finished = unsafe {
       device
         .create_semaphore(&semaphore_create_info, None)
-        .expect("Failed to create Semaphore Object!")
+        .context("Failed to create semaphore")?
     };
 
     let fence_create_info = vk::FenceCreateInfo {
@@ -63,18 +90,17 @@ impl Frame {
     let finished = unsafe {
       device
         .create_fence(&fence_create_info, None)
-        .expect("Failed to create Fence Object!")
+        .context("Failed to create fence")?
     };
-    Self {
+    Ok(Self {
       image_available,
       render_finished,
       instance_compute_finished,
       finished,
-    }
+    })
   }
 
   pub unsafe fn destroy(&mut self, device: &ash::Device) {
-    device.device_wait_idle().expect("Failed to wait on device");
     device.destroy_semaphore(self.image_available, None);
     device.destroy_semaphore(self.render_finished, None);
     device.destroy_semaphore(self.instance_compute_finished, None);
@@ -139,10 +165,40 @@ impl GPULattency {
   }
 }
 
+// groups objects by model, each group becomes a contiguous instance range
+// todo: needs refinement / optimizations
+pub(super) fn get_dyn_instance_data(
+  dyn_objects: &[&dyn RenderableIn3d],
+) -> (Vec<MatrixInstance>, Vec<InstProperties>) {
+  let mut groups: BTreeMap<ModelHandle, Vec<MatrixInstance>> = BTreeMap::new();
+  for obj in dyn_objects.iter() {
+    groups
+      .entry(obj.model_i())
+      .or_default()
+      .push(MatrixInstance::new(*obj.ren().model()));
+  }
+
+  let mut instances = Vec::with_capacity(dyn_objects.len());
+  let mut props = Vec::with_capacity(groups.len());
+  for (model, group) in groups {
+    props.push(InstProperties {
+      inst_count: group.len() as u32,
+      inst_offset: instances.len() as u32,
+      model_i: model.index(),
+    });
+    instances.extend(group);
+  }
+  (instances, props)
+}
+
 pub struct SyncRender {
   renderer: Renderer,
+  // the surface is destroyed together with the renderer, so the window has to outlive it
+  window: Window,
   frames: Vec<Frame>,
   last_in_use_i: usize,
+  // number of frames submitted so far
+  frame_count: u64,
   fps_counter: Option<FPSCounter>,
   gpu_latency_counter: Option<GPULattency>,
   recreate_swapchain_next_frame: bool,
@@ -151,12 +207,36 @@ pub struct SyncRender {
   pub camera: RenderCamera,
   updated_aspect_ratio: bool,
   delta_zoom: f32,
+  capture_path: Option<PathBuf>,
+  shader_watcher: Option<ShaderWatcher>,
 }
 
 impl SyncRender {
-  pub fn initialize(event_loop: &EventLoop<()>, camera: Camera, max_square_amount: u64) -> Self {
-    let renderer = Renderer::new(event_loop, max_square_amount);
-    let frames = vec![Frame::new(&renderer.device), Frame::new(&renderer.device)];
+  pub fn initialize(
+    event_loop: &EventLoop<()>,
+    camera: Camera,
+    models: Models,
+    scene: &StaticScene,
+    initial_dyn_inst_count: u64,
+    frames_in_flight: usize,
+    gpu_override: Option<GpuOverride>,
+  ) -> RenderResult<Self> {
+    assert!(
+      FRAMES_IN_FLIGHT_RANGE.contains(&frames_in_flight),
+      "Frames in flight should be in {:?}, got {}",
+      FRAMES_IN_FLIGHT_RANGE,
+      frames_in_flight
+    );
+    let window = Self::init_window(event_loop);
+    let renderer = Renderer::new(
+      &window,
+      models,
+      scene,
+      initial_dyn_inst_count,
+      frames_in_flight,
+      gpu_override,
+    )?;
+    let frames = create_frames(renderer.device(), frames_in_flight)?;
 
     let fps_counter = if PRINT_FPS {
       Some(FPSCounter::new())
@@ -170,7 +250,7 @@ impl SyncRender {
       None
     };
 
-    let window_dimensions = renderer.window.inner_size();
+    let window_dimensions = window.inner_size();
     let aspect_ratio = window_dimensions.width as f32 / window_dimensions.height as f32;
     let middle_screen = PhysicalPosition {
       x: window_dimensions.width as f64 / 2.0,
@@ -179,10 +259,12 @@ impl SyncRender {
 
     let camera = RenderCamera::new(camera, INITIAL_CAMERA_FOV, aspect_ratio, CAMERA_SENTIVITY);
 
-    Self {
+    Ok(Self {
       renderer,
+      window,
       frames,
       last_in_use_i: 0,
+      frame_count: 0,
       fps_counter,
       gpu_latency_counter,
       recreate_swapchain_next_frame: false,
@@ -191,13 +273,31 @@ impl SyncRender {
       camera,
       updated_aspect_ratio: false,
       delta_zoom: 0.0,
-    }
+      capture_path: None,
+      shader_watcher: None,
+    })
+  }
+
+  fn init_window(event_loop: &EventLoop<()>) -> Window {
+    winit::window::WindowBuilder::new()
+      .with_title(WINDOW_TITLE)
+      .with_inner_size(winit::dpi::LogicalSize::new(
+        INITIAL_WINDOW_WIDTH,
+        INITIAL_WINDOW_HEIGHT,
+      ))
+      .build(event_loop)
+      .expect("Failed to create window.")
+  }
+
+  fn get_aspect_ratio(&self) -> f32 {
+    let window_size = self.window.inner_size();
+    window_size.width as f32 / window_size.height as f32
   }
 
   pub fn handle_window_resize(&mut self) {
     // calculating the aspect ratio involves recalculating camera's projection matrix
     self.updated_aspect_ratio = true;
-    let window_dimensions = self.renderer.window.inner_size();
+    let window_dimensions = self.window.inner_size();
     self.middle_screen = PhysicalPosition {
       x: window_dimensions.width as f64 / 2.0,
       y: window_dimensions.height as f64 / 2.0,
@@ -213,11 +313,7 @@ impl SyncRender {
         self.cursor.delta_x += position.x - self.middle_screen.x;
         self.cursor.delta_y += position.y - self.middle_screen.y;
       }
-      self
-        .renderer
-        .window
-        .set_cursor_position(self.middle_screen)
-        .unwrap();
+      self.window.set_cursor_position(self.middle_screen).unwrap();
     }
   }
 
@@ -231,34 +327,24 @@ impl SyncRender {
     // the program resets the mouse each frame anyway, however its nice when you can't
     // accidentaly leave the application from a big mouse jump
     self
-      .renderer
       .window
       .set_cursor_grab(CursorGrabMode::Locked)
       .unwrap_or_else(|_| {
         self
-          .renderer
           .window
           .set_cursor_grab(CursorGrabMode::Confined)
           .unwrap_or(());
       });
 
-    self
-      .renderer
-      .window
-      .set_cursor_position(self.middle_screen)
-      .unwrap();
-    // self.renderer.window.set_cursor_visible(false);
+    self.window.set_cursor_position(self.middle_screen).unwrap();
+    // self.window.set_cursor_visible(false);
     self.cursor.delta_x = 0.0;
     self.cursor.delta_y = 0.0;
   }
 
   fn ungrab_cursor(&mut self) {
-    self
-      .renderer
-      .window
-      .set_cursor_grab(CursorGrabMode::None)
-      .unwrap();
-    self.renderer.window.set_cursor_visible(true);
+    self.window.set_cursor_grab(CursorGrabMode::None).unwrap();
+    self.window.set_cursor_visible(true);
   }
 
   pub fn handle_cursor_entered_window(&mut self) {
@@ -285,15 +371,58 @@ impl SyncRender {
     }
   }
 
-  pub fn render_next_frame(&mut self, time_since_last_frame: &Duration, dyn_objects: &Vec<Niko>) {
-    let s = Duration::from_secs_f32(1.0 / 60.0);
-    // if time_since_last_frame < &s {
-    //   std::thread::sleep(s - *time_since_last_frame);
-    // }
+  pub fn model_state(&self, handle: ModelHandle) -> LoadState {
+    self.renderer.model_state(handle)
+  }
+
+  pub fn memory_stats(&self) -> AllocatorStats {
+    self.renderer.memory_stats()
+  }
+
+  // dev mode: shaders are recompiled and their pipelines rebuilt when their sources change
+  pub fn watch_shaders(&mut self) {
+    self.shader_watcher = ShaderWatcher::new();
+  }
+
+  // the next rendered frame will be saved to path before being presented
+  pub fn capture_next_frame(&mut self, path: PathBuf) {
+    self.capture_path = Some(path);
+  }
+
+  // if the device gets lost, it is recreated and the frame skipped
+  pub fn render_next_frame(
+    &mut self,
+    time_since_last_frame: &Duration,
+    dyn_objects: &[&dyn RenderableIn3d],
+  ) -> RenderResult<()> {
+    match self.try_render_next_frame(time_since_last_frame, dyn_objects) {
+      Err(err) if err.is_device_lost() => unsafe { self.recover_lost_device() },
+      result => result,
+    }
+  }
+
+  // frame objects are created from the device, so they get recreated with it
+  unsafe fn recover_lost_device(&mut self) -> RenderResult<()> {
+    let frames_in_flight = self.frames.len();
+    self.renderer.wait_idle();
+    for mut frame in self.frames.drain(..) {
+      frame.destroy(self.renderer.device());
+    }
+    self.renderer.recover_lost_device()?;
+    self.frames = create_frames(self.renderer.device(), frames_in_flight)?;
+    self.last_in_use_i = 0;
+    // the swapchain was created for the current window size
+    self.recreate_swapchain_next_frame = false;
+    Ok(())
+  }
+
+  fn try_render_next_frame(
+    &mut self,
+    time_since_last_frame: &Duration,
+    dyn_objects: &[&dyn RenderableIn3d],
+  ) -> RenderResult<()> {
     if self.updated_aspect_ratio {
-      self
-        .camera
-        .set_aspect_ratio(self.renderer.get_aspect_ratio());
+      self.camera.set_aspect_ratio(self.get_aspect_ratio());
     }
     if self.delta_zoom > 0.0 {
       self.camera.zoom_relative(self.delta_zoom);
@@ -305,51 +434,23 @@ impl SyncRender {
     self.cursor.delta_x = 0.0;
     self.cursor.delta_y = 0.0;
 
-    // todo: needs refinement / optimizations
-    let square_instances: Vec<MatrixInstance> = dyn_objects
-      .iter()
-      .map(|sq| MatrixInstance::new(*sq.ren().model()))
-      .collect();
-    let dyn_inst_props = vec![InstProperties {
-      inst_count: dyn_objects.len() as u32,
-      inst_offset: 0,
-      model_i: dyn_objects[0].model_i(),
-    }];
-
-    //
+    let (dyn_instances, dyn_inst_props) = get_dyn_instance_data(dyn_objects);
 
-    let cur_frame_i = (self.last_in_use_i + 1) % FRAMES_IN_FLIGHT;
+    let cur_frame_i = (self.last_in_use_i + 1) % self.frames.len();
     let cur_frame = &self.frames[cur_frame_i];
-    let last_frame = &self.frames[self.last_in_use_i];
 
     let wait_start = Instant::now();
-    if self.recreate_swapchain_next_frame {
-      // recreate swapchain - this function currently waits for last frame
-      // TODO: implement old swapchain functionality so that rendering doesn't
-      // stop during swapchain recreation
-      unsafe {
-        self.renderer.recreate_swapchain(last_frame.finished);
-      }
-      self.recreate_swapchain_next_frame = false;
-
-      // current frame fence should already have been signaled (waited upon)
-    } else {
-      unsafe {
-        self
-          .renderer
-          .device
-          .wait_for_fences(&[cur_frame.finished], true, u64::MAX)
-          .unwrap();
-      }
-    }
-
-    // the fence should be signaled afterwards or it will deadlock
     unsafe {
       self
         .renderer
-        .device
-        .reset_fences(&[cur_frame.finished])
-        .expect("Failed to reset fence");
+        .device()
+        .wait_for_fences(&[cur_frame.finished], true, u64::MAX)
+        .context("Failed to wait for frame")?;
+    }
+    // every frame up to the last one that used the current frame's resources has finished
+    let finished_frame_count = (self.frame_count + 1).saturating_sub(self.frames.len() as u64);
+    unsafe {
+      self.renderer.destroy_retired(finished_frame_count);
     }
 
     let wait_elapsed = wait_start.elapsed();
@@ -366,6 +467,33 @@ impl SyncRender {
       }
     }
 
+    unsafe {
+      self.renderer.stream_models(self.frame_count)?;
+    }
+
+    if let Some(watcher) = &mut self.shader_watcher {
+      let reloaded = watcher.poll();
+      if reloaded.any() {
+        // a broken shader shouldn't stop the app, the previous pipelines are kept
+        if let Err(err) = self.renderer.reload_shaders(reloaded, self.frame_count) {
+          if err.vk_result().is_some() {
+            return Err(err);
+          }
+          error!("{}", err);
+        }
+      }
+    }
+
+    if self.recreate_swapchain_next_frame {
+      // frames in flight keep presenting to the old swapchain
+      unsafe {
+        self
+          .renderer
+          .recreate_swapchain(&self.window.inner_size(), self.frame_count)?;
+      }
+      self.recreate_swapchain_next_frame = false;
+    }
+
     let image_index = match unsafe { self.renderer.acquire_next_image(cur_frame.image_available) } {
       Ok((image_index, suboptimal)) => {
         if suboptimal {
@@ -374,82 +502,47 @@ impl SyncRender {
         }
         image_index
       }
-      Err(_) => unsafe {
+      Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => unsafe {
         self
           .renderer
-          .recreate_swapchain(self.frames[self.last_in_use_i].finished);
+          .recreate_swapchain(&self.window.inner_size(), self.frame_count)?;
 
         let (image_index, new_suboptimal) = self
           .renderer
           .acquire_next_image(cur_frame.image_available)
-          .expect("Invalid swapchain upon recreation");
+          .context("Failed to acquire image from the recreated swapchain")?;
         if new_suboptimal {
-          panic!("Suboptimal swapchain upon recreation");
+          warn!("Suboptimal swapchain upon recreation");
+          self.recreate_swapchain_next_frame = true;
         }
         image_index
       },
+      Err(result) => {
+        return Err(RenderError::Vulkan {
+          context: "Failed to acquire swapchain image",
+          result,
+        })
+      }
     };
 
     // image not in use = safe to record current command buffer
     unsafe {
-      self
-        .renderer
-        .record_main_command_buffer(cur_frame_i, image_index as usize, &dyn_inst_props);
-
-      self
-        .renderer
-        .record_inst_static_comm_buffer(cur_frame_i, &self.camera);
-
-      self
-        .renderer
-        .update_inst_dyn_descriptor_set(cur_frame_i, dyn_objects.len() as u64);
-      self
-        .renderer
-        .update_instance_data(cur_frame_i, &square_instances);
-      self.renderer.record_inst_dyn_comm_buffer(
+      self.renderer.record_frame(
         cur_frame_i,
+        image_index as usize,
         &self.camera,
-        dyn_objects.len() as u32,
-      );
+        &dyn_instances,
+        &dyn_inst_props,
+      )?;
     }
 
     // compute queue submit
-    let wait_semaphores = [];
-    let wait_stages = [];
-    let signal_semaphores = [cur_frame.instance_compute_finished];
-    // in theory these can be changed to execute on different queues (concurrently)
-    let command_buffers = [
-      self.renderer.command_buffer_pools.compute.inst_static[cur_frame_i],
-      self.renderer.command_buffer_pools.compute.inst_dyn[cur_frame_i],
-    ];
-    let submit_infos = [vk::SubmitInfo {
-      s_type: vk::StructureType::SUBMIT_INFO,
-      p_next: ptr::null(),
-      wait_semaphore_count: wait_semaphores.len() as u32,
-      p_wait_semaphores: wait_semaphores.as_ptr(),
-      p_wait_dst_stage_mask: wait_stages.as_ptr(),
-      command_buffer_count: command_buffers.len() as u32,
-      p_command_buffers: command_buffers.as_ptr(),
-      signal_semaphore_count: signal_semaphores.len() as u32,
-      p_signal_semaphores: signal_semaphores.as_ptr(),
-    }];
     unsafe {
       self
         .renderer
-        .device
-        .queue_submit(
-          self.renderer.queues.compute,
-          &submit_infos,
-          vk::Fence::null(),
-        )
-        .expect("failed to execute queue submit");
+        .submit_compute(cur_frame_i, cur_frame.instance_compute_finished)?;
     }
 
-    // std::thread::sleep(std::time::Duration::from_millis(5000));
-    // unsafe {
-    //   self.renderer.device.device_wait_idle().unwrap();
-    // }
-
     // graphics queue submit
     let wait_semaphores = [
       cur_frame.image_available,
@@ -457,64 +550,142 @@ impl SyncRender {
     ];
     let wait_stages = [
       vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-      vk::PipelineStageFlags::VERTEX_INPUT,
+      // draw commands and instances are written by the compute shader
+      vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::VERTEX_INPUT,
     ];
     let signal_semaphores = [cur_frame.render_finished];
-    let submit_infos = [vk::SubmitInfo {
-      s_type: vk::StructureType::SUBMIT_INFO,
-      p_next: ptr::null(),
-      wait_semaphore_count: wait_semaphores.len() as u32,
-      p_wait_semaphores: wait_semaphores.as_ptr(),
-      p_wait_dst_stage_mask: wait_stages.as_ptr(),
-      command_buffer_count: 1,
-      p_command_buffers: &self
-        .renderer
-        .command_buffer_pools
-        .main
-        .command_buffer(cur_frame_i),
-      signal_semaphore_count: signal_semaphores.len() as u32,
-      p_signal_semaphores: signal_semaphores.as_ptr(),
-    }];
     unsafe {
+      // only reset right before submitting, so that the fence stays signaled if anything before
+      // fails, otherwise the next wait on it would deadlock
       self
         .renderer
-        .device
-        .queue_submit(
-          self.renderer.queues.graphics,
-          &submit_infos,
-          cur_frame.finished,
-        )
-        .expect("failed to execute queue submit");
+        .device()
+        .reset_fences(&[cur_frame.finished])
+        .context("Failed to reset fence")?;
+      self.renderer.submit_graphics(
+        cur_frame_i,
+        &wait_semaphores,
+        &wait_stages,
+        &signal_semaphores,
+        cur_frame.finished,
+      )?;
     }
 
-    // unsafe {
-    //   self.renderer.device.device_wait_idle().unwrap();
-    // }
+    if let Some(path) = self.capture_path.take() {
+      let frame = unsafe {
+        self
+          .renderer
+          .capture_frame(image_index as usize, cur_frame.finished)
+      };
+      match frame {
+        Ok(frame) => match frame.save(&path) {
+          Ok(()) => info!("Saved frame capture to {:?}", path),
+          Err(err) => warn!("Failed to save frame capture to {:?}: {}", path, err),
+        },
+        // the frame still gets presented if it can't be captured
+        Err(err) if err.vk_result().is_none() => warn!("Failed to capture frame: {}", err),
+        Err(err) => return Err(err),
+      }
+    }
 
-    unsafe {
-      if let Err(_) = self.renderer.queue_present(image_index, &signal_semaphores) {
+    match unsafe { self.renderer.queue_present(image_index, &signal_semaphores) } {
+      Ok(_) => {}
+      Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
         // NOTE: It seems that sometimes the window can be resized while the image is being presented
         // this occurs because winit only takes notice at the start of the next frame
         // however, it is very inconsistent, so I don't know how to fix this
         warn!("failed to present to swapchain");
         self.recreate_swapchain_next_frame = true;
       }
+      Err(result) => {
+        return Err(RenderError::Vulkan {
+          context: "Failed to present",
+          result,
+        })
+      }
     }
 
     self.last_in_use_i = cur_frame_i;
+    self.frame_count += 1;
+    Ok(())
   }
 
   pub fn request_redraw(&mut self) {
-    self.renderer.window.request_redraw();
+    self.window.request_redraw();
   }
 }
 
 impl Drop for SyncRender {
   fn drop(&mut self) {
+    self.renderer.wait_idle();
     unsafe {
       for frame in self.frames.iter_mut() {
-        frame.destroy(&self.renderer.device);
+        frame.destroy(self.renderer.device());
       }
     }
   }
 }
+
+fn create_frames(device: &ash::Device, frames_in_flight: usize) -> RenderResult<Vec<Frame>> {
+  (0..frames_in_flight).map(|_| Frame::new(device)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::Point3;
+
+  use super::*;
+  use crate::{objects::Object, render::Renderable3dObject};
+
+  fn object(model: ModelHandle, x: f32) -> Object {
+    Object::new(Renderable3dObject::new(Point3::new(x, 0.0, 0.0)), model)
+  }
+
+  #[test]
+  fn dyn_instances_are_grouped_by_model() {
+    let models = Models::builtin();
+    let square = models.get("square").unwrap();
+    let cube = models.get("cube").unwrap();
+    let objects = [object(cube, 0.0), object(square, 1.0), object(cube, 2.0)];
+    let objects: Vec<&dyn RenderableIn3d> = objects.iter().map(|obj| obj as _).collect();
+
+    let (instances, props) = get_dyn_instance_data(&objects);
+
+    assert_eq!(instances.len(), 3);
+    let ranges: Vec<(usize, u32, u32)> = props
+      .iter()
+      .map(|p| (p.model_i, p.inst_offset, p.inst_count))
+      .collect();
+    assert_eq!(ranges, vec![(square.index(), 0, 1), (cube.index(), 1, 2)]);
+    // instances keep their relative order inside a group
+    let translation_x: Vec<f32> = instances.iter().map(|inst| inst.matrix[3][0]).collect();
+    assert_eq!(translation_x, vec![1.0, 0.0, 2.0]);
+  }
+
+  #[test]
+  fn frames_in_flight_from_1_to_4() {
+    assert_eq!(parse_frames_in_flight("1"), Ok(1));
+    assert_eq!(parse_frames_in_flight("4"), Ok(4));
+  }
+
+  #[test]
+  fn no_frames_in_flight() {
+    assert!(parse_frames_in_flight("0")
+      .unwrap_err()
+      .starts_with("Frames in flight should be in"));
+  }
+
+  #[test]
+  fn too_many_frames_in_flight() {
+    assert!(parse_frames_in_flight("5")
+      .unwrap_err()
+      .starts_with("Frames in flight should be in"));
+  }
+
+  #[test]
+  fn no_dyn_instances() {
+    let (instances, props) = get_dyn_instance_data(&[]);
+    assert!(instances.is_empty());
+    assert!(props.is_empty());
+  }
+}
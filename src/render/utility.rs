@@ -25,24 +25,25 @@ where
 {
   slice.sort();
   for item in other.iter() {
-    if let Err(_) = slice.binary_search(item) {
+    if slice.binary_search(item).is_err() {
       return Err(item);
     }
   }
-  return Ok(());
+  Ok(())
 }
 
 pub unsafe fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
   std::slice::from_raw_parts((p as *const T) as *const u8, std::mem::size_of::<T>())
 }
 
+#[allow(dead_code, unused_macros)]
 macro_rules! iter_into_array {
   ($x:expr, $size:expr) => {{
     let mut tmp: [MaybeUninit<_>; $size] = unsafe { MaybeUninit::uninit().assume_init() };
     for i in 0..$size {
       tmp[i] = MaybeUninit::new($x.next().unwrap());
     }
-    unsafe { std::mem::transmute::<_, [_; $size]>(tmp) }
+    unsafe { std::mem::transmute::<[MaybeUninit<_>; $size], [_; $size]>(tmp) }
   }};
 }
 
@@ -53,7 +54,7 @@ macro_rules! vec_to_array {
     for i in 0..$size {
       tmp[i] = MaybeUninit::new($x[i]);
     }
-    unsafe { std::mem::transmute::<_, [_; $size]>(tmp) }
+    unsafe { std::mem::transmute::<[MaybeUninit<_>; $size], [_; $size]>(tmp) }
   }};
   ($x:expr, $size:expr, $offset:expr) => {{
     let mut tmp: [MaybeUninit<_>; $size] = unsafe { MaybeUninit::uninit().assume_init() };
@@ -64,6 +65,7 @@ macro_rules! vec_to_array {
   }};
 }
 
+#[allow(unused_imports)]
 pub(crate) use iter_into_array;
 #[allow(unused_imports)]
 pub(crate) use vec_to_array;
@@ -0,0 +1,261 @@
+use std::{
+  fmt, io,
+  path::{Path, PathBuf},
+};
+
+use cgmath::{Euler, Point3, Rad};
+use ron::error::SpannedError;
+use serde::Deserialize;
+
+use crate::{
+  objects::Object,
+  render::{Camera, ModelHandle, Models, Renderable3dObject},
+  static_scene::StaticScene,
+};
+
+pub const DEFAULT_SCENE_PATH: &str = "./assets/scenes/default.ron";
+
+// scene file contents, see assets/scenes/default.ron for an example
+#[derive(Debug, Deserialize)]
+pub struct SceneDescription {
+  pub camera: CameraDescription,
+  #[serde(default)]
+  pub static_objects: Vec<ObjectDescription>,
+  #[serde(default)]
+  pub dynamic_objects: Vec<ObjectDescription>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CameraDescription {
+  pub position: [f32; 3],
+  #[serde(default)]
+  pub yaw: f32,
+  #[serde(default)]
+  pub pitch: f32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ObjectDescription {
+  pub model: String,
+  pub position: [f32; 3],
+  // euler angles in radians
+  #[serde(default)]
+  pub rotation: [f32; 3],
+  #[serde(default = "default_scale")]
+  pub scale: f32,
+}
+
+fn default_scale() -> f32 {
+  1.0
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+  Read(io::Error),
+  // keeps the line and column the RON error was found at
+  Syntax(SpannedError),
+  UnknownModel(String),
+}
+
+impl fmt::Display for SceneError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      SceneError::Read(err) => write!(f, "Failed to read file: {}", err),
+      SceneError::Syntax(err) => write!(
+        f,
+        "Invalid RON at line {}, column {}: {}",
+        err.position.line, err.position.col, err.code
+      ),
+      SceneError::UnknownModel(model) => write!(f, "Unknown model {:?}", model),
+    }
+  }
+}
+
+impl std::error::Error for SceneError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      SceneError::Read(err) => Some(err),
+      SceneError::Syntax(err) => Some(err),
+      SceneError::UnknownModel(_) => None,
+    }
+  }
+}
+
+// a scene file that failed to load
+#[derive(Debug)]
+pub struct SceneFileError {
+  pub path: PathBuf,
+  pub error: SceneError,
+}
+
+impl fmt::Display for SceneFileError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Failed to load scene {:?}: {}", self.path, self.error)
+  }
+}
+
+impl std::error::Error for SceneFileError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    Some(&self.error)
+  }
+}
+
+impl SceneDescription {
+  pub fn load<P: AsRef<Path>>(path: P, models: &Models) -> Result<Self, SceneFileError> {
+    let path = path.as_ref();
+    std::fs::read_to_string(path)
+      .map_err(SceneError::Read)
+      .and_then(|contents| Self::parse(&contents, models))
+      .map_err(|error| SceneFileError {
+        path: path.to_owned(),
+        error,
+      })
+  }
+
+  pub fn parse(contents: &str, models: &Models) -> Result<Self, SceneError> {
+    let scene: Self = ron::from_str(contents).map_err(SceneError::Syntax)?;
+
+    for obj in scene
+      .static_objects
+      .iter()
+      .chain(scene.dynamic_objects.iter())
+    {
+      obj.model_i(models)?;
+    }
+
+    Ok(scene)
+  }
+
+  pub fn camera(&self, speed: f32) -> Camera {
+    let mut camera = Camera::new(speed);
+    camera.position = Point3::from(self.camera.position);
+    camera.yaw = self.camera.yaw;
+    camera.pitch = self.camera.pitch;
+    camera
+  }
+
+  // models should be the registry the scene was loaded with
+  pub fn static_scene(&self, models: &Models) -> StaticScene {
+    StaticScene {
+      objects: Self::objects(&self.static_objects, models),
+    }
+  }
+
+  pub fn dynamic_objects(&self, models: &Models) -> Vec<Object> {
+    Self::objects(&self.dynamic_objects, models)
+  }
+
+  fn objects(descriptions: &[ObjectDescription], models: &Models) -> Vec<Object> {
+    descriptions
+      .iter()
+      .map(|obj| Object::new(obj.renderable(), obj.model_i(models).unwrap()))
+      .collect()
+  }
+}
+
+impl ObjectDescription {
+  fn model_i(&self, models: &Models) -> Result<ModelHandle, SceneError> {
+    models
+      .get(&self.model)
+      .ok_or_else(|| SceneError::UnknownModel(self.model.clone()))
+  }
+
+  fn renderable(&self) -> Renderable3dObject {
+    let [x, y, z] = self.rotation;
+    Renderable3dObject::from_full(
+      Point3::from(self.position),
+      Euler::new(Rad(x), Rad(y), Rad(z)),
+      self.scale,
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::render::MODELS_DIR;
+
+  #[test]
+  fn parses_full_scene() {
+    let models = Models::builtin();
+    let scene = SceneDescription::parse(
+      r#"(
+        camera: (position: (1.0, 2.0, 3.0), yaw: 0.5),
+        static_objects: [
+          (model: "cube", position: (3.0, 5.0, 5.0), scale: 0.5),
+          (model: "square", position: (5.0, 5.0, 5.0), rotation: (0.0, 1.0, 0.0)),
+        ],
+        dynamic_objects: [(model: "cube", position: (0.0, 0.0, 0.0))],
+      )"#,
+      &models,
+    )
+    .unwrap();
+
+    assert_eq!(scene.camera.position, [1.0, 2.0, 3.0]);
+    assert_eq!(scene.camera.pitch, 0.0);
+    assert_eq!(scene.static_objects.len(), 2);
+    assert_eq!(scene.static_objects[1].rotation, [0.0, 1.0, 0.0]);
+    assert_eq!(scene.static_objects[1].scale, 1.0);
+    assert_eq!(scene.static_scene(&models).total_obj_count(), 2);
+    assert_eq!(scene.dynamic_objects(&models).len(), 1);
+  }
+
+  #[test]
+  fn rejects_unknown_models() {
+    let result = SceneDescription::parse(
+      r#"(
+        camera: (position: (0.0, 0.0, 0.0)),
+        static_objects: [(model: "teapot", position: (0.0, 0.0, 0.0))],
+      )"#,
+      &Models::builtin(),
+    );
+    assert!(matches!(result, Err(SceneError::UnknownModel(model)) if model == "teapot"));
+  }
+
+  #[test]
+  fn load_errors_keep_path_and_position() {
+    let models = Models::builtin();
+    let err = SceneDescription::load("missing.ron", &models).unwrap_err();
+    assert_eq!(err.path, Path::new("missing.ron"));
+    assert!(matches!(err.error, SceneError::Read(_)));
+
+    let err =
+      SceneDescription::parse("(\n  camera: (position: (0.0, 0.0)),\n)", &models).unwrap_err();
+    match err {
+      SceneError::Syntax(err) => assert_eq!(err.position.line, 2),
+      err => panic!("Expected a syntax error, got {}", err),
+    }
+  }
+
+  #[test]
+  fn groups_static_objects_by_model() {
+    let models = Models::builtin();
+    let scene = SceneDescription::parse(
+      r#"(
+        camera: (position: (0.0, 0.0, 0.0)),
+        static_objects: [
+          (model: "cube", position: (0.0, 0.0, 0.0)),
+          (model: "square", position: (0.0, 0.0, 0.0)),
+          (model: "cube", position: (1.0, 0.0, 0.0)),
+        ],
+      )"#,
+      &models,
+    )
+    .unwrap();
+    let static_scene = scene.static_scene(&models);
+    let (objects, model_indices) = static_scene.objects();
+
+    let square = models.get("square").unwrap().index();
+    let cube = models.get("cube").unwrap().index();
+    assert_eq!(model_indices, vec![square, cube]);
+    let sizes: Vec<usize> = objects.into_parts_iter().map(|part| part.size).collect();
+    assert_eq!(sizes, vec![1, 2]);
+  }
+
+  #[test]
+  fn default_scene_is_valid() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let models = Models::load(root.join(MODELS_DIR));
+    SceneDescription::load(root.join(DEFAULT_SCENE_PATH), &models).unwrap();
+  }
+}
@@ -1,51 +1,31 @@
-use cgmath::Point3;
+use std::collections::BTreeMap;
 
 use crate::{
-  objects::{Cube, Square},
-  render::{Models, RenderableIn3d},
+  objects::Object,
+  render::{ModelHandle, RenderableIn3d},
   structures::Linear2dVec,
 };
 
+// objects with constant location, uploaded once into local constant memory
+#[derive(Clone)]
 pub struct StaticScene {
-  pub total_obj_count: usize,
-  pub squares: Vec<Square>,
-  pub cubes: Vec<Cube>,
+  pub objects: Vec<Object>,
 }
 
 impl StaticScene {
-  pub fn load() -> Self {
-    let mut squares = vec![Square::new(Point3::new(5.0, 5.0, 5.0))];
-    squares[0].ren_mut().set_scale(3.0);
-    let cubes = vec![Cube::new(Point3::new(3.0, 5.0, 5.0))];
-    Self {
-      total_obj_count: squares.len() + cubes.len(),
-      squares,
-      cubes,
-    }
+  pub fn total_obj_count(&self) -> usize {
+    self.objects.len()
   }
 
+  // returns objects grouped by model and the model index of each group
   pub fn objects<'a>(&'a self) -> (Linear2dVec<&'a dyn RenderableIn3d>, Vec<usize>) {
-    let squares: Vec<&'a dyn RenderableIn3d> = self
-      .squares
-      .iter()
-      .map(|x| {
-        let result: &'a dyn RenderableIn3d = x;
-        result
-      })
-      .collect();
-    let cubes: Vec<&'a dyn RenderableIn3d> = self
-      .cubes
-      .iter()
-      .map(|x| {
-        let result: &'a dyn RenderableIn3d = x;
-        result
-      })
-      .collect();
+    let mut groups: BTreeMap<ModelHandle, Vec<&'a dyn RenderableIn3d>> = BTreeMap::new();
+    for obj in self.objects.iter() {
+      groups.entry(obj.model_i()).or_default().push(obj);
+    }
 
-    let all = [squares, cubes];
-    // shoud correspond to the above
-    let model_indices = vec![Models::SQUARE_INDEX, Models::CUBE_INDEX];
-    let mut iter = all.into_iter();
+    let model_indices = groups.keys().map(|handle| handle.index()).collect();
+    let mut iter = groups.into_values();
     let iter: &mut dyn ExactSizeIterator<Item = Vec<&'a dyn RenderableIn3d>> = &mut iter;
     (Linear2dVec::from(iter), model_indices)
   }
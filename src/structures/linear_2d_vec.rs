@@ -14,29 +14,6 @@ macro_rules! sized_flatten {
   }};
 }
 
-pub struct SizesIter<'a, T> {
-  vec: &'a Linear2dVec<T>,
-  i: usize,
-}
-
-impl<'a, T> Iterator for SizesIter<'a, T> {
-  type Item = usize;
-  fn next(&mut self) -> Option<Self::Item> {
-    if self.i < self.vec.parts.len() {
-      self.i += 1;
-      Some(self.vec.parts[self.i].size)
-    } else {
-      None
-    }
-  }
-}
-
-impl<'a, T> ExactSizeIterator for SizesIter<'a, T> {
-  fn len(&self) -> usize {
-    self.vec.parts.len() - self.i
-  }
-}
-
 pub struct Linear2dVec<T> {
   data: Vec<T>,
   parts: Vec<Partition<usize>>,
@@ -49,10 +26,25 @@ impl<T> Linear2dVec<T> {
     Self { data, parts }
   }
 
+  // appends a new row at the end
+  pub fn push(&mut self, item: Vec<T>) {
+    self.parts.push(Partition::new(item.len(), self.data.len()));
+    self.data.extend(item);
+  }
+
+  pub fn part_count(&self) -> usize {
+    self.parts.len()
+  }
+
   pub fn len(&self) -> usize {
     self.data.len()
   }
 
+  // size and offset of a row
+  pub fn part(&self, index: usize) -> Partition<usize> {
+    self.parts[index]
+  }
+
   pub fn into_parts_iter(self) -> IntoIter<Partition<usize>> {
     self.parts.into_iter()
   }
@@ -66,6 +58,15 @@ impl<T> Linear2dVec<T> {
   }
 }
 
+impl<T> Default for Linear2dVec<T> {
+  fn default() -> Self {
+    Self {
+      data: Vec::new(),
+      parts: Vec::new(),
+    }
+  }
+}
+
 impl<T> IntoIterator for Linear2dVec<T> {
   type Item = T;
   type IntoIter = std::vec::IntoIter<Self::Item>;
@@ -109,7 +110,7 @@ impl<T> From<&mut dyn Iterator<Item = Vec<T>>> for Linear2dVec<T> {
     };
     let data: Vec<T> = iter
       .flat_map(|item| {
-        let size = item.len() as usize;
+        let size = item.len();
         parts.push(Partition::new(size, offset));
         offset += size;
         item
@@ -3,4 +3,3 @@ mod linear_2d_vec;
 mod partition;
 
 pub use linear_2d_vec::Linear2dVec;
-pub use partition::Partition;